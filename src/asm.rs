@@ -0,0 +1,630 @@
+// 8080 machine code from Rust, for test fixtures
+// `Assembler` is a builder with one method per instruction and labels that
+// can be used before they're bound. The `asm8080!` macro sits on top of it:
+//
+//     let code = asm8080! {
+//         org 0x100;
+//         mvi a, 0x10;
+//     again:
+//         dcr a;
+//         jnz again;
+//         hlt
+//     };
+//     cpu.load_from(&code, 0x100);
+//
+// Mnemonics, registers and register pairs are checked by the macro, and every
+// label becomes a local variable so undefined labels fail to compile too.
+// Operands are single tokens: literals, identifiers (labels or constants) or
+// parenthesized expressions such as `(COUNT - 1)`, and needn't be constant;
+// an `rst` vector outside 0-7 panics when the fixture is built. Labels must
+// not be Rust keywords. Fixtures past about 120 statements need a higher
+// `recursion_limit` in the crate using the macro.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg {
+    B = 0,
+    C = 1,
+    D = 2,
+    E = 3,
+    H = 4,
+    L = 5,
+    M = 6,
+    A = 7,
+}
+
+// Register pairs for LXI, DAD, INX and DCX
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pair {
+    B = 0,
+    D = 1,
+    H = 2,
+    SP = 3,
+}
+
+// Register pairs for PUSH and POP
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StackPair {
+    B = 0,
+    D = 1,
+    H = 2,
+    PSW = 3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond {
+    NZ = 0,
+    Z = 1,
+    NC = 2,
+    C = 3,
+    PO = 4,
+    PE = 5,
+    P = 6,
+    M = 7,
+}
+
+// Arithmetic and logic group, in opcode order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Alu {
+    Add = 0,
+    Adc = 1,
+    Sub = 2,
+    Sbb = 3,
+    Ana = 4,
+    Xra = 5,
+    Ora = 6,
+    Cmp = 7,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Label(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    Address(u16),
+    Label(Label),
+}
+
+impl From<u16> for Target {
+    fn from(address:u16) -> Self {
+        Target::Address(address)
+    }
+}
+
+impl From<Label> for Target {
+    fn from(label:Label) -> Self {
+        Target::Label(label)
+    }
+}
+
+// Anything `db` accepts
+pub trait Data {
+    fn bytes(&self) -> Vec<u8>;
+}
+
+impl Data for u8 {
+    fn bytes(&self) -> Vec<u8> {
+        vec![*self]
+    }
+}
+
+// Untyped integer literals; only the low byte is kept
+impl Data for i32 {
+    fn bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+impl Data for char {
+    fn bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+impl Data for &str {
+    fn bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl Data for &[u8] {
+    fn bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<const N:usize> Data for &[u8; N] {
+    fn bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+#[derive(Default)]
+pub struct Assembler {
+    origin:u16,
+    code:Vec<u8>,
+    labels:Vec<Option<u16>>,
+    fixups:Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Code that will be loaded at `origin`
+    pub fn at(origin:u16) -> Self {
+        Self { origin, ..Self::default() }
+    }
+
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    // Address of the next byte
+    pub fn here(&self) -> u16 {
+        self.origin.wrapping_add(self.code.len() as u16)
+    }
+
+    // Sets the origin before any code, otherwise pads with zeros up to
+    // `address`
+    pub fn org(&mut self, address:u16) -> &mut Self {
+        if self.code.is_empty() {
+            self.origin = address;
+        } else {
+            let here = self.here();
+            assert!(address >= here, "org {:04x} is behind the current address {:04x}", address, here);
+            self.ds(address - here);
+        }
+        self
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub fn bind(&mut self, label:Label) -> &mut Self {
+        assert!(self.labels[label.0].is_none(), "label bound twice");
+        self.labels[label.0] = Some(self.here());
+        self
+    }
+
+    // A new label bound to the current address
+    pub fn label(&mut self) -> Label {
+        let label = self.new_label();
+        self.bind(label);
+        label
+    }
+
+    // The code, with every label use filled in. Panics if a label that was
+    // used was never bound.
+    pub fn assemble(mut self) -> Vec<u8> {
+        for (offset, label) in std::mem::take(&mut self.fixups) {
+            let address = self.labels[label.0].expect("label used but never bound");
+            self.code[offset] = address as u8;
+            self.code[offset + 1] = (address >> 8) as u8;
+        }
+        self.code
+    }
+
+    pub fn byte(&mut self, value:u8) -> &mut Self {
+        self.code.push(value);
+        self
+    }
+
+    pub fn word<T:Into<Target>>(&mut self, value:T) -> &mut Self {
+        let address = match value.into() {
+            Target::Address(address) => address,
+            Target::Label(label) => {
+                self.fixups.push((self.code.len(), label));
+                0
+            }
+        };
+        self.code.push(address as u8);
+        self.code.push((address >> 8) as u8);
+        self
+    }
+
+    pub fn db<T:Data>(&mut self, data:T) -> &mut Self {
+        self.code.extend(data.bytes());
+        self
+    }
+
+    pub fn dw<T:Into<Target>>(&mut self, value:T) -> &mut Self {
+        self.word(value)
+    }
+
+    pub fn ds(&mut self, size:u16) -> &mut Self {
+        self.code.extend(std::iter::repeat_n(0, size as usize));
+        self
+    }
+
+    fn op_word<T:Into<Target>>(&mut self, opcode:u8, value:T) -> &mut Self {
+        self.byte(opcode).word(value)
+    }
+
+    // Data transfer
+    pub fn mov(&mut self, destination:Reg, source:Reg) -> &mut Self {
+        assert!(!(destination == Reg::M && source == Reg::M), "mov m, m is hlt");
+        self.byte(0x40 | (destination as u8) << 3 | source as u8)
+    }
+
+    pub fn mvi(&mut self, register:Reg, value:u8) -> &mut Self {
+        self.byte(0x06 | (register as u8) << 3).byte(value)
+    }
+
+    pub fn lxi<T:Into<Target>>(&mut self, pair:Pair, value:T) -> &mut Self {
+        self.op_word(0x01 | (pair as u8) << 4, value)
+    }
+
+    pub fn lda<T:Into<Target>>(&mut self, address:T) -> &mut Self {
+        self.op_word(0x3A, address)
+    }
+
+    pub fn sta<T:Into<Target>>(&mut self, address:T) -> &mut Self {
+        self.op_word(0x32, address)
+    }
+
+    pub fn lhld<T:Into<Target>>(&mut self, address:T) -> &mut Self {
+        self.op_word(0x2A, address)
+    }
+
+    pub fn shld<T:Into<Target>>(&mut self, address:T) -> &mut Self {
+        self.op_word(0x22, address)
+    }
+
+    // Only B and D are valid
+    pub fn ldax(&mut self, pair:Pair) -> &mut Self {
+        assert!(matches!(pair, Pair::B | Pair::D), "ldax takes b or d");
+        self.byte(0x0A | (pair as u8) << 4)
+    }
+
+    pub fn stax(&mut self, pair:Pair) -> &mut Self {
+        assert!(matches!(pair, Pair::B | Pair::D), "stax takes b or d");
+        self.byte(0x02 | (pair as u8) << 4)
+    }
+
+    pub fn xchg(&mut self) -> &mut Self {
+        self.byte(0xEB)
+    }
+
+    // Arithmetic and logic
+    pub fn alu(&mut self, operation:Alu, register:Reg) -> &mut Self {
+        self.byte(0x80 | (operation as u8) << 3 | register as u8)
+    }
+
+    pub fn alu_immediate(&mut self, operation:Alu, value:u8) -> &mut Self {
+        self.byte(0xC6 | (operation as u8) << 3).byte(value)
+    }
+
+    pub fn inr(&mut self, register:Reg) -> &mut Self {
+        self.byte(0x04 | (register as u8) << 3)
+    }
+
+    pub fn dcr(&mut self, register:Reg) -> &mut Self {
+        self.byte(0x05 | (register as u8) << 3)
+    }
+
+    pub fn inx(&mut self, pair:Pair) -> &mut Self {
+        self.byte(0x03 | (pair as u8) << 4)
+    }
+
+    pub fn dcx(&mut self, pair:Pair) -> &mut Self {
+        self.byte(0x0B | (pair as u8) << 4)
+    }
+
+    pub fn dad(&mut self, pair:Pair) -> &mut Self {
+        self.byte(0x09 | (pair as u8) << 4)
+    }
+
+    // Branches; `None` is the unconditional form
+    pub fn jmp<T:Into<Target>>(&mut self, condition:Option<Cond>, target:T) -> &mut Self {
+        match condition {
+            Some(condition) => self.op_word(0xC2 | (condition as u8) << 3, target),
+            None => self.op_word(0xC3, target),
+        }
+    }
+
+    pub fn call<T:Into<Target>>(&mut self, condition:Option<Cond>, target:T) -> &mut Self {
+        match condition {
+            Some(condition) => self.op_word(0xC4 | (condition as u8) << 3, target),
+            None => self.op_word(0xCD, target),
+        }
+    }
+
+    pub fn ret(&mut self, condition:Option<Cond>) -> &mut Self {
+        match condition {
+            Some(condition) => self.byte(0xC0 | (condition as u8) << 3),
+            None => self.byte(0xC9),
+        }
+    }
+
+    pub fn rst(&mut self, vector:u8) -> &mut Self {
+        assert!(vector < 8, "rst vector must be 0-7");
+        self.byte(0xC7 | vector << 3)
+    }
+
+    pub fn pchl(&mut self) -> &mut Self {
+        self.byte(0xE9)
+    }
+
+    // Stack
+    pub fn push(&mut self, pair:StackPair) -> &mut Self {
+        self.byte(0xC5 | (pair as u8) << 4)
+    }
+
+    pub fn pop(&mut self, pair:StackPair) -> &mut Self {
+        self.byte(0xC1 | (pair as u8) << 4)
+    }
+
+    pub fn xthl(&mut self) -> &mut Self {
+        self.byte(0xE3)
+    }
+
+    pub fn sphl(&mut self) -> &mut Self {
+        self.byte(0xF9)
+    }
+
+    // I/O
+    pub fn input(&mut self, port:u8) -> &mut Self {
+        self.byte(0xDB).byte(port)
+    }
+
+    pub fn output(&mut self, port:u8) -> &mut Self {
+        self.byte(0xD3).byte(port)
+    }
+}
+
+#[macro_export]
+macro_rules! asm8080 {
+    // First pass: every label becomes a local variable
+    (@labels $asm:ident;) => {};
+    (@labels $asm:ident; ; $($rest:tt)*) => {
+        $crate::asm8080!(@labels $asm; $($rest)*);
+    };
+    (@labels $asm:ident; $label:ident : $($rest:tt)*) => {
+        let $label = $asm.new_label();
+        $crate::asm8080!(@labels $asm; $($rest)*);
+    };
+    (@labels $asm:ident; $mnemonic:ident ; $($rest:tt)*) => {
+        $crate::asm8080!(@labels $asm; $($rest)*);
+    };
+    (@labels $asm:ident; $mnemonic:ident $first:tt $(, $operand:tt)* ; $($rest:tt)*) => {
+        $crate::asm8080!(@labels $asm; $($rest)*);
+    };
+    (@labels $asm:ident; $($other:tt)*) => {
+        compile_error!(concat!("bad statement: ", stringify!($($other)*)));
+    };
+
+    (@reg a) => { $crate::asm::Reg::A };
+    (@reg b) => { $crate::asm::Reg::B };
+    (@reg c) => { $crate::asm::Reg::C };
+    (@reg d) => { $crate::asm::Reg::D };
+    (@reg e) => { $crate::asm::Reg::E };
+    (@reg h) => { $crate::asm::Reg::H };
+    (@reg l) => { $crate::asm::Reg::L };
+    (@reg m) => { $crate::asm::Reg::M };
+    (@reg $other:tt) => { compile_error!(concat!("unknown register: ", stringify!($other))) };
+
+    (@pair b) => { $crate::asm::Pair::B };
+    (@pair d) => { $crate::asm::Pair::D };
+    (@pair h) => { $crate::asm::Pair::H };
+    (@pair sp) => { $crate::asm::Pair::SP };
+    (@pair $other:tt) => { compile_error!(concat!("expected b, d, h or sp: ", stringify!($other))) };
+
+    (@index b) => { $crate::asm::Pair::B };
+    (@index d) => { $crate::asm::Pair::D };
+    (@index $other:tt) => { compile_error!(concat!("expected b or d: ", stringify!($other))) };
+
+    (@stack b) => { $crate::asm::StackPair::B };
+    (@stack d) => { $crate::asm::StackPair::D };
+    (@stack h) => { $crate::asm::StackPair::H };
+    (@stack psw) => { $crate::asm::StackPair::PSW };
+    (@stack $other:tt) => { compile_error!(concat!("expected b, d, h or psw: ", stringify!($other))) };
+
+    (@target $value:tt) => { $crate::asm::Target::from($value) };
+
+    // Second pass: one instruction per statement
+    (@ins $asm:ident;) => {};
+    (@ins $asm:ident; ; $($rest:tt)*) => {
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; $label:ident : $($rest:tt)*) => {
+        $asm.bind($label);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+
+    (@ins $asm:ident; org $address:tt ; $($rest:tt)*) => {
+        $asm.org($address);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; db $($value:tt),+ ; $($rest:tt)*) => {
+        $($asm.db($value);)+
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; dw $($value:tt),+ ; $($rest:tt)*) => {
+        $($asm.dw($crate::asm8080!(@target $value));)+
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; ds $size:tt ; $($rest:tt)*) => {
+        $asm.ds($size);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+
+    (@ins $asm:ident; mov m, m ; $($rest:tt)*) => {
+        compile_error!("mov m, m is not an instruction, use hlt");
+    };
+    (@ins $asm:ident; mov $destination:ident , $source:ident ; $($rest:tt)*) => {
+        $asm.mov($crate::asm8080!(@reg $destination), $crate::asm8080!(@reg $source));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; mvi $register:ident , $value:tt ; $($rest:tt)*) => {
+        $asm.mvi($crate::asm8080!(@reg $register), ($value) as u8);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; lxi $pair:ident , $value:tt ; $($rest:tt)*) => {
+        $asm.lxi($crate::asm8080!(@pair $pair), $crate::asm8080!(@target $value));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; lda $address:tt ; $($rest:tt)*) => {
+        $asm.lda($crate::asm8080!(@target $address));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; sta $address:tt ; $($rest:tt)*) => {
+        $asm.sta($crate::asm8080!(@target $address));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; lhld $address:tt ; $($rest:tt)*) => {
+        $asm.lhld($crate::asm8080!(@target $address));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; shld $address:tt ; $($rest:tt)*) => {
+        $asm.shld($crate::asm8080!(@target $address));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; ldax $pair:ident ; $($rest:tt)*) => {
+        $asm.ldax($crate::asm8080!(@index $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; stax $pair:ident ; $($rest:tt)*) => {
+        $asm.stax($crate::asm8080!(@index $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+
+    (@ins $asm:ident; add $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Add $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; adc $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Adc $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; sub $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Sub $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; sbb $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Sbb $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ana $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Ana $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; xra $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Xra $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ora $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Ora $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cmp $r:ident ; $($rest:tt)*) => { $crate::asm8080!(@alu $asm; Cmp $r); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@alu $asm:ident; $operation:ident $r:ident) => {
+        $asm.alu($crate::asm::Alu::$operation, $crate::asm8080!(@reg $r));
+    };
+
+    (@ins $asm:ident; adi $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Add $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; aci $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Adc $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; sui $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Sub $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; sbi $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Sbb $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ani $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Ana $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; xri $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Xra $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ori $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Ora $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cpi $v:tt ; $($rest:tt)*) => { $crate::asm8080!(@alu_imm $asm; Cmp $v); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@alu_imm $asm:ident; $operation:ident $value:tt) => {
+        $asm.alu_immediate($crate::asm::Alu::$operation, ($value) as u8);
+    };
+
+    (@ins $asm:ident; inr $r:ident ; $($rest:tt)*) => {
+        $asm.inr($crate::asm8080!(@reg $r));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; dcr $r:ident ; $($rest:tt)*) => {
+        $asm.dcr($crate::asm8080!(@reg $r));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; inx $pair:ident ; $($rest:tt)*) => {
+        $asm.inx($crate::asm8080!(@pair $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; dcx $pair:ident ; $($rest:tt)*) => {
+        $asm.dcx($crate::asm8080!(@pair $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; dad $pair:ident ; $($rest:tt)*) => {
+        $asm.dad($crate::asm8080!(@pair $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+
+    (@ins $asm:ident; jmp $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp None, $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jnz $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(NZ), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jz $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(Z), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jnc $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(NC), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jc $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(C), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jpo $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(PO), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jpe $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(PE), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jp $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(P), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; jm $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; jmp Some(M), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; call $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call None, $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cnz $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(NZ), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cz $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(Z), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cnc $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(NC), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cc $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(C), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cpo $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(PO), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cpe $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(PE), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cp $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(P), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cm $t:tt ; $($rest:tt)*) => { $crate::asm8080!(@branch $asm; call Some(M), $t); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@branch $asm:ident; $kind:ident None, $target:tt) => {
+        $asm.$kind(None, $crate::asm8080!(@target $target));
+    };
+    (@branch $asm:ident; $kind:ident Some($condition:ident), $target:tt) => {
+        $asm.$kind(Some($crate::asm::Cond::$condition), $crate::asm8080!(@target $target));
+    };
+
+    (@ins $asm:ident; ret ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; None); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rnz ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(NZ)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rz ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(Z)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rnc ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(NC)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rc ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(C)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rpo ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(PO)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rpe ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(PE)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rp ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(P)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rm ; $($rest:tt)*) => { $crate::asm8080!(@ret $asm; Some(M)); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ret $asm:ident; None) => {
+        $asm.ret(None);
+    };
+    (@ret $asm:ident; Some($condition:ident)) => {
+        $asm.ret(Some($crate::asm::Cond::$condition));
+    };
+
+    (@ins $asm:ident; rst $vector:tt ; $($rest:tt)*) => {
+        $asm.rst(u8::try_from($vector).unwrap_or(u8::MAX));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; push $pair:ident ; $($rest:tt)*) => {
+        $asm.push($crate::asm8080!(@stack $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; pop $pair:ident ; $($rest:tt)*) => {
+        $asm.pop($crate::asm8080!(@stack $pair));
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; in $port:tt ; $($rest:tt)*) => {
+        $asm.input(($port) as u8);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+    (@ins $asm:ident; out $port:tt ; $($rest:tt)*) => {
+        $asm.output(($port) as u8);
+        $crate::asm8080!(@ins $asm; $($rest)*);
+    };
+
+    (@ins $asm:ident; xchg ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xEB); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; xthl ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xE3); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; sphl ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xF9); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; pchl ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xE9); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; nop ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x00); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; hlt ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x76); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rlc ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x07); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rrc ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x0F); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ral ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x17); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; rar ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x1F); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; daa ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x27); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cma ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x2F); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; stc ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x37); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; cmc ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0x3F); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; ei ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xFB); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@ins $asm:ident; di ; $($rest:tt)*) => { $crate::asm8080!(@byte $asm; 0xF3); $crate::asm8080!(@ins $asm; $($rest)*); };
+    (@byte $asm:ident; $opcode:literal) => {
+        $asm.byte($opcode);
+    };
+    (@ins $asm:ident; $mnemonic:tt $($rest:tt)*) => {
+        compile_error!(concat!("unknown instruction or bad operands: ", stringify!($mnemonic)));
+    };
+
+    ($($body:tt)*) => {{
+        let mut asm = $crate::asm::Assembler::new();
+        $crate::asm8080!(@labels asm; $($body)* ;);
+        $crate::asm8080!(@ins asm; $($body)* ;);
+        asm.assemble()
+    }};
+}
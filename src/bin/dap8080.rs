@@ -0,0 +1,26 @@
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+
+use intel8080_core::dap::DapServer;
+
+// Debug adapter: speaks DAP on stdin and stdout, so editors can launch it
+// as the debug adapter executable. See the dap module for the launch
+// arguments.
+
+fn main() -> ExitCode {
+    if std::env::args().len() > 1 {
+        eprintln!("usage: dap8080\n\nA Debug Adapter Protocol server on stdin and stdout; takes no arguments.");
+        return ExitCode::FAILURE;
+    }
+
+    // Faults are reported to the editor; keep stdout for the protocol
+    std::panic::set_hook(Box::new(|info| eprintln!("dap8080: fault: {}", info)));
+
+    match DapServer::new(io::stdout()).serve(BufReader::new(io::stdin())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("dap8080: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::{self, Read, Stdin, Stdout, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
+
+use intel8080_core::gdb::{Connection, GdbServer};
+use intel8080_core::loader::parse_address;
+use intel8080_core::machine::{Machine, Profile};
+use intel8080_core::romset::RomSet;
+
+// GDB remote stub: loads an image on a machine profile and serves one
+// debugger session, e.g. `target remote localhost:1234` or
+// `target remote | gdb8080 --stdio prog.com`
+
+const USAGE:&str = "usage: gdb8080 [options] <image>
+
+options:
+  -p, --profile <name>   bare (default), cpm, altair or invaders
+  -a, --address <addr>   load address of binary images (default 0)
+  -s, --start <addr>     start here instead of the image's entry point
+  -l, --listen <addr>    TCP address to listen on (default 127.0.0.1:1234)
+      --stdio            talk to the debugger over stdin and stdout
+  -i, --input <text>     console input, \\n for newlines
+      --args <text>      CP/M command tail";
+
+// The debugger on the other end of a pipe; Ctrl-C can't be seen without
+// blocking, so a running target can only be stopped by a breakpoint
+struct Stdio {
+    input:Stdin,
+    output:Stdout,
+}
+
+impl Read for Stdio {
+    fn read(&mut self, buffer:&mut [u8]) -> io::Result<usize> {
+        self.input.read(buffer)
+    }
+}
+
+impl Write for Stdio {
+    fn write(&mut self, buffer:&[u8]) -> io::Result<usize> {
+        self.output.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl Connection for Stdio {}
+
+struct Options {
+    image:String,
+    profile:Profile,
+    address:u16,
+    start:Option<u16>,
+    listen:String,
+    stdio:bool,
+    input:Vec<u8>,
+    arguments:String,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        image: String::new(),
+        profile: Profile::Bare,
+        address: 0,
+        start: None,
+        listen: "127.0.0.1:1234".to_string(),
+        stdio: false,
+        input: Vec::new(),
+        arguments: String::new(),
+    };
+
+    let mut arguments = std::env::args().skip(1);
+    let mut image = None;
+    while let Some(argument) = arguments.next() {
+        let mut value = |name:&str| arguments.next().ok_or(format!("{} needs a value", name));
+        let address = |text:String| parse_address(&text).ok_or(format!("bad address {}", text));
+
+        match argument.as_str() {
+            "-p" | "--profile" => {
+                let name = value(&argument)?;
+                options.profile = Profile::from_name(&name).ok_or(format!("unknown profile {}", name))?;
+            }
+            "-a" | "--address" => options.address = address(value(&argument)?)?,
+            "-s" | "--start" => options.start = Some(address(value(&argument)?)?),
+            "-l" | "--listen" => options.listen = value(&argument)?,
+            "--stdio" => options.stdio = true,
+            "-i" | "--input" => options.input.extend(value(&argument)?.replace("\\n", "\r").bytes()),
+            "--args" => options.arguments = value(&argument)?,
+            "-h" | "--help" => return Err(String::new()),
+            _ if argument.starts_with('-') => return Err(format!("unknown option {}", argument)),
+            _ if image.is_none() => image = Some(argument),
+            _ => return Err("only one image can be given".to_string()),
+        }
+    }
+
+    options.image = image.ok_or("no image given")?;
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("gdb8080: {}", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut machine = Machine::new(options.profile);
+    let image = Path::new(&options.image);
+    let loaded = if options.profile == Profile::Invaders && image.is_dir() {
+        machine.load_romset(&RomSet::invaders(), image).map_err(|error| error.to_string())
+    } else {
+        machine.load(image, options.address, &options.arguments).map(|_| ()).map_err(|error| error.to_string())
+    };
+    if let Err(message) = loaded {
+        eprintln!("gdb8080: {}", message);
+        return ExitCode::FAILURE;
+    }
+    if let Some(start) = options.start {
+        machine.cpu.pc = start;
+    }
+    machine.send_input(&options.input);
+
+    // Faults are reported to the debugger as SIGILL
+    std::panic::set_hook(Box::new(|info| eprintln!("gdb8080: fault: {}", info)));
+
+    let result = if options.stdio {
+        GdbServer::new(machine, Stdio { input: io::stdin(), output: io::stdout() }).serve()
+    } else {
+        let listener = match TcpListener::bind(&options.listen) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!("gdb8080: {}: {}", options.listen, error);
+                return ExitCode::FAILURE;
+            }
+        };
+        eprintln!("gdb8080: waiting for a debugger on {}", options.listen);
+        listener.accept().and_then(|(stream, peer)| {
+            eprintln!("gdb8080: connected to {}", peer);
+            stream.set_nodelay(true)?;
+            GdbServer::new(machine, stream).serve()
+        })
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("gdb8080: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use intel8080_core::disasm::disassemble;
+use intel8080_core::machine::{Machine, Profile, Stop};
+
+// Interactive monitor in the style of DDT and SID
+// Commands are a letter followed by comma separated expressions. Numbers
+// are hex unless prefixed with '#'; '.name' is a symbol, '$' the PC,
+// '%reg' a register and 'c' a character. + - * / & | ^ and parentheses
+// work as usual on 16 bit values.
+
+const HELP:&str = "\
+D [start][,end]        display memory
+L [start][,end]        list (disassemble)
+G [start][,bp[,bp]]    go, stopping at breakpoints and the temporary ones given
+T [count]              trace count instructions
+U [count]              run count instructions quietly, then show registers
+B [addr]               set a breakpoint, or list them; B -addr clears one, B - all
+S addr,byte...         set memory; bytes may be \"strings\"
+X [reg[,value]]        show registers, or set A B C D E H L F BC DE HL SP PC
+F start,end,byte       fill memory
+M start,end,dest       move memory
+W start,end,byte...    where: search memory for bytes
+R file[,addr]          read an image (binary at addr) or a .sym symbol file
+N [name[,value]]       name a symbol (default the PC), or list symbols
+H expr[,expr]          evaluate; with two values shows sum and difference
+I text                 queue console input, \\n for return
+Q                      quit";
+
+// G stops to let the user back in after this many instructions
+const RUN_LIMIT:u64 = 100_000_000;
+const LIST_LINES:usize = 12;
+const DISPLAY_BYTES:usize = 0x80;
+
+struct Monitor {
+    machine:Machine,
+    breakpoints:BTreeSet<u16>,
+    symbols:HashMap<String, u16>,
+    names:BTreeMap<u16, String>,
+    next_display:u16,
+    next_list:u16,
+}
+
+fn split_arguments(text:&str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut depth = 0;
+    for character in text.chars() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                arguments.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(character);
+    }
+    if !current.trim().is_empty() || !arguments.is_empty() {
+        arguments.push(current.trim().to_string());
+    }
+    arguments
+}
+
+impl Monitor {
+    fn new(machine:Machine) -> Self {
+        let pc = machine.cpu.pc;
+        Self {
+            machine,
+            breakpoints: BTreeSet::new(),
+            symbols: HashMap::new(),
+            names: BTreeMap::new(),
+            next_display: pc,
+            next_list: pc,
+        }
+    }
+
+    fn define(&mut self, name:&str, value:u16) {
+        let name = name.to_ascii_uppercase();
+        if let Some(old) = self.symbols.insert(name.clone(), value) {
+            self.names.remove(&old);
+        }
+        self.names.insert(value, name);
+    }
+
+    fn register(&self, name:&str) -> Option<u16> {
+        let cpu = &self.machine.cpu;
+        let registers = cpu.registers();
+        let value = match name.to_ascii_uppercase().as_str() {
+            "A" => registers.a as u16,
+            "F" => registers.flags as u16,
+            "B" => registers.b as u16,
+            "C" => registers.c as u16,
+            "D" => registers.d as u16,
+            "E" => registers.e as u16,
+            "H" => registers.h as u16,
+            "L" => registers.l as u16,
+            "BC" => registers.bc(),
+            "DE" => registers.de(),
+            "HL" => registers.hl(),
+            "SP" => registers.sp,
+            "PC" => cpu.pc,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn set_register(&mut self, name:&str, value:u16) -> Result<(), String> {
+        let cpu = &mut self.machine.cpu;
+        let mut registers = cpu.registers();
+        match name.to_ascii_uppercase().as_str() {
+            "A" => registers.a = value as u8,
+            "F" => registers.flags = value as u8,
+            "B" => registers.b = value as u8,
+            "C" => registers.c = value as u8,
+            "D" => registers.d = value as u8,
+            "E" => registers.e = value as u8,
+            "H" => registers.h = value as u8,
+            "L" => registers.l = value as u8,
+            "BC" => registers.set_bc(value),
+            "DE" => registers.set_de(value),
+            "HL" => registers.set_hl(value),
+            "SP" => registers.sp = value,
+            "PC" => {
+                cpu.pc = value;
+                cpu.halted = false;
+                return Ok(());
+            }
+            _ => return Err(format!("no register {}", name)),
+        }
+        cpu.set_registers(&registers);
+        Ok(())
+    }
+
+    fn evaluate(&self, text:&str) -> Result<u16, String> {
+        let tokens:Vec<char> = text.chars().filter(|character| !character.is_whitespace()).collect();
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(format!("bad expression {}", text));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens:&[char], position:&mut usize) -> Result<u16, String> {
+        let mut value = self.term(tokens, position)?;
+        while let Some(&operator) = tokens.get(*position) {
+            if !"+-|^".contains(operator) {
+                break;
+            }
+            *position += 1;
+            let right = self.term(tokens, position)?;
+            value = match operator {
+                '+' => value.wrapping_add(right),
+                '-' => value.wrapping_sub(right),
+                '|' => value | right,
+                _ => value ^ right,
+            };
+        }
+        Ok(value)
+    }
+
+    fn term(&self, tokens:&[char], position:&mut usize) -> Result<u16, String> {
+        let mut value = self.factor(tokens, position)?;
+        while let Some(&operator) = tokens.get(*position) {
+            if !"*/&".contains(operator) {
+                break;
+            }
+            *position += 1;
+            let right = self.factor(tokens, position)?;
+            value = match operator {
+                '*' => value.wrapping_mul(right),
+                '/' => value.checked_div(right).ok_or("division by zero")?,
+                _ => value & right,
+            };
+        }
+        Ok(value)
+    }
+
+    fn factor(&self, tokens:&[char], position:&mut usize) -> Result<u16, String> {
+        let word = |position:&mut usize| {
+            let start = *position;
+            while tokens.get(*position).is_some_and(|character| character.is_ascii_alphanumeric() || *character == '_') {
+                *position += 1;
+            }
+            tokens[start..*position].iter().collect::<String>()
+        };
+
+        match tokens.get(*position) {
+            None => Err("missing value".to_string()),
+            Some('-') => {
+                *position += 1;
+                Ok(self.factor(tokens, position)?.wrapping_neg())
+            }
+            Some('(') => {
+                *position += 1;
+                let value = self.expression(tokens, position)?;
+                if tokens.get(*position) != Some(&')') {
+                    return Err("missing )".to_string());
+                }
+                *position += 1;
+                Ok(value)
+            }
+            Some('\'') => match (tokens.get(*position + 1), tokens.get(*position + 2)) {
+                (Some(character), Some('\'')) => {
+                    *position += 3;
+                    Ok(*character as u16 & 0xFF)
+                }
+                _ => Err("bad character constant".to_string()),
+            },
+            Some('$') => {
+                *position += 1;
+                Ok(self.machine.cpu.pc)
+            }
+            Some('.') => {
+                *position += 1;
+                let name = word(position);
+                self.symbols.get(&name.to_ascii_uppercase()).copied().ok_or(format!("no symbol {}", name))
+            }
+            Some('%') => {
+                *position += 1;
+                let name = word(position);
+                self.register(&name).ok_or(format!("no register {}", name))
+            }
+            Some('#') => {
+                *position += 1;
+                let digits = word(position);
+                digits.parse::<u32>().map(|value| value as u16).map_err(|_| format!("bad number #{}", digits))
+            }
+            Some(_) => {
+                let digits = word(position);
+                u32::from_str_radix(&digits, 16).map(|value| value as u16).map_err(|_| format!("bad number {}", digits))
+            }
+        }
+    }
+
+    fn arguments(&self, text:&str) -> Result<Vec<u16>, String> {
+        split_arguments(text).iter().map(|argument| self.evaluate(argument)).collect()
+    }
+
+    // Bytes from expressions and "strings"
+    fn bytes(&self, arguments:&[String]) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        for argument in arguments {
+            match argument.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+                Some(text) => bytes.extend(text.bytes()),
+                None => bytes.push(self.evaluate(argument)? as u8),
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn show_registers(&self) {
+        let cpu = &self.machine.cpu;
+        let registers = cpu.registers();
+        let flag = |mask:u8| (registers.flags & mask != 0) as u8;
+        let instruction = disassemble(&cpu.ram, cpu.pc).format(|address| self.names.get(&address).cloned());
+        let label = self.names.get(&cpu.pc).map(|name| format!(".{}: ", name)).unwrap_or_default();
+        println!(
+            "C{}Z{}M{}E{}I{} A={:02X} B={:04X} D={:04X} H={:04X} S={:04X} P={:04X} {}{}",
+            flag(0x01),
+            flag(0x40),
+            flag(0x80),
+            flag(0x04),
+            flag(0x10),
+            registers.a,
+            registers.bc(),
+            registers.de(),
+            registers.hl(),
+            registers.sp,
+            cpu.pc,
+            label,
+            instruction
+        );
+    }
+
+    fn flush_output(&mut self) {
+        let output = self.machine.take_output();
+        if !output.is_empty() {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(&output);
+            let _ = stdout.flush();
+        }
+    }
+
+    // Runs until a stop, a breakpoint (not counting the first instruction)
+    // or `count` instructions
+    fn run(&mut self, count:u64, temporary:&[u16], trace:bool) {
+        let mut executed = 0;
+        // Faults are reported below, so the default hook stays out of the
+        // way until the loop is done
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        loop {
+            if let Some(stop) = self.machine.stop_reason() {
+                self.flush_output();
+                match stop {
+                    Stop::Halted => println!("halted at {:04X}", self.machine.cpu.pc),
+                    Stop::Stopped => println!("stopped at {:04X}", self.machine.cpu.pc),
+                    Stop::Exited => println!("program exited"),
+                    Stop::WaitingForInput => println!("waiting for console input, queue some with I"),
+                }
+                break;
+            }
+            let pc = self.machine.cpu.pc;
+            if executed > 0 && (self.breakpoints.contains(&pc) || temporary.contains(&pc)) {
+                self.flush_output();
+                println!("*{:04X}", pc);
+                break;
+            }
+            if executed >= count {
+                break;
+            }
+            if trace {
+                self.show_registers();
+            }
+            let machine = &mut self.machine;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| machine.step()));
+            if let Err(fault) = result {
+                let message = fault
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| fault.downcast_ref::<&str>().map(|message| message.to_string()))
+                    .unwrap_or_default();
+                self.flush_output();
+                println!("fault at {:04X}: {}", pc, message);
+                break;
+            }
+            executed += 1;
+            self.flush_output();
+        }
+        panic::set_hook(hook);
+        self.next_list = self.machine.cpu.pc;
+    }
+
+    fn display(&mut self, start:u16, end:u16) {
+        let mut address = start as usize;
+        while address <= end as usize {
+            let line_end = (address | 0x0F).min(end as usize);
+            let bytes = &self.machine.cpu.ram[address..=line_end];
+            let hex:Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text:String = bytes.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+            println!("{:04X} {:<47} {}", address, hex.join(" "), text);
+            address = line_end + 1;
+        }
+        self.next_display = address as u16;
+    }
+
+    fn list(&mut self, start:u16, end:Option<u16>) {
+        let mut address = start;
+        let mut lines = 0;
+        loop {
+            if let Some(name) = self.names.get(&address) {
+                println!(".{}:", name);
+            }
+            let instruction = disassemble(&self.machine.cpu.ram, address);
+            let bytes:Vec<String> = (0..instruction.length).map(|offset| format!("{:02X}", self.machine.cpu.ram[address.wrapping_add(offset) as usize])).collect();
+            let marker = if self.breakpoints.contains(&address) { '*' } else { ' ' };
+            println!("{}{:04X} {:<9}{}", marker, address, bytes.join(" "), instruction.format(|target| self.names.get(&target).cloned()));
+            let next = address.wrapping_add(instruction.length);
+            lines += 1;
+            let done = match end {
+                Some(end) => next > end || next < address,
+                None => lines >= LIST_LINES,
+            };
+            address = next;
+            if done {
+                break;
+            }
+        }
+        self.next_list = address;
+    }
+
+    fn read(&mut self, arguments:&[String]) -> Result<(), String> {
+        let path = arguments.first().filter(|path| !path.is_empty()).ok_or("R needs a file name")?;
+        if Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("sym")) {
+            let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+            let fields:Vec<&str> = text.split_whitespace().collect();
+            let mut count = 0;
+            for pair in fields.chunks(2) {
+                if let [value, name] = pair {
+                    let value = u16::from_str_radix(value, 16).map_err(|_| format!("bad symbol value {}", value))?;
+                    self.define(name, value);
+                    count += 1;
+                }
+            }
+            println!("{} symbols", count);
+            return Ok(());
+        }
+
+        let address = match arguments.get(1) {
+            Some(text) => self.evaluate(text)?,
+            None => 0x0100,
+        };
+        let start = self.machine.load(path, address, "").map_err(|error| error.to_string())?;
+        let pc = start.unwrap_or(address);
+        self.machine.cpu.pc = pc;
+        self.next_display = pc;
+        self.next_list = pc;
+        println!("loaded, PC={:04X}", pc);
+        Ok(())
+    }
+
+    // Returns false to quit
+    fn command(&mut self, line:&str) -> Result<bool, String> {
+        let line = line.trim();
+        let Some(letter) = line.chars().next() else {
+            return Ok(true);
+        };
+        let rest = line[letter.len_utf8()..].trim();
+        let text_arguments = split_arguments(rest);
+
+        match letter.to_ascii_uppercase() {
+            'Q' => return Ok(false),
+            '?' => println!("{}", HELP),
+            'D' => {
+                let values = self.arguments(rest)?;
+                let start = values.first().copied().unwrap_or(self.next_display);
+                let end = values.get(1).copied().unwrap_or_else(|| start.saturating_add(DISPLAY_BYTES as u16 - 1));
+                self.display(start, end);
+            }
+            'L' => {
+                let values = self.arguments(rest)?;
+                let start = values.first().copied().unwrap_or(self.next_list);
+                self.list(start, values.get(1).copied());
+            }
+            'G' => {
+                let mut arguments = text_arguments.iter();
+                if let Some(start) = arguments.next().filter(|start| !start.is_empty()) {
+                    let start = self.evaluate(start)?;
+                    self.set_register("PC", start)?;
+                }
+                let temporary = arguments.map(|argument| self.evaluate(argument)).collect::<Result<Vec<u16>, String>>()?;
+                self.run(RUN_LIMIT, &temporary, false);
+                if self.machine.stop_reason().is_none() {
+                    self.show_registers();
+                }
+            }
+            'T' | 'U' => {
+                let count = self.arguments(rest)?.first().copied().unwrap_or(1) as u64;
+                let trace = letter.eq_ignore_ascii_case(&'T');
+                self.run(count, &[], trace);
+                self.show_registers();
+            }
+            'B' => {
+                if let Some(clear) = rest.strip_prefix('-') {
+                    if clear.trim().is_empty() {
+                        self.breakpoints.clear();
+                    } else {
+                        let address = self.evaluate(clear)?;
+                        if !self.breakpoints.remove(&address) {
+                            return Err(format!("no breakpoint at {:04X}", address));
+                        }
+                    }
+                } else if rest.is_empty() {
+                    for address in &self.breakpoints {
+                        let name = self.names.get(address).map(|name| format!(" .{}", name)).unwrap_or_default();
+                        println!("{:04X}{}", address, name);
+                    }
+                } else {
+                    for address in self.arguments(rest)? {
+                        self.breakpoints.insert(address);
+                    }
+                }
+            }
+            'S' => {
+                let (address, bytes) = text_arguments.split_first().ok_or("S needs an address")?;
+                let address = self.evaluate(address)?;
+                for (offset, byte) in self.bytes(bytes)?.iter().enumerate() {
+                    self.machine.cpu.ram[address.wrapping_add(offset as u16) as usize] = *byte;
+                }
+            }
+            'X' => match text_arguments.as_slice() {
+                [] => self.show_registers(),
+                [name] => println!("{}={:X}", name.to_ascii_uppercase(), self.register(name).ok_or(format!("no register {}", name))?),
+                [name, value] => {
+                    let value = self.evaluate(value)?;
+                    self.set_register(name, value)?;
+                    self.show_registers();
+                }
+                _ => return Err("X takes a register and a value".to_string()),
+            },
+            'F' => {
+                let (bounds, bytes) = text_arguments.split_at(2.min(text_arguments.len()));
+                let [start, end] = bounds else {
+                    return Err("F needs start,end,byte".to_string());
+                };
+                let (start, end) = (self.evaluate(start)?, self.evaluate(end)?);
+                let pattern = self.bytes(bytes)?;
+                if pattern.is_empty() || end < start {
+                    return Err("F needs start,end,byte".to_string());
+                }
+                for (offset, address) in (start..=end).enumerate() {
+                    self.machine.cpu.ram[address as usize] = pattern[offset % pattern.len()];
+                }
+            }
+            'M' => {
+                let [start, end, destination] = self.arguments(rest)?[..] else {
+                    return Err("M needs start,end,dest".to_string());
+                };
+                if end < start || destination as usize + (end - start) as usize > 0xFFFF {
+                    return Err("bad range".to_string());
+                }
+                self.machine.cpu.ram.copy_within(start as usize..=end as usize, destination as usize);
+            }
+            'W' => {
+                let (bounds, bytes) = text_arguments.split_at(2.min(text_arguments.len()));
+                let [start, end] = bounds else {
+                    return Err("W needs start,end,byte...".to_string());
+                };
+                let (start, end) = (self.evaluate(start)? as usize, self.evaluate(end)? as usize);
+                let pattern = self.bytes(bytes)?;
+                if pattern.is_empty() || end < start {
+                    return Err("W needs start,end,byte...".to_string());
+                }
+                let memory = &self.machine.cpu.ram[start..=end];
+                let found:Vec<String> = memory.windows(pattern.len()).enumerate().filter(|(_, window)| *window == pattern.as_slice()).map(|(offset, _)| format!("{:04X}", start + offset)).collect();
+                if found.is_empty() {
+                    println!("not found");
+                } else {
+                    println!("{}", found.join(" "));
+                }
+            }
+            'R' => self.read(&text_arguments)?,
+            'N' => match text_arguments.as_slice() {
+                [] => {
+                    let mut symbols:Vec<(&u16, &String)> = self.symbols.iter().map(|(name, value)| (value, name)).collect();
+                    symbols.sort();
+                    for (value, name) in symbols {
+                        println!("{:04X} .{}", value, name);
+                    }
+                }
+                [name] => self.define(name, self.machine.cpu.pc),
+                [name, value] => {
+                    let value = self.evaluate(value)?;
+                    self.define(name, value);
+                }
+                _ => return Err("N takes a name and a value".to_string()),
+            },
+            'H' => match self.arguments(rest)?[..] {
+                [value] => println!("{:04X} #{} '{}'", value, value, if (0x20..0x7F).contains(&value) { value as u8 as char } else { '.' }),
+                [first, second] => println!("{:04X} {:04X}", first.wrapping_add(second), first.wrapping_sub(second)),
+                _ => return Err("H takes one or two values".to_string()),
+            },
+            'I' => {
+                let input = rest.replace("\\n", "\r");
+                self.machine.send_input(input.as_bytes());
+            }
+            _ => return Err(format!("unknown command {}, ? for help", letter)),
+        }
+        Ok(true)
+    }
+}
+
+fn main() {
+    let mut profile = Profile::Bare;
+    let mut image = None;
+    let mut address = 0x0100;
+    let mut arguments = String::new();
+    let mut options = std::env::args().skip(1);
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "-p" | "--profile" => {
+                let name = options.next().unwrap_or_default();
+                profile = Profile::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("mon8080: unknown profile {}", name);
+                    std::process::exit(1);
+                });
+            }
+            "-a" | "--address" => {
+                let text = options.next().unwrap_or_default();
+                address = intel8080_core::loader::parse_address(&text).unwrap_or_else(|| {
+                    eprintln!("mon8080: bad address {}", text);
+                    std::process::exit(1);
+                });
+            }
+            "--args" => arguments = options.next().unwrap_or_default(),
+            "-h" | "--help" => {
+                println!("usage: mon8080 [-p bare|cpm|altair|invaders] [-a addr] [--args text] [image]\n\n{}", HELP);
+                return;
+            }
+            _ => image = Some(option),
+        }
+    }
+
+    let mut machine = Machine::new(profile);
+    machine.cpu.pc = address;
+    if let Some(image) = &image {
+        match machine.load(image, address, &arguments) {
+            Ok(start) => machine.cpu.pc = start.unwrap_or(address),
+            Err(error) => {
+                eprintln!("mon8080: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut monitor = Monitor::new(machine);
+    println!("8080 monitor, ? for help");
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("-");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            break;
+        };
+        match monitor.command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => println!("? {}", message),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::ExitCode;
+
+use intel8080_core::loader::parse_address;
+use intel8080_core::machine::{Machine, Profile, Stop};
+use intel8080_core::romset::RomSet;
+
+// Headless runner: loads an image, runs it on a machine profile until it
+// halts or hits a limit, then reports the registers and any memory asked for
+
+const EXIT_HALTED:u8 = 0;
+const EXIT_USAGE:u8 = 1;
+const EXIT_LIMIT:u8 = 2;
+const EXIT_FAULT:u8 = 3;
+const EXIT_WAITING:u8 = 4;
+
+const USAGE:&str = "usage: run8080 [options] <image>
+
+Runs a .com, .bin, .hex or S-record image. With the invaders profile the
+image may also be a directory holding invaders.h, .g, .f and .e.
+
+options:
+  -p, --profile <name>       bare (default), cpm, altair or invaders
+  -a, --address <addr>       load address of binary images (default 0)
+  -s, --start <addr>         start here instead of the image's entry point
+  -c, --cycles <count>       stop after this many cycles
+  -n, --instructions <count> stop after this many instructions
+                             (default 100000000 when no limit is given)
+  -t, --trace                print every instruction to stderr
+  -d, --dump <addr>:<len>    dump memory when done; may be repeated
+  -i, --input <text>         console input, \\n for newlines
+      --stdin                console input from stdin
+      --args <text>          CP/M command tail
+      --drive <directory>    CP/M drive A (default .)
+  -q, --quiet                no register report
+
+Addresses are decimal, 0x-prefixed or h-suffixed hex.
+
+exit status: 0 halted or exited, 1 usage or load error, 2 limit reached,
+3 fault (unimplemented opcode or emulator panic), 4 waiting for input";
+
+struct Options {
+    image:String,
+    profile:Profile,
+    address:u16,
+    start:Option<u16>,
+    max_cycles:Option<u64>,
+    max_instructions:Option<u64>,
+    trace:bool,
+    dumps:Vec<(u16, usize)>,
+    input:Vec<u8>,
+    arguments:String,
+    drive:Option<String>,
+    quiet:bool,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        image: String::new(),
+        profile: Profile::Bare,
+        address: 0,
+        start: None,
+        max_cycles: None,
+        max_instructions: None,
+        trace: false,
+        dumps: Vec::new(),
+        input: Vec::new(),
+        arguments: String::new(),
+        drive: None,
+        quiet: false,
+    };
+
+    let mut arguments = std::env::args().skip(1);
+    let mut image = None;
+    while let Some(argument) = arguments.next() {
+        let mut value = |name:&str| arguments.next().ok_or(format!("{} needs a value", name));
+        let address = |text:String| parse_address(&text).ok_or(format!("bad address {}", text));
+        let count = |text:String| text.parse::<u64>().map_err(|_| format!("bad count {}", text));
+
+        match argument.as_str() {
+            "-p" | "--profile" => {
+                let name = value(&argument)?;
+                options.profile = Profile::from_name(&name).ok_or(format!("unknown profile {}", name))?;
+            }
+            "-a" | "--address" => options.address = address(value(&argument)?)?,
+            "-s" | "--start" => options.start = Some(address(value(&argument)?)?),
+            "-c" | "--cycles" => options.max_cycles = Some(count(value(&argument)?)?),
+            "-n" | "--instructions" => options.max_instructions = Some(count(value(&argument)?)?),
+            "-t" | "--trace" => options.trace = true,
+            "-d" | "--dump" => {
+                let text = value(&argument)?;
+                let (start, length) = text.split_once(':').ok_or(format!("bad dump {}, expected <addr>:<len>", text))?;
+                let length = parse_address(length).ok_or(format!("bad length {}", length))?;
+                options.dumps.push((address(start.to_string())?, length as usize));
+            }
+            "-i" | "--input" => options.input.extend(value(&argument)?.replace("\\n", "\r").bytes()),
+            "--stdin" => {
+                io::stdin().read_to_end(&mut options.input).map_err(|error| error.to_string())?;
+                for byte in options.input.iter_mut().filter(|byte| **byte == b'\n') {
+                    *byte = b'\r';
+                }
+            }
+            "--args" => options.arguments = value(&argument)?,
+            "--drive" => options.drive = Some(value(&argument)?),
+            "-q" | "--quiet" => options.quiet = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if argument.starts_with('-') => return Err(format!("unknown option {}", argument)),
+            _ if image.is_none() => image = Some(argument),
+            _ => return Err("only one image can be given".to_string()),
+        }
+    }
+
+    options.image = image.ok_or("no image given")?;
+    if options.max_cycles.is_none() && options.max_instructions.is_none() {
+        options.max_instructions = Some(100_000_000);
+    }
+    Ok(options)
+}
+
+fn trace(machine:&Machine) {
+    let cpu = &machine.cpu;
+    let registers = cpu.registers();
+    eprintln!(
+        "{:04x}  {:02x} {:02x} {:02x}  A-{:02x} F-{:02x} BC-{:04x} DE-{:04x} HL-{:04x} SP-{:04x}",
+        cpu.pc,
+        cpu.ram[cpu.pc as usize],
+        cpu.ram[cpu.pc.wrapping_add(1) as usize],
+        cpu.ram[cpu.pc.wrapping_add(2) as usize],
+        registers.a,
+        registers.flags,
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.sp
+    );
+}
+
+fn report(machine:&Machine) {
+    let cpu = &machine.cpu;
+    let registers = cpu.registers();
+    let flags:String = [(0x80, 'S'), (0x40, 'Z'), (0x10, 'A'), (0x04, 'P'), (0x01, 'C')]
+        .iter()
+        .map(|(mask, name)| if registers.flags & mask != 0 { *name } else { '.' })
+        .collect();
+    eprintln!(
+        "PC={:04x} SP={:04x} A={:02x} BC={:04x} DE={:04x} HL={:04x} F={:02x} {} IE={}",
+        cpu.pc,
+        registers.sp,
+        registers.a,
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.flags,
+        flags,
+        cpu.int_enabled as u8
+    );
+    eprintln!("{} instructions, {} cycles", machine.instructions, machine.cycles);
+}
+
+fn dump(machine:&Machine, start:u16, length:usize) {
+    let end = (start as usize + length).min(0x10000);
+    for line in (start as usize..end).step_by(16) {
+        let bytes = &machine.cpu.ram[line..(line + 16).min(end)];
+        let hex:Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text:String = bytes.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+        eprintln!("{:04x}  {:<47}  {}", line, hex.join(" "), text);
+    }
+}
+
+fn flush_output(machine:&mut Machine) {
+    let output = machine.take_output();
+    if !output.is_empty() {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&output);
+        let _ = stdout.flush();
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("run8080: {}", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let mut machine = Machine::new(options.profile);
+    if let (Some(bdos), Some(drive)) = (&machine.bdos, &options.drive) {
+        bdos.borrow_mut().map_drive(0, drive);
+    }
+
+    let image = Path::new(&options.image);
+    let loaded = if options.profile == Profile::Invaders && image.is_dir() {
+        machine.load_romset(&RomSet::invaders(), image).map_err(|error| error.to_string())
+    } else {
+        machine.load(image, options.address, &options.arguments).map(|_| ()).map_err(|error| error.to_string())
+    };
+    if let Err(message) = loaded {
+        eprintln!("run8080: {}", message);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if let Some(start) = options.start {
+        machine.cpu.pc = start;
+    }
+    machine.send_input(&options.input);
+
+    // Keep panics from the core as faults rather than a crash with a
+    // backtrace hint
+    panic::set_hook(Box::new(|info| eprintln!("run8080: fault: {}", info)));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| loop {
+        if let Some(stop) = machine.stop_reason() {
+            return Some(stop);
+        }
+        if options.max_instructions.is_some_and(|limit| machine.instructions >= limit) || options.max_cycles.is_some_and(|limit| machine.cycles >= limit) {
+            return None;
+        }
+        if options.trace {
+            trace(&machine);
+        }
+        machine.step();
+        flush_output(&mut machine);
+    }));
+    let _ = panic::take_hook();
+    flush_output(&mut machine);
+
+    let code = match result {
+        Ok(Some(Stop::Halted)) | Ok(Some(Stop::Stopped)) | Ok(Some(Stop::Exited)) => EXIT_HALTED,
+        Ok(Some(Stop::WaitingForInput)) => {
+            eprintln!("run8080: waiting for console input");
+            EXIT_WAITING
+        }
+        Ok(None) => {
+            eprintln!("run8080: limit reached");
+            EXIT_LIMIT
+        }
+        Err(_) => EXIT_FAULT,
+    };
+
+    if !options.quiet {
+        report(&machine);
+    }
+    for (start, length) in &options.dumps {
+        dump(&machine, *start, *length);
+    }
+    ExitCode::from(code)
+}
@@ -0,0 +1,854 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cpm::bios::{DiskParameterBlock, MAX_DRIVES};
+use crate::trap::TrapAction;
+use crate::CPU;
+
+// High level CP/M 2.2 BDOS
+// Instead of running the real BDOS against disk images, calls to 0x0005 are
+// handled in Rust and CP/M drives map onto host directories. Only files whose
+// names fit 8.3 are visible; new files are created with upper case names.
+// FCBs hold no host state: every read or write reopens the file and seeks to
+// the record the FCB points at, so programs can copy and move FCBs freely.
+//
+// Memory layout: the BDOS entry point sits at `base` + 6, followed by a
+// dummy disk parameter block and allocation vector. A console only BIOS jump
+// table at `base` + 0x200 serves programs that call the BIOS directly, and
+// its warm boot entry ends the program.
+//
+// The entry points are CPU traps, so the BDOS is shared with the CPU as an
+// `Rc<RefCell<Bdos>>` like the I/O devices. Ending the program, or a console
+// read with no input queued, stops the CPU at the entry point; clearing
+// `CPU::stopped` after queueing input retries the read.
+
+const RECORD_SIZE:usize = 128;
+const EXTENT_RECORDS:u32 = 128;
+const EOF:u8 = 0x1A;
+
+// Offsets from the BDOS base
+const ENTRY:u16 = 0x0006;
+const DPB:u16 = 0x0010;
+const ALV:u16 = 0x0020;
+const BIOS:u16 = 0x0200;
+const BIOS_STUBS:u16 = 0x0240;
+const BIOS_FUNCTIONS:u16 = 17;
+
+// Reported for every mapped drive: 8 MiB in 2 KiB blocks
+const HOST_DPB:DiskParameterBlock = DiskParameterBlock {
+    spt: 64,
+    bsh: 4,
+    blm: 15,
+    exm: 0,
+    dsm: 2047,
+    drm: 1023,
+    al0: 0xFF,
+    al1: 0x00,
+    cks: 0,
+    off: 0,
+};
+
+pub struct Bdos {
+    pub base:u16,
+
+    drives:Vec<Option<PathBuf>>,
+    dma:u16,
+    user:u8,
+    search:VecDeque<[u8; 32]>,
+
+    console_in:VecDeque<u8>,
+    console_out:VecDeque<u8>,
+    list_out:Vec<u8>,
+    punch_out:Vec<u8>,
+    reader_in:VecDeque<u8>,
+    waiting:bool,
+    exited:bool,
+}
+
+impl Default for Bdos {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bdos {
+    pub fn new() -> Self {
+        Self::with_base(0xFC00)
+    }
+
+    // `base` must leave 0x300 bytes below 0x10000 and lie above page zero;
+    // the TPA ends at `base` + 6
+    pub fn with_base(base:u16) -> Self {
+        Self {
+            base: base.clamp(0x0100, 0xFD00),
+            drives: (0..MAX_DRIVES).map(|_| None).collect(),
+            dma: 0x0080,
+            user: 0,
+            search: VecDeque::new(),
+            console_in: VecDeque::new(),
+            console_out: VecDeque::new(),
+            list_out: Vec::new(),
+            punch_out: Vec::new(),
+            reader_in: VecDeque::new(),
+            waiting: false,
+            exited: false,
+        }
+    }
+
+    // Maps CP/M drive `drive` (0 = A:) onto a host directory
+    pub fn map_drive<P:AsRef<Path>>(&mut self, drive:usize, directory:P) {
+        self.drives[drive % MAX_DRIVES] = Some(directory.as_ref().to_path_buf());
+    }
+
+    pub fn unmap_drive(&mut self, drive:usize) {
+        self.drives[drive % MAX_DRIVES] = None;
+    }
+
+    // Host side console
+    pub fn send(&mut self, byte:u8) {
+        self.console_in.push_back(byte);
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        self.console_in.extend(bytes);
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.console_in.len()
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        self.console_out.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.console_out.drain(..).collect()
+    }
+
+    pub fn take_list_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.list_out)
+    }
+
+    pub fn take_punch_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.punch_out)
+    }
+
+    pub fn send_reader(&mut self, bytes:&[u8]) {
+        self.reader_in.extend(bytes);
+    }
+
+    // True while a console read is blocked on an empty input queue
+    pub fn waiting_for_input(&self) -> bool {
+        self.waiting
+    }
+
+    // True once the program has warm booted or called function 0
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    // Writes page zero, the BDOS entry and the console BIOS into memory and
+    // traps the entry points
+    pub fn install(bdos:&Rc<RefCell<Bdos>>, cpu:&mut CPU) {
+        let (entry, stubs) = {
+            let mut bdos = bdos.borrow_mut();
+            bdos.write_tables(cpu);
+            (bdos.base + ENTRY, bdos.base + BIOS_STUBS)
+        };
+        let handler = bdos.clone();
+        cpu.set_trap(entry, move |cpu| handler.borrow_mut().bdos_call(cpu));
+        for function in 0..BIOS_FUNCTIONS {
+            let handler = bdos.clone();
+            cpu.set_trap(stubs + function, move |cpu| handler.borrow_mut().bios_call(function, cpu));
+        }
+    }
+
+    fn write_tables(&mut self, cpu:&mut CPU) {
+        let base = self.base as usize;
+        let entry = self.base + ENTRY;
+        let wboot = self.base + BIOS + 3;
+
+        cpu.ram[0x0000] = 0xC3;
+        cpu.ram[0x0001] = wboot as u8;
+        cpu.ram[0x0002] = (wboot >> 8) as u8;
+        cpu.ram[0x0005] = 0xC3;
+        cpu.ram[0x0006] = entry as u8;
+        cpu.ram[0x0007] = (entry >> 8) as u8;
+        cpu.ram[entry as usize] = 0xC9;
+
+        cpu.ram[base + DPB as usize..base + DPB as usize + 15].copy_from_slice(&HOST_DPB.to_bytes());
+        cpu.ram[base + ALV as usize..base + ALV as usize + HOST_DPB.alv_size()].fill(0);
+
+        for index in 0..BIOS_FUNCTIONS {
+            let table = base + BIOS as usize + index as usize * 3;
+            let stub = self.base + BIOS_STUBS + index;
+            cpu.ram[table] = 0xC3;
+            cpu.ram[table + 1] = stub as u8;
+            cpu.ram[table + 2] = (stub >> 8) as u8;
+            cpu.ram[stub as usize] = 0xC9;
+        }
+        self.dma = 0x0080;
+        self.exited = false;
+    }
+
+    // Loads a .COM program at 0x0100 the way the CCP would, with the
+    // command tail at 0x0080 and the first two arguments parsed into the
+    // default FCBs at 0x005C and 0x006C
+    pub fn load_program(bdos:&Rc<RefCell<Bdos>>, cpu:&mut CPU, program:&[u8], arguments:&str) {
+        Self::install(bdos, cpu);
+        let base = bdos.borrow().base;
+
+        let size = program.len().min((base as usize).saturating_sub(0x0100));
+        cpu.load_from(&program[..size], 0x0100);
+
+        let arguments = arguments.trim().to_ascii_uppercase();
+        let mut tail = Vec::new();
+        if !arguments.is_empty() {
+            tail.push(b' ');
+            tail.extend(arguments.bytes().take(125));
+        }
+        cpu.ram[0x0080] = tail.len() as u8;
+        cpu.ram[0x0081..0x0081 + tail.len()].copy_from_slice(&tail);
+        cpu.ram[0x0081 + tail.len()] = 0;
+
+        cpu.ram[0x005C..0x0080].fill(0);
+        let mut words = arguments.split_whitespace();
+        for fcb in [0x005C, 0x006C] {
+            let fcb_bytes = parse_fcb(words.next().unwrap_or(""));
+            cpu.ram[fcb..fcb + 12].copy_from_slice(&fcb_bytes);
+        }
+
+        // Returning from the program lands on the warm boot jump at 0x0000
+        cpu.sp = base.wrapping_sub(2);
+        cpu.ram[cpu.sp as usize] = 0;
+        cpu.ram[cpu.sp as usize + 1] = 0;
+        cpu.pc = 0x0100;
+        cpu.halted = false;
+    }
+
+    fn bios_call(&mut self, function:u16, cpu:&mut CPU) -> TrapAction {
+        self.waiting = false;
+        match function {
+            // BOOT, WBOOT
+            0 | 1 => {
+                self.exited = true;
+                return TrapAction::Stop;
+            }
+            // CONST
+            2 => cpu.a = if self.console_in.is_empty() { 0x00 } else { 0xFF },
+            // CONIN
+            3 => match self.console_in.pop_front() {
+                Some(byte) => cpu.a = byte & 0x7F,
+                None => {
+                    self.waiting = true;
+                    return TrapAction::Stop;
+                }
+            },
+            4 => self.console_out.push_back(cpu.c),
+            5 => self.list_out.push(cpu.c),
+            6 => self.punch_out.push(cpu.c),
+            7 => cpu.a = self.reader_in.pop_front().unwrap_or(EOF),
+            // SELDSK has no disk parameter headers to hand out
+            9 => {
+                cpu.h = 0;
+                cpu.l = 0;
+            }
+            // READ, WRITE
+            13 | 14 => cpu.a = 1,
+            // LISTST
+            15 => cpu.a = 0xFF,
+            // SECTRAN
+            16 => {
+                cpu.h = cpu.b;
+                cpu.l = cpu.c;
+            }
+            _ => {}
+        }
+        TrapAction::Return
+    }
+
+    fn current_drive(cpu:&CPU) -> usize {
+        (cpu.ram[0x0004] & 0x0F) as usize
+    }
+
+    // Host directory for the drive byte of the FCB at `fcb`
+    fn directory(&self, cpu:&CPU, fcb:u16) -> Option<PathBuf> {
+        let drive = match cpu.ram[fcb as usize] {
+            0 | b'?' => Self::current_drive(cpu),
+            drive => (drive as usize - 1) % MAX_DRIVES,
+        };
+        self.drives[drive].clone()
+    }
+
+    // Host files in `directory` whose 8.3 names match `pattern`, sorted
+    fn matching(directory:&Path, pattern:&[u8; 11]) -> Vec<([u8; 11], PathBuf)> {
+        let mut files:Vec<([u8; 11], PathBuf)> = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().map(|kind| kind.is_file()).unwrap_or(false))
+                .filter_map(|entry| {
+                    let name = fcb_name_from_host(&entry.file_name().to_string_lossy())?;
+                    if name_matches(pattern, &name) {
+                        Some((name, entry.path()))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort();
+        files
+    }
+
+    // Host path of the file named in the FCB, existing or not
+    fn host_path(&self, cpu:&CPU, fcb:u16) -> Option<PathBuf> {
+        let directory = self.directory(cpu, fcb)?;
+        let name = fcb_name(cpu, fcb);
+        match Self::matching(&directory, &name).into_iter().next() {
+            Some((_, path)) => Some(path),
+            None => Some(directory.join(safe_host_name(&name)?)),
+        }
+    }
+
+    fn existing_path(&self, cpu:&CPU, fcb:u16) -> Option<PathBuf> {
+        let directory = self.directory(cpu, fcb)?;
+        let name = fcb_name(cpu, fcb);
+        Self::matching(&directory, &name).into_iter().next().map(|(_, path)| path)
+    }
+
+    fn bdos_call(&mut self, cpu:&mut CPU) -> TrapAction {
+        let de = (cpu.d as u16) << 8 | cpu.e as u16;
+        let fcb = de;
+        self.waiting = false;
+
+        let mut hl:Option<u16> = None;
+        let a:u8 = match cpu.c {
+            0 => {
+                self.exited = true;
+                return TrapAction::Stop;
+            }
+            // Console input with echo
+            1 => match self.console_in.pop_front() {
+                Some(byte) => {
+                    let byte = byte & 0x7F;
+                    self.console_out.push_back(byte);
+                    byte
+                }
+                None => {
+                    self.waiting = true;
+                    return TrapAction::Stop;
+                }
+            },
+            2 => {
+                self.console_out.push_back(cpu.e);
+                0
+            }
+            3 => self.reader_in.pop_front().unwrap_or(EOF),
+            4 => {
+                self.punch_out.push(cpu.e);
+                0
+            }
+            5 => {
+                self.list_out.push(cpu.e);
+                0
+            }
+            // Direct console I/O
+            6 => match cpu.e {
+                0xFF => self.console_in.pop_front().unwrap_or(0),
+                0xFE => (!self.console_in.is_empty()) as u8 * 0xFF,
+                byte => {
+                    self.console_out.push_back(byte);
+                    0
+                }
+            },
+            7 => cpu.ram[0x0003],
+            8 => {
+                cpu.ram[0x0003] = cpu.e;
+                0
+            }
+            9 => {
+                let mut address = de;
+                while cpu.ram[address as usize] != b'$' {
+                    self.console_out.push_back(cpu.ram[address as usize]);
+                    address = address.wrapping_add(1);
+                    if address == de {
+                        break;
+                    }
+                }
+                0
+            }
+            10 => {
+                if !self.read_buffer(cpu, fcb) {
+                    self.waiting = true;
+                    return TrapAction::Stop;
+                }
+                0
+            }
+            11 => (!self.console_in.is_empty()) as u8,
+            12 => {
+                hl = Some(0x0022);
+                0
+            }
+            13 => {
+                self.dma = 0x0080;
+                cpu.ram[0x0004] &= 0xF0;
+                0
+            }
+            14 => {
+                let drive = (cpu.e & 0x0F) as usize;
+                if self.drives[drive].is_some() {
+                    cpu.ram[0x0004] = (cpu.ram[0x0004] & 0xF0) | drive as u8;
+                    0
+                } else {
+                    0xFF
+                }
+            }
+            15 => self.open(cpu, fcb),
+            16 => match self.existing_path(cpu, fcb) {
+                Some(_) => 0,
+                None => 0xFF,
+            },
+            17 => self.search_first(cpu, fcb),
+            18 => self.search_next(cpu),
+            19 => self.delete(cpu, fcb),
+            20 => {
+                let record = fcb_record(cpu, fcb);
+                let result = self.read_record(cpu, fcb, record);
+                if result == 0 {
+                    set_fcb_record(cpu, fcb, record + 1);
+                }
+                result
+            }
+            21 => {
+                let record = fcb_record(cpu, fcb);
+                let result = self.write_record(cpu, fcb, record);
+                if result == 0 {
+                    set_fcb_record(cpu, fcb, record + 1);
+                }
+                result
+            }
+            22 => self.make(cpu, fcb),
+            23 => self.rename(cpu, fcb),
+            24 => {
+                let vector = self
+                    .drives
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, directory)| directory.is_some())
+                    .fold(0u16, |vector, (drive, _)| vector | 1 << drive);
+                hl = Some(vector);
+                0
+            }
+            25 => Self::current_drive(cpu) as u8,
+            26 => {
+                self.dma = de;
+                0
+            }
+            27 => {
+                hl = Some(self.base + ALV);
+                0
+            }
+            28 => 0,
+            29 => {
+                hl = Some(0);
+                0
+            }
+            30 => match self.existing_path(cpu, fcb) {
+                Some(_) => 0,
+                None => 0xFF,
+            },
+            31 => {
+                hl = Some(self.base + DPB);
+                0
+            }
+            32 => {
+                if cpu.e == 0xFF {
+                    self.user
+                } else {
+                    self.user = cpu.e & 0x0F;
+                    0
+                }
+            }
+            33 => match random_record(cpu, fcb) {
+                Some(record) => {
+                    set_fcb_record(cpu, fcb, record);
+                    self.read_record(cpu, fcb, record)
+                }
+                None => 6,
+            },
+            34 | 40 => match random_record(cpu, fcb) {
+                Some(record) => {
+                    set_fcb_record(cpu, fcb, record);
+                    self.write_record(cpu, fcb, record)
+                }
+                None => 6,
+            },
+            35 => {
+                let size = self.existing_path(cpu, fcb).and_then(|path| fs::metadata(path).ok()).map(|metadata| metadata.len());
+                match size {
+                    Some(size) => {
+                        let records = size.div_ceil(RECORD_SIZE as u64) as u32;
+                        cpu.ram[fcb_address(fcb, 33)] = records as u8;
+                        cpu.ram[fcb_address(fcb, 34)] = (records >> 8) as u8;
+                        cpu.ram[fcb_address(fcb, 35)] = (records >> 16) as u8;
+                        0
+                    }
+                    None => 0xFF,
+                }
+            }
+            36 => {
+                let record = fcb_record(cpu, fcb);
+                cpu.ram[fcb_address(fcb, 33)] = record as u8;
+                cpu.ram[fcb_address(fcb, 34)] = (record >> 8) as u8;
+                cpu.ram[fcb_address(fcb, 35)] = (record >> 16) as u8;
+                0
+            }
+            37 => 0,
+            _ => 0xFF,
+        };
+
+        // Results come back in A = L and B = H
+        let hl = hl.unwrap_or(a as u16);
+        cpu.h = (hl >> 8) as u8;
+        cpu.l = hl as u8;
+        cpu.b = cpu.h;
+        cpu.a = cpu.l;
+        TrapAction::Return
+    }
+
+    // Function 10: waits until a whole line is queued, then fills the buffer
+    fn read_buffer(&mut self, cpu:&mut CPU, buffer:u16) -> bool {
+        let max = cpu.ram[buffer as usize] as usize;
+        let line_end = self.console_in.iter().position(|&byte| byte == b'\r' || byte == b'\n');
+        if line_end.is_none() && self.console_in.len() < max {
+            return false;
+        }
+
+        let mut line:Vec<u8> = Vec::new();
+        while line.len() < max {
+            let byte = match self.console_in.pop_front() {
+                Some(byte) => byte & 0x7F,
+                None => break,
+            };
+            match byte {
+                b'\r' | b'\n' => {
+                    self.console_out.push_back(b'\r');
+                    break;
+                }
+                0x08 | 0x7F => {
+                    if line.pop().is_some() {
+                        self.console_out.extend([0x08, b' ', 0x08]);
+                    }
+                }
+                _ => {
+                    self.console_out.push_back(byte);
+                    line.push(byte);
+                }
+            }
+        }
+        // A CR that arrives right after a full buffer belongs to this line
+        if line.len() == max && matches!(self.console_in.front(), Some(b'\r' | b'\n')) {
+            self.console_in.pop_front();
+        }
+
+        cpu.ram[buffer.wrapping_add(1) as usize] = line.len() as u8;
+        for (index, byte) in line.iter().enumerate() {
+            cpu.ram[buffer.wrapping_add(2 + index as u16) as usize] = *byte;
+        }
+        true
+    }
+
+    fn open(&mut self, cpu:&mut CPU, fcb:u16) -> u8 {
+        let size = match self.existing_path(cpu, fcb).and_then(|path| fs::metadata(path).ok()) {
+            Some(metadata) => metadata.len(),
+            None => return 0xFF,
+        };
+        cpu.ram[fcb_address(fcb, 14)] = 0;
+        let extent = extent_number(cpu, fcb);
+        let records = size.div_ceil(RECORD_SIZE as u64) as u32;
+        if extent > 0 && extent * EXTENT_RECORDS >= records {
+            return 0xFF;
+        }
+        cpu.ram[fcb_address(fcb, 15)] = extent_records(records, extent);
+        0
+    }
+
+    fn make(&mut self, cpu:&mut CPU, fcb:u16) -> u8 {
+        let path = match self.host_path(cpu, fcb) {
+            Some(path) => path,
+            None => return 0xFF,
+        };
+        if File::create(path).is_err() {
+            return 0xFF;
+        }
+        cpu.ram[fcb_address(fcb, 14)] = 0;
+        cpu.ram[fcb_address(fcb, 15)] = 0;
+        0
+    }
+
+    fn delete(&mut self, cpu:&mut CPU, fcb:u16) -> u8 {
+        let directory = match self.directory(cpu, fcb) {
+            Some(directory) => directory,
+            None => return 0xFF,
+        };
+        let files = Self::matching(&directory, &fcb_name(cpu, fcb));
+        let deleted = files.iter().filter(|(_, path)| fs::remove_file(path).is_ok()).count();
+        if deleted > 0 {
+            0
+        } else {
+            0xFF
+        }
+    }
+
+    fn rename(&mut self, cpu:&mut CPU, fcb:u16) -> u8 {
+        let (old, directory) = match (self.existing_path(cpu, fcb), self.directory(cpu, fcb)) {
+            (Some(old), Some(directory)) => (old, directory),
+            _ => return 0xFF,
+        };
+        let new_name = fcb_name(cpu, fcb.wrapping_add(16));
+        if !Self::matching(&directory, &new_name).is_empty() {
+            return 0xFF;
+        }
+        let Some(new_name) = safe_host_name(&new_name) else {
+            return 0xFF;
+        };
+        match fs::rename(old, directory.join(new_name)) {
+            Ok(_) => 0,
+            Err(_) => 0xFF,
+        }
+    }
+
+    fn search_first(&mut self, cpu:&mut CPU, fcb:u16) -> u8 {
+        self.search.clear();
+        let directory = match self.directory(cpu, fcb) {
+            Some(directory) => directory,
+            None => return 0xFF,
+        };
+        let all = cpu.ram[fcb as usize] == b'?';
+        let pattern = if all { [b'?'; 11] } else { fcb_name(cpu, fcb) };
+        let wanted_extent = if all || cpu.ram[fcb_address(fcb, 12)] == b'?' { None } else { Some(extent_number(cpu, fcb)) };
+
+        for (name, path) in Self::matching(&directory, &pattern) {
+            let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+            let records = size.div_ceil(RECORD_SIZE as u64) as u32;
+            let extents = records.div_ceil(EXTENT_RECORDS).max(1);
+            for extent in 0..extents {
+                if wanted_extent.is_some_and(|wanted| wanted != extent) {
+                    continue;
+                }
+                let mut entry = [0u8; 32];
+                entry[0] = self.user;
+                entry[1..12].copy_from_slice(&name);
+                entry[12] = (extent % 32) as u8;
+                entry[14] = (extent / 32) as u8;
+                let count = extent_records(records, extent);
+                entry[15] = count;
+                // Dummy block numbers so the extent looks allocated
+                let blocks = (count as usize).div_ceil(16);
+                for (index, block) in entry[16..16 + blocks].iter_mut().enumerate() {
+                    *block = index as u8 + 1;
+                }
+                self.search.push_back(entry);
+            }
+        }
+        self.search_next(cpu)
+    }
+
+    fn search_next(&mut self, cpu:&mut CPU) -> u8 {
+        match self.search.pop_front() {
+            Some(entry) => {
+                let dma = self.dma as usize;
+                let end = (dma + RECORD_SIZE).min(cpu.ram.len());
+                cpu.ram[dma..end].fill(0xE5);
+                let end = (dma + entry.len()).min(cpu.ram.len());
+                cpu.ram[dma..end].copy_from_slice(&entry[..end - dma]);
+                0
+            }
+            None => 0xFF,
+        }
+    }
+
+    // 0 = ok, 1 = end of file
+    fn read_record(&mut self, cpu:&mut CPU, fcb:u16, record:u32) -> u8 {
+        let path = match self.existing_path(cpu, fcb) {
+            Some(path) => path,
+            None => return 1,
+        };
+        let mut buffer = [EOF; RECORD_SIZE];
+        let read = File::open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+            let mut count = 0;
+            while count < RECORD_SIZE {
+                match file.read(&mut buffer[count..])? {
+                    0 => break,
+                    read => count += read,
+                }
+            }
+            Ok(count)
+        });
+        match read {
+            Ok(0) | Err(_) => 1,
+            Ok(_) => {
+                let dma = self.dma as usize;
+                let end = (dma + RECORD_SIZE).min(cpu.ram.len());
+                cpu.ram[dma..end].copy_from_slice(&buffer[..end - dma]);
+                0
+            }
+        }
+    }
+
+    // 0 = ok, 2 = disk full (or any host error)
+    fn write_record(&mut self, cpu:&mut CPU, fcb:u16, record:u32) -> u8 {
+        let path = match self.existing_path(cpu, fcb) {
+            Some(path) => path,
+            None => return 2,
+        };
+        let dma = self.dma as usize;
+        let mut buffer = [0u8; RECORD_SIZE];
+        let end = (dma + RECORD_SIZE).min(cpu.ram.len());
+        buffer[..end - dma].copy_from_slice(&cpu.ram[dma..end]);
+
+        let written = OpenOptions::new().write(true).open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+            file.write_all(&buffer)
+        });
+        if written.is_err() {
+            return 2;
+        }
+        let extent_count = cpu.ram[fcb_address(fcb, 15)] as u32;
+        let in_extent = record % EXTENT_RECORDS + 1;
+        if in_extent > extent_count {
+            cpu.ram[fcb_address(fcb, 15)] = in_extent as u8;
+        }
+        0
+    }
+}
+
+// Address of byte `offset` of the FCB at `fcb`. Like any other memory access
+// by the CPU an FCB wraps around from 0xFFFF to 0x0000.
+fn fcb_address(fcb:u16, offset:u16) -> usize {
+    fcb.wrapping_add(offset) as usize
+}
+
+// Name and type from the FCB at `fcb`, attribute bits stripped
+fn fcb_name(cpu:&CPU, fcb:u16) -> [u8; 11] {
+    let mut name = [b' '; 11];
+    for (index, byte) in name.iter_mut().enumerate() {
+        *byte = (cpu.ram[fcb_address(fcb, 1 + index as u16)] & 0x7F).to_ascii_uppercase();
+    }
+    name
+}
+
+fn name_matches(pattern:&[u8; 11], name:&[u8; 11]) -> bool {
+    pattern.iter().zip(name.iter()).all(|(&want, &have)| want == b'?' || want == have)
+}
+
+// "NAME.TYP" for an 8.3 name in FCB form
+fn host_name(name:&[u8; 11]) -> String {
+    let base = String::from_utf8_lossy(&name[..8]).trim_end().to_string();
+    let extension = String::from_utf8_lossy(&name[8..]).trim_end().to_string();
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+// Host name for an FCB name that is a valid 8.3 name, so that it can only
+// name a file inside the drive directory
+fn safe_host_name(name:&[u8; 11]) -> Option<String> {
+    let host = host_name(name);
+    (fcb_name_from_host(&host) == Some(*name)).then_some(host)
+}
+
+// FCB form of a host file name, None if it doesn't fit 8.3
+fn fcb_name_from_host(host:&str) -> Option<[u8; 11]> {
+    let (base, extension) = match host.rfind('.') {
+        Some(dot) => (&host[..dot], &host[dot + 1..]),
+        None => (host, ""),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+    let valid = |byte:u8| byte.is_ascii_graphic() && !b"<>.,;:=?*[]/\\".contains(&byte);
+    if !base.bytes().chain(extension.bytes()).all(valid) {
+        return None;
+    }
+
+    let mut name = [b' '; 11];
+    for (index, byte) in base.bytes().enumerate() {
+        name[index] = byte.to_ascii_uppercase();
+    }
+    for (index, byte) in extension.bytes().enumerate() {
+        name[8 + index] = byte.to_ascii_uppercase();
+    }
+    Some(name)
+}
+
+// Drive byte, name and type of an FCB for a command line word like
+// "B:*.ASM", with '*' expanded to '?'
+fn parse_fcb(word:&str) -> [u8; 12] {
+    let mut fcb = [b' '; 12];
+    fcb[0] = 0;
+    let bytes = word.as_bytes();
+    let name = if bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic() {
+        fcb[0] = bytes[0].to_ascii_uppercase() - b'A' + 1;
+        &word[2..]
+    } else {
+        word
+    };
+    let (base, extension) = match name.find('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let fill = |field:&mut [u8], text:&str| {
+        for (index, byte) in text.bytes().enumerate().take(field.len()) {
+            if byte == b'*' {
+                field[index..].fill(b'?');
+                return;
+            }
+            field[index] = byte;
+        }
+    };
+    fill(&mut fcb[1..9], base);
+    fill(&mut fcb[9..12], extension);
+    fcb
+}
+
+fn extent_number(cpu:&CPU, fcb:u16) -> u32 {
+    (cpu.ram[fcb_address(fcb, 14)] & 0x3F) as u32 * 32 + (cpu.ram[fcb_address(fcb, 12)] & 0x1F) as u32
+}
+
+// Records held by logical extent `extent` of a file `records` long
+fn extent_records(records:u32, extent:u32) -> u8 {
+    records.saturating_sub(extent * EXTENT_RECORDS).min(EXTENT_RECORDS) as u8
+}
+
+// Sequential position from the extent, module and current record fields
+fn fcb_record(cpu:&CPU, fcb:u16) -> u32 {
+    extent_number(cpu, fcb) * EXTENT_RECORDS + (cpu.ram[fcb_address(fcb, 32)] & 0x7F) as u32
+}
+
+fn set_fcb_record(cpu:&mut CPU, fcb:u16, record:u32) {
+    let extent = record / EXTENT_RECORDS;
+    let changed = extent != extent_number(cpu, fcb);
+    cpu.ram[fcb_address(fcb, 12)] = (extent % 32) as u8;
+    cpu.ram[fcb_address(fcb, 14)] = (extent / 32) as u8;
+    cpu.ram[fcb_address(fcb, 32)] = (record % EXTENT_RECORDS) as u8;
+    if changed {
+        cpu.ram[fcb_address(fcb, 15)] = 0;
+    }
+}
+
+// Random record number from r0-r2, None past the 8 MiB limit
+fn random_record(cpu:&CPU, fcb:u16) -> Option<u32> {
+    if cpu.ram[fcb_address(fcb, 35)] != 0 {
+        return None;
+    }
+    Some(cpu.ram[fcb_address(fcb, 33)] as u32 | (cpu.ram[fcb_address(fcb, 34)] as u32) << 8)
+}
@@ -0,0 +1,551 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::disk::{DiskGeometry, DiskImage};
+use crate::trap::TrapAction;
+use crate::CPU;
+
+// CP/M 2.2 BIOS
+// The jump table at the top of memory points at a row of RET stubs. Each
+// stub is a CPU trap that performs the BIOS function in Rust instead and
+// returns to the caller, so the BIOS is shared with the CPU as an
+// `Rc<RefCell<Bios>>`. Programs that patch the jump table keep working since
+// only the stubs are intercepted. CONIN with no input queued stops the CPU
+// at its stub; clearing `CPU::stopped` after queueing input retries it.
+// Drives are raw sector images described by a disk parameter block; the BIOS
+// deblocks 128 byte CP/M records onto larger physical sectors itself.
+
+pub const MAX_DRIVES:usize = 16;
+
+const RECORD_SIZE:usize = 128;
+const FUNCTIONS:u16 = 17;
+
+// Offsets from the BIOS base
+const STUBS:u16 = 0x40;
+const DIRBUF:u16 = 0x60;
+const TABLES:u16 = DIRBUF + RECORD_SIZE as u16;
+
+// Size of CCP + BDOS, loaded by cold and warm boot
+const SYSTEM_SIZE:usize = 0x1600;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Function {
+    Boot,
+    WBoot,
+    Const,
+    Conin,
+    Conout,
+    List,
+    Punch,
+    Reader,
+    Home,
+    SelDsk,
+    SetTrk,
+    SetSec,
+    SetDma,
+    Read,
+    Write,
+    ListSt,
+    SecTran,
+}
+
+impl Function {
+    fn from_index(index:u16) -> Self {
+        match index {
+            0 => Function::Boot,
+            1 => Function::WBoot,
+            2 => Function::Const,
+            3 => Function::Conin,
+            4 => Function::Conout,
+            5 => Function::List,
+            6 => Function::Punch,
+            7 => Function::Reader,
+            8 => Function::Home,
+            9 => Function::SelDsk,
+            10 => Function::SetTrk,
+            11 => Function::SetSec,
+            12 => Function::SetDma,
+            13 => Function::Read,
+            14 => Function::Write,
+            15 => Function::ListSt,
+            _ => Function::SecTran,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskParameterBlock {
+    pub spt:u16, // 128 byte records per track
+    pub bsh:u8,
+    pub blm:u8,
+    pub exm:u8,
+    pub dsm:u16, // Highest block number
+    pub drm:u16, // Highest directory entry number
+    pub al0:u8,
+    pub al1:u8,
+    pub cks:u16,
+    pub off:u16, // Reserved system tracks
+}
+
+impl DiskParameterBlock {
+    // The standard 8" single density distribution format
+    pub const IBM_3740:DiskParameterBlock = DiskParameterBlock {
+        spt: 26,
+        bsh: 3,
+        blm: 7,
+        exm: 0,
+        dsm: 242,
+        drm: 63,
+        al0: 0xC0,
+        al1: 0x00,
+        cks: 16,
+        off: 2,
+    };
+
+    // Parameters for a removable disk with `block_size` byte allocation
+    // blocks (1024 to 16384) and `dir_entries` directory entries
+    pub fn new(spt:u16, tracks:u16, block_size:usize, dir_entries:u16, off:u16) -> Self {
+        let records = block_size / RECORD_SIZE;
+        let blocks = (tracks.saturating_sub(off) as usize * spt as usize) / records;
+        let dsm = blocks.saturating_sub(1) as u16;
+        let exm = if dsm < 256 { block_size / 1024 } else { block_size / 2048 };
+
+        let dir_blocks = (dir_entries as usize * 32).div_ceil(block_size).min(16);
+        let alloc = !(0xFFFFu32 >> dir_blocks) as u16;
+
+        Self {
+            spt,
+            bsh: records.trailing_zeros() as u8,
+            blm: (records - 1) as u8,
+            exm: exm.saturating_sub(1) as u8,
+            dsm,
+            drm: dir_entries - 1,
+            al0: (alloc >> 8) as u8,
+            al1: alloc as u8,
+            cks: dir_entries / 4,
+            off,
+        }
+    }
+
+    // Parameters covering a whole image, one side of a cylinder per track
+    pub fn for_geometry(geometry:&DiskGeometry, block_size:usize, dir_entries:u16, off:u16) -> Self {
+        let spt = (geometry.sectors * geometry.sector_size / RECORD_SIZE) as u16;
+        let tracks = (geometry.tracks * geometry.sides) as u16;
+        Self::new(spt, tracks, block_size, dir_entries, off)
+    }
+
+    pub fn alv_size(&self) -> usize {
+        self.dsm as usize / 8 + 1
+    }
+
+    pub fn to_bytes(&self) -> [u8; 15] {
+        [
+            self.spt as u8,
+            (self.spt >> 8) as u8,
+            self.bsh,
+            self.blm,
+            self.exm,
+            self.dsm as u8,
+            (self.dsm >> 8) as u8,
+            self.drm as u8,
+            (self.drm >> 8) as u8,
+            self.al0,
+            self.al1,
+            self.cks as u8,
+            (self.cks >> 8) as u8,
+            self.off as u8,
+            (self.off >> 8) as u8,
+        ]
+    }
+}
+
+// Physical sector numbers for logical sectors 0.., interleaved by `skew`
+pub fn skew_table(sectors:usize, skew:usize, first_sector:u8) -> Vec<u8> {
+    let mut used = vec![false; sectors];
+    let mut table = Vec::with_capacity(sectors);
+    let mut position = 0;
+    for _ in 0..sectors {
+        while used[position] {
+            position = (position + 1) % sectors;
+        }
+        used[position] = true;
+        table.push(position as u8 + first_sector);
+        position = (position + skew) % sectors;
+    }
+    table
+}
+
+pub struct CpmDrive {
+    pub image:DiskImage,
+    pub dpb:DiskParameterBlock,
+    // Sector translation used by SECTRAN. With a table the BIOS is handed
+    // record numbers counted from the disk's first sector number, which for
+    // 128 byte sectors are the physical sectors. Without one it gets 0 based
+    // record numbers.
+    pub translate:Option<Vec<u8>>,
+}
+
+impl CpmDrive {
+    pub fn new(image:DiskImage, dpb:DiskParameterBlock, translate:Option<Vec<u8>>) -> Self {
+        Self { image, dpb, translate }
+    }
+
+    // 8" single density disk with the standard skew of 6
+    pub fn ibm_3740(image:DiskImage) -> Self {
+        Self::new(image, DiskParameterBlock::IBM_3740, Some(skew_table(26, 6, 1)))
+    }
+
+    // Cylinder, side, physical sector and byte offset of a CP/M record
+    fn locate(&self, track:u16, sector:u16) -> Option<(usize, usize, u8, usize)> {
+        let geometry = self.image.geometry;
+        let (cylinder, side) = (track as usize / geometry.sides, track as usize % geometry.sides);
+        let record = match self.translate {
+            Some(_) => (sector as usize).checked_sub(geometry.first_sector as usize)?,
+            None => sector as usize,
+        };
+        let per_sector = geometry.sector_size / RECORD_SIZE;
+        let physical = geometry.first_sector as usize + record / per_sector;
+        let offset = (record % per_sector) * RECORD_SIZE;
+        Some((cylinder, side, u8::try_from(physical).ok()?, offset))
+    }
+
+    fn read_record(&self, track:u16, sector:u16) -> Option<&[u8]> {
+        let (cylinder, side, physical, offset) = self.locate(track, sector)?;
+        let data = self.image.read_sector(cylinder, side, physical)?;
+        data.get(offset..offset + RECORD_SIZE)
+    }
+
+    fn write_record(&mut self, track:u16, sector:u16, record:&[u8]) -> bool {
+        let (cylinder, side, physical, offset) = match self.locate(track, sector) {
+            Some(location) => location,
+            None => return false,
+        };
+        let mut data = match self.image.read_sector(cylinder, side, physical) {
+            Some(data) => data.to_vec(),
+            None => return false,
+        };
+        if offset + RECORD_SIZE > data.len() {
+            return false;
+        }
+        data[offset..offset + RECORD_SIZE].copy_from_slice(record);
+        self.image.write_sector(cylinder, side, physical, &data)
+    }
+}
+
+pub struct Bios {
+    pub ccp:u16,
+    pub bdos:u16,
+    pub base:u16,
+
+    drives:Vec<Option<CpmDrive>>,
+    dph:[u16; MAX_DRIVES],
+    system:Option<Vec<u8>>,
+
+    disk:usize,
+    track:u16,
+    sector:u16,
+    dma:u16,
+
+    console_in:VecDeque<u8>,
+    console_out:VecDeque<u8>,
+    list_out:Vec<u8>,
+    punch_out:Vec<u8>,
+    reader_in:VecDeque<u8>,
+    waiting:bool,
+}
+
+impl Bios {
+    // Standard CP/M 2.2 layout for a system of `memory_kb` KiB (20 to 64)
+    pub fn new(memory_kb:u16) -> Self {
+        let bias = (memory_kb.clamp(20, 64) - 20) as u32 * 1024;
+        let ccp = (0x3400 + bias) as u16;
+        Self {
+            ccp,
+            bdos: ccp + 0x0800,
+            base: ccp + 0x1600,
+            drives: (0..MAX_DRIVES).map(|_| None).collect(),
+            dph: [0; MAX_DRIVES],
+            system: None,
+            disk: 0,
+            track: 0,
+            sector: 0,
+            dma: 0x0080,
+            console_in: VecDeque::new(),
+            console_out: VecDeque::new(),
+            list_out: Vec::new(),
+            punch_out: Vec::new(),
+            reader_in: VecDeque::new(),
+            waiting: false,
+        }
+    }
+
+    // Drives have to be set up before `install` / `boot`, which lay out the
+    // disk parameter headers
+    pub fn set_drive(&mut self, drive:usize, cpm_drive:CpmDrive) {
+        self.drives[drive % MAX_DRIVES] = Some(cpm_drive);
+    }
+
+    pub fn remove_drive(&mut self, drive:usize) -> Option<CpmDrive> {
+        self.drives[drive % MAX_DRIVES].take()
+    }
+
+    pub fn drive(&self, drive:usize) -> Option<&CpmDrive> {
+        self.drives.get(drive)?.as_ref()
+    }
+
+    // CCP + BDOS image (0x1600 bytes, assembled for `ccp`) loaded on boot.
+    // Without one the system tracks of drive A are used, starting at the
+    // second 128 byte record after the cold boot loader.
+    pub fn set_system(&mut self, system:Vec<u8>) {
+        self.system = Some(system);
+    }
+
+    // Host side console
+    pub fn send(&mut self, byte:u8) {
+        self.console_in.push_back(byte);
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        self.console_in.extend(bytes);
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.console_in.len()
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        self.console_out.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.console_out.drain(..).collect()
+    }
+
+    pub fn take_list_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.list_out)
+    }
+
+    pub fn take_punch_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.punch_out)
+    }
+
+    pub fn send_reader(&mut self, bytes:&[u8]) {
+        self.reader_in.extend(bytes);
+    }
+
+    // True while CONIN is blocked on an empty console queue
+    pub fn waiting_for_input(&self) -> bool {
+        self.waiting
+    }
+
+    fn stub(&self, function:Function) -> u16 {
+        self.base + STUBS + function as u16
+    }
+
+    // Writes the jump table, stubs and disk parameter headers into memory and
+    // traps the stubs. Returns false if the drive tables don't fit below
+    // 0x10000.
+    pub fn install(bios:&Rc<RefCell<Bios>>, cpu:&mut CPU) -> bool {
+        let stubs = {
+            let mut bios = bios.borrow_mut();
+            if !bios.write_tables(cpu) {
+                return false;
+            }
+            bios.base + STUBS
+        };
+        for index in 0..FUNCTIONS {
+            let handler = bios.clone();
+            let function = Function::from_index(index);
+            cpu.set_trap(stubs + index, move |cpu| handler.borrow_mut().call(function, cpu));
+        }
+        true
+    }
+
+    fn write_tables(&mut self, cpu:&mut CPU) -> bool {
+        let base = self.base as usize;
+        for index in 0..FUNCTIONS {
+            let entry = base + index as usize * 3;
+            let stub = self.stub(Function::from_index(index));
+            cpu.ram[entry] = 0xC3;
+            cpu.ram[entry + 1] = stub as u8;
+            cpu.ram[entry + 2] = (stub >> 8) as u8;
+            cpu.ram[stub as usize] = 0xC9;
+        }
+
+        let mut next = base + TABLES as usize;
+        let mut allocate = |size:usize| {
+            let address = next;
+            next += size;
+            address
+        };
+
+        let mut tables = Vec::new();
+        for (drive, cpm_drive) in self.drives.iter().enumerate() {
+            let cpm_drive = match cpm_drive {
+                Some(cpm_drive) => cpm_drive,
+                None => {
+                    self.dph[drive] = 0;
+                    continue;
+                }
+            };
+            let dph = allocate(16);
+            let dpb = allocate(15);
+            let xlt = cpm_drive.translate.as_ref().map(|table| (allocate(table.len()), table.clone()));
+            let csv = allocate(cpm_drive.dpb.cks as usize);
+            let alv = allocate(cpm_drive.dpb.alv_size());
+            self.dph[drive] = dph as u16;
+            tables.push((dph, dpb, xlt, csv, alv, cpm_drive.dpb));
+        }
+        if next > cpu.ram.len() {
+            return false;
+        }
+
+        let dirbuf = base + DIRBUF as usize;
+        let write_word = |ram:&mut [u8], address:usize, value:usize| {
+            ram[address] = value as u8;
+            ram[address + 1] = (value >> 8) as u8;
+        };
+        for (dph, dpb, xlt, csv, alv, parameters) in tables {
+            cpu.ram[dph..dph + 16].fill(0);
+            match &xlt {
+                Some((address, table)) => {
+                    write_word(&mut cpu.ram, dph, *address);
+                    cpu.ram[*address..*address + table.len()].copy_from_slice(table);
+                }
+                None => write_word(&mut cpu.ram, dph, 0),
+            }
+            write_word(&mut cpu.ram, dph + 8, dirbuf);
+            write_word(&mut cpu.ram, dph + 10, dpb);
+            write_word(&mut cpu.ram, dph + 12, csv);
+            write_word(&mut cpu.ram, dph + 14, alv);
+            cpu.ram[dpb..dpb + 15].copy_from_slice(&parameters.to_bytes());
+        }
+        true
+    }
+
+    // Installs the BIOS and cold boots into the CCP
+    pub fn boot(bios:&Rc<RefCell<Bios>>, cpu:&mut CPU) -> bool {
+        if !Self::install(bios, cpu) {
+            return false;
+        }
+        bios.borrow_mut().cold_boot(cpu)
+    }
+
+    fn load_system(&self, cpu:&mut CPU) -> bool {
+        let system = match &self.system {
+            Some(system) => system.as_slice(),
+            None => match self.drive(0) {
+                Some(drive) => match drive.image.data().get(RECORD_SIZE..RECORD_SIZE + SYSTEM_SIZE) {
+                    Some(system) => system,
+                    None => return false,
+                },
+                None => return false,
+            },
+        };
+        let size = system.len().min(SYSTEM_SIZE).min(cpu.ram.len() - self.ccp as usize);
+        cpu.load_from(&system[..size], self.ccp as usize);
+        true
+    }
+
+    fn cold_boot(&mut self, cpu:&mut CPU) -> bool {
+        cpu.ram[0x0003] = 0; // IOBYTE
+        cpu.ram[0x0004] = 0; // Current drive and user
+        self.warm_boot(cpu)
+    }
+
+    fn warm_boot(&mut self, cpu:&mut CPU) -> bool {
+        if !self.load_system(cpu) {
+            // Nothing to boot, stop the CPU
+            cpu.int_enabled = false;
+            cpu.halted = true;
+            return false;
+        }
+
+        let wboot = self.base + 3;
+        let bdos = self.bdos + 6;
+        cpu.ram[0x0000] = 0xC3;
+        cpu.ram[0x0001] = wboot as u8;
+        cpu.ram[0x0002] = (wboot >> 8) as u8;
+        cpu.ram[0x0005] = 0xC3;
+        cpu.ram[0x0006] = bdos as u8;
+        cpu.ram[0x0007] = (bdos >> 8) as u8;
+
+        self.dma = 0x0080;
+        cpu.sp = 0x0080;
+        cpu.c = cpu.ram[0x0004];
+        cpu.pc = self.ccp;
+        cpu.halted = false;
+        true
+    }
+
+    // Performs a BIOS function. Boot carries on in the CCP, and CONIN with
+    // no input yet stops the CPU instead of returning to the caller.
+    fn call(&mut self, function:Function, cpu:&mut CPU) -> TrapAction {
+        let bc = (cpu.b as u16) << 8 | cpu.c as u16;
+        let de = (cpu.d as u16) << 8 | cpu.e as u16;
+        self.waiting = false;
+
+        match function {
+            Function::Boot => return if self.cold_boot(cpu) { TrapAction::Continue } else { TrapAction::Stop },
+            Function::WBoot => return if self.warm_boot(cpu) { TrapAction::Continue } else { TrapAction::Stop },
+            Function::Const => cpu.a = if self.console_in.is_empty() { 0x00 } else { 0xFF },
+            Function::Conin => match self.console_in.pop_front() {
+                Some(byte) => cpu.a = byte & 0x7F,
+                None => {
+                    self.waiting = true;
+                    return TrapAction::Stop;
+                }
+            },
+            Function::Conout => self.console_out.push_back(cpu.c),
+            Function::List => self.list_out.push(cpu.c),
+            Function::Punch => self.punch_out.push(cpu.c),
+            Function::Reader => cpu.a = self.reader_in.pop_front().unwrap_or(0x1A),
+            Function::Home => self.track = 0,
+            Function::SelDsk => {
+                let drive = cpu.c as usize;
+                let dph = if drive < MAX_DRIVES { self.dph[drive] } else { 0 };
+                if dph != 0 {
+                    self.disk = drive;
+                }
+                cpu.h = (dph >> 8) as u8;
+                cpu.l = dph as u8;
+            }
+            Function::SetTrk => self.track = bc,
+            Function::SetSec => self.sector = bc,
+            Function::SetDma => self.dma = bc,
+            Function::Read => {
+                let dma = self.dma as usize;
+                let record = self.drives[self.disk].as_ref().and_then(|drive| drive.read_record(self.track, self.sector));
+                cpu.a = match record {
+                    Some(record) if dma + RECORD_SIZE <= cpu.ram.len() => {
+                        cpu.ram[dma..dma + RECORD_SIZE].copy_from_slice(record);
+                        0
+                    }
+                    _ => 1,
+                };
+            }
+            Function::Write => {
+                let dma = self.dma as usize;
+                let record = cpu.ram.get(dma..dma + RECORD_SIZE);
+                let written = match (self.drives[self.disk].as_mut(), record) {
+                    (Some(drive), Some(record)) => drive.write_record(self.track, self.sector, record),
+                    _ => false,
+                };
+                cpu.a = if written { 0 } else { 1 };
+            }
+            Function::ListSt => cpu.a = 0xFF,
+            Function::SecTran => {
+                let sector = if de == 0 {
+                    bc
+                } else {
+                    cpu.ram[de.wrapping_add(bc) as usize] as u16
+                };
+                cpu.h = (sector >> 8) as u8;
+                cpu.l = sector as u8;
+            }
+        }
+
+        TrapAction::Return
+    }
+}
@@ -0,0 +1,8 @@
+// CP/M 2.2 support: either a BIOS implemented on the host side so the
+// genuine CCP and BDOS can run unmodified in 8080 memory, or a high level
+// BDOS that maps drives onto host directories
+pub mod bdos;
+pub mod bios;
+
+pub use bdos::Bdos;
+pub use bios::{Bios, CpmDrive, DiskParameterBlock};
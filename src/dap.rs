@@ -0,0 +1,806 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use crate::disasm::disassemble;
+use crate::json::Json;
+use crate::listing::Listing;
+use crate::loader::parse_address;
+use crate::machine::{Machine, Profile, Stop};
+
+// Debug Adapter Protocol server
+// Runs one program for an editor over a DAP connection (normally stdio).
+// The launch request takes:
+//
+//   program      image to load (.com, .bin, .hex or S-record)
+//   profile      machine profile; cpm for .com files, bare otherwise
+//   address      load address of binary images
+//   args         CP/M command tail
+//   input        console input, given up front
+//   listing      assembler listing mapping source lines to addresses
+//   source       the source file the listing is of (default: the listing
+//                with an .asm extension)
+//   stopOnEntry  stop before the first instruction
+//
+// There is one thread. Call frames come from watching CALL, RST and RET
+// (a frame is dropped once SP moves above its return address), so code
+// that jumps through a pushed address shows up as the frame it ran from.
+// Steps are by instruction whatever the granularity asked for.
+
+const THREAD_ID:i64 = 1;
+// Instructions run between checks for requests, e.g. pause
+const SLICE:u32 = 10_000;
+
+const REGISTERS:i64 = 1;
+const FLAGS:i64 = 2;
+const MEMORY:i64 = 3;
+const STACK:i64 = 4;
+
+// Bytes shown for each register pair in the memory scope, and stack words
+const MEMORY_ROW:u16 = 16;
+const STACK_WORDS:u16 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct CallFrame {
+    // Address of the CALL or RST
+    call_site:u16,
+    // Where the call went
+    target:u16,
+    // SP after the return address was pushed
+    stack:u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Run {
+    Continue,
+    // One instruction
+    Step,
+    // Until the call depth is back to `depth` or less
+    StepOver { depth:usize },
+    // Until the call depth is below `depth`
+    StepOut { depth:usize },
+}
+
+fn memory_reference(address:u16) -> String {
+    format!("0x{:04X}", address)
+}
+
+fn parse_reference(text:&str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => parse_address(text),
+    }
+}
+
+fn base64(bytes:&[u8]) -> String {
+    const ALPHABET:&[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let word = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(word >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn same_file(a:&Path, b:&Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.file_name().map(|name| name.to_ascii_lowercase()) == b.file_name().map(|name| name.to_ascii_lowercase()),
+    }
+}
+
+// Reads one Content-Length framed message; None at the end of the input
+fn read_message<R:BufRead>(input:&mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0u8; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    // Anything unreadable is passed on as null and ignored
+    Ok(Some(Json::parse(&String::from_utf8_lossy(&body)).unwrap_or(Json::Null)))
+}
+
+pub struct DapServer<W:Write> {
+    output:W,
+    sequence:i64,
+    machine:Option<Machine>,
+    listing:Option<Listing>,
+    source:Option<PathBuf>,
+    // Breakpoints from source lines, instruction references and function names
+    line_breakpoints:BTreeSet<u16>,
+    instruction_breakpoints:BTreeSet<u16>,
+    function_breakpoints:BTreeSet<u16>,
+    frames:Vec<CallFrame>,
+    run:Option<Run>,
+    // Instructions run since the last resume
+    executed:u64,
+    stop_on_entry:bool,
+    launched:bool,
+    configured:bool,
+    started:bool,
+    done:bool,
+}
+
+impl<W:Write> DapServer<W> {
+    pub fn new(output:W) -> Self {
+        Self {
+            output,
+            sequence: 1,
+            machine: None,
+            listing: None,
+            source: None,
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            function_breakpoints: BTreeSet::new(),
+            frames: Vec::new(),
+            run: None,
+            executed: 0,
+            stop_on_entry: false,
+            launched: false,
+            configured: false,
+            started: false,
+            done: false,
+        }
+    }
+
+    fn send(&mut self, mut message:Json) -> io::Result<()> {
+        if let Json::Object(members) = &mut message {
+            members.insert(0, ("seq".to_string(), self.sequence.into()));
+        }
+        self.sequence += 1;
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn event(&mut self, event:&str, body:Json) -> io::Result<()> {
+        self.send(Json::object([("type", "event".into()), ("event", event.into()), ("body", body)]))
+    }
+
+    fn respond(&mut self, request:&Json, result:Result<Json, String>) -> io::Result<()> {
+        let mut response = Json::object([
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("success", result.is_ok().into()),
+            ("command", request.get("command").clone()),
+        ]);
+        if let Json::Object(members) = &mut response {
+            match result {
+                Ok(Json::Null) => {}
+                Ok(body) => members.push(("body".to_string(), body)),
+                Err(message) => members.push(("message".to_string(), message.into())),
+            }
+        }
+        self.send(response)
+    }
+
+    fn console(&mut self, text:&str) -> io::Result<()> {
+        self.event("output", Json::object([("category", "console".into()), ("output", format!("{}\n", text).into())]))
+    }
+
+    fn forward_output(&mut self) -> io::Result<()> {
+        let output = self.machine.as_mut().map(|machine| machine.take_output()).unwrap_or_default();
+        if !output.is_empty() {
+            let text = String::from_utf8_lossy(&output).into_owned();
+            self.event("output", Json::object([("category", "stdout".into()), ("output", text.into())]))?;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, reason:&str, description:Option<String>) -> io::Result<()> {
+        self.run = None;
+        self.forward_output()?;
+        let mut body = Json::object([("reason", reason.into()), ("threadId", THREAD_ID.into()), ("allThreadsStopped", true.into())]);
+        if let (Json::Object(members), Some(description)) = (&mut body, description) {
+            members.push(("description".to_string(), description.clone().into()));
+            members.push(("text".to_string(), description.into()));
+        }
+        self.event("stopped", body)
+    }
+
+    fn terminated(&mut self, exit_code:i64) -> io::Result<()> {
+        self.run = None;
+        self.forward_output()?;
+        self.event("exited", Json::object([("exitCode", exit_code.into())]))?;
+        self.event("terminated", Json::object([]))
+    }
+
+    fn machine(&self) -> Result<&Machine, String> {
+        self.machine.as_ref().ok_or("no program has been launched".to_string())
+    }
+
+    fn label(&self, address:u16) -> Option<String> {
+        let listing = self.listing.as_ref()?;
+        let index = listing.labels.binary_search_by_key(&address, |(start, _)| *start).ok()?;
+        Some(listing.labels[index].1.clone())
+    }
+
+    // "NAME+3" from the listing's labels, else the address
+    fn location_name(&self, address:u16) -> String {
+        match self.listing.as_ref().and_then(|listing| listing.label(address)) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => memory_reference(address),
+        }
+    }
+
+    fn source_json(&self) -> Option<Json> {
+        let path = self.source.as_ref()?;
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Some(Json::object([("name", name.into()), ("path", path.to_string_lossy().into_owned().into())]))
+    }
+
+    fn source_line(&self, address:u16) -> Option<usize> {
+        self.source.as_ref()?;
+        self.listing.as_ref()?.line(address)
+    }
+
+    fn is_breakpoint(&self, address:u16) -> bool {
+        self.line_breakpoints.contains(&address) || self.instruction_breakpoints.contains(&address) || self.function_breakpoints.contains(&address)
+    }
+
+    fn launch(&mut self, arguments:&Json) -> Result<Json, String> {
+        let program = arguments.get("program").as_str().ok_or("launch needs a program")?;
+        let is_com = Path::new(program).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("com"));
+        let profile = match arguments.get("profile").as_str() {
+            Some(name) => Profile::from_name(name).ok_or(format!("unknown profile {}", name))?,
+            None if is_com => Profile::Cpm,
+            None => Profile::Bare,
+        };
+        let address = match arguments.get("address") {
+            Json::Null => 0,
+            Json::String(text) => parse_reference(text).ok_or(format!("bad address {}", text))?,
+            value => value.as_i64().and_then(|value| u16::try_from(value).ok()).ok_or("bad address")?,
+        };
+
+        let mut machine = Machine::new(profile);
+        machine.load(program, address, arguments.get("args").as_str().unwrap_or("")).map_err(|error| error.to_string())?;
+        if let Some(input) = arguments.get("input").as_str() {
+            machine.send_input(input.replace('\n', "\r").as_bytes());
+        }
+
+        if let Some(listing) = arguments.get("listing").as_str() {
+            self.listing = Some(Listing::from_file(listing).map_err(|error| format!("{}: {}", listing, error))?);
+            let source = match arguments.get("source").as_str() {
+                Some(source) => PathBuf::from(source),
+                None => Path::new(listing).with_extension("asm"),
+            };
+            self.source = Some(source);
+        }
+        self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.machine = Some(machine);
+        self.launched = true;
+        Ok(Json::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments:&Json) -> Json {
+        let path = arguments.get("source").get("path").as_str().map(PathBuf::from);
+        let mapped = match (&path, &self.source, &self.listing) {
+            (Some(path), Some(source), Some(listing)) if same_file(path, source) => Some(listing),
+            _ => None,
+        };
+
+        let mut addresses = BTreeSet::new();
+        let mut results = Vec::new();
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            let line = breakpoint.get("line").as_i64().unwrap_or(0).max(0) as usize;
+            match mapped.and_then(|listing| listing.address(line)) {
+                Some((line, address)) => {
+                    addresses.insert(address);
+                    results.push(Json::object([("verified", true.into()), ("line", (line as i64).into()), ("instructionReference", memory_reference(address).into())]));
+                }
+                None => {
+                    let message = if mapped.is_some() { "no code at or after this line" } else { "no listing for this source" };
+                    results.push(Json::object([("verified", false.into()), ("line", (line as i64).into()), ("message", message.into())]));
+                }
+            }
+        }
+        // Only one source has a listing, so any other file has none to set
+        if mapped.is_some() {
+            self.line_breakpoints = addresses;
+        }
+        Json::object([("breakpoints", results.into())])
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments:&Json) -> Json {
+        self.instruction_breakpoints.clear();
+        let mut results = Vec::new();
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            let reference = breakpoint.get("instructionReference").as_str().and_then(parse_reference);
+            let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+            match reference {
+                Some(reference) => {
+                    let address = reference.wrapping_add(offset as u16);
+                    self.instruction_breakpoints.insert(address);
+                    results.push(Json::object([("verified", true.into()), ("instructionReference", memory_reference(address).into())]));
+                }
+                None => results.push(Json::object([("verified", false.into()), ("message", "bad instruction reference".into())])),
+            }
+        }
+        Json::object([("breakpoints", results.into())])
+    }
+
+    fn set_function_breakpoints(&mut self, arguments:&Json) -> Json {
+        self.function_breakpoints.clear();
+        let mut results = Vec::new();
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            let name = breakpoint.get("name").as_str().unwrap_or("");
+            let label = self.listing.as_ref().and_then(|listing| listing.labels.iter().find(|(_, label)| label.eq_ignore_ascii_case(name)).map(|(address, _)| *address));
+            match label.or_else(|| parse_reference(name)) {
+                Some(address) => {
+                    self.function_breakpoints.insert(address);
+                    let mut result = Json::object([("verified", true.into()), ("instructionReference", memory_reference(address).into())]);
+                    if let (Json::Object(members), Some(source), Some(line)) = (&mut result, self.source_json(), self.source_line(address)) {
+                        members.push(("source".to_string(), source));
+                        members.push(("line".to_string(), (line as i64).into()));
+                    }
+                    results.push(result);
+                }
+                None => results.push(Json::object([("verified", false.into()), ("message", format!("no label {}", name).into())])),
+            }
+        }
+        Json::object([("breakpoints", results.into())])
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let machine = self.machine()?;
+        // Each frame's location, innermost first: PC, then the call sites
+        let locations = std::iter::once(machine.cpu.pc).chain(self.frames.iter().rev().map(|frame| frame.call_site));
+        // and the routine each one is in, as far as the calls tell
+        let functions = self.frames.iter().rev().map(|frame| Some(frame.target)).chain(std::iter::repeat(None));
+        let mut frames = Vec::new();
+        for (index, (address, function)) in locations.zip(functions).enumerate() {
+            let name = match (self.listing.as_ref().and_then(|listing| listing.label(address)), function) {
+                (None, Some(function)) => memory_reference(function),
+                _ => self.location_name(address),
+            };
+            let line = self.source_line(address);
+            let mut frame = Json::object([
+                ("id", (index as i64).into()),
+                ("name", name.into()),
+                ("line", (line.unwrap_or(0) as i64).into()),
+                ("column", (line.is_some() as i64).into()),
+                ("instructionPointerReference", memory_reference(address).into()),
+            ]);
+            if let (Json::Object(members), Some(source), Some(_)) = (&mut frame, self.source_json(), line) {
+                members.push(("source".to_string(), source));
+            }
+            frames.push(frame);
+        }
+        let total = frames.len() as i64;
+        Ok(Json::object([("stackFrames", frames.into()), ("totalFrames", total.into())]))
+    }
+
+    fn scopes(&self) -> Json {
+        let scope = |name:&str, reference:i64| Json::object([("name", name.into()), ("variablesReference", reference.into()), ("expensive", false.into())]);
+        Json::object([("scopes", vec![scope("Registers", REGISTERS), scope("Flags", FLAGS), scope("Memory", MEMORY)].into())])
+    }
+
+    fn variables(&self, reference:i64) -> Result<Json, String> {
+        let cpu = &self.machine()?.cpu;
+        let registers = cpu.registers();
+        let variable = |name:&str, value:String, memory:Option<u16>, children:i64| {
+            let mut variable = Json::object([("name", name.into()), ("value", value.into()), ("variablesReference", children.into())]);
+            if let (Json::Object(members), Some(address)) = (&mut variable, memory) {
+                members.push(("memoryReference".to_string(), memory_reference(address).into()));
+            }
+            variable
+        };
+        let byte = |name:&str, value:u8| variable(name, format!("0x{:02X}", value), None, 0);
+        let pair = |name:&str, value:u16| variable(name, memory_reference(value), Some(value), 0);
+        let flag = |name:&str, mask:u8| variable(name, ((registers.flags & mask != 0) as u8).to_string(), None, 0);
+        let row = |address:u16, length:u16| (0..length).map(|offset| format!("{:02X}", cpu.ram[address.wrapping_add(offset) as usize])).collect::<Vec<_>>().join(" ");
+        let word = |address:u16| cpu.ram[address as usize] as u16 | (cpu.ram[address.wrapping_add(1) as usize] as u16) << 8;
+
+        let variables = match reference {
+            REGISTERS => vec![
+                byte("A", registers.a),
+                byte("B", registers.b),
+                byte("C", registers.c),
+                byte("D", registers.d),
+                byte("E", registers.e),
+                byte("H", registers.h),
+                byte("L", registers.l),
+                pair("BC", registers.bc()),
+                pair("DE", registers.de()),
+                pair("HL", registers.hl()),
+                pair("SP", registers.sp),
+                pair("PC", cpu.pc),
+            ],
+            FLAGS => vec![
+                byte("F", registers.flags),
+                flag("S", 0x80),
+                flag("Z", 0x40),
+                flag("AC", 0x10),
+                flag("P", 0x04),
+                flag("CY", 0x01),
+                variable("IE", (cpu.int_enabled as u8).to_string(), None, 0),
+            ],
+            MEMORY => vec![
+                variable("(BC)", row(registers.bc(), MEMORY_ROW), Some(registers.bc()), 0),
+                variable("(DE)", row(registers.de(), MEMORY_ROW), Some(registers.de()), 0),
+                variable("(HL)", row(registers.hl(), MEMORY_ROW), Some(registers.hl()), 0),
+                variable("Stack", memory_reference(registers.sp), Some(registers.sp), STACK),
+            ],
+            STACK => (0..STACK_WORDS)
+                .map(|index| {
+                    let address = registers.sp.wrapping_add(index * 2);
+                    pair(&format!("SP+{}", index * 2), word(address))
+                })
+                .collect(),
+            _ => return Err(format!("no variables {}", reference)),
+        };
+        Ok(Json::object([("variables", variables.into())]))
+    }
+
+    fn set_variable(&mut self, arguments:&Json) -> Result<Json, String> {
+        let name = arguments.get("name").as_str().unwrap_or("").to_ascii_uppercase();
+        let text = arguments.get("value").as_str().unwrap_or("").trim();
+        let value = parse_reference(text).ok_or(format!("bad value {}", text))?;
+        let machine = self.machine.as_mut().ok_or("no program has been launched")?;
+        let cpu = &mut machine.cpu;
+        let mut registers = cpu.registers();
+        let flag = |mask:u8| match value {
+            0 => Ok(registers.flags & !mask),
+            1 => Ok(registers.flags | mask),
+            _ => Err("flags are 0 or 1".to_string()),
+        };
+
+        match (arguments.get("variablesReference").as_i64(), name.as_str()) {
+            (Some(REGISTERS), "A") => registers.a = value as u8,
+            (Some(REGISTERS), "B") => registers.b = value as u8,
+            (Some(REGISTERS), "C") => registers.c = value as u8,
+            (Some(REGISTERS), "D") => registers.d = value as u8,
+            (Some(REGISTERS), "E") => registers.e = value as u8,
+            (Some(REGISTERS), "H") => registers.h = value as u8,
+            (Some(REGISTERS), "L") => registers.l = value as u8,
+            (Some(REGISTERS), "BC") => registers.set_bc(value),
+            (Some(REGISTERS), "DE") => registers.set_de(value),
+            (Some(REGISTERS), "HL") => registers.set_hl(value),
+            (Some(REGISTERS), "SP") => registers.sp = value,
+            (Some(REGISTERS), "PC") => {
+                cpu.pc = value;
+                cpu.halted = false;
+            }
+            (Some(FLAGS), "F") => registers.flags = value as u8,
+            (Some(FLAGS), "S") => registers.flags = flag(0x80)?,
+            (Some(FLAGS), "Z") => registers.flags = flag(0x40)?,
+            (Some(FLAGS), "AC") => registers.flags = flag(0x10)?,
+            (Some(FLAGS), "P") => registers.flags = flag(0x04)?,
+            (Some(FLAGS), "CY") => registers.flags = flag(0x01)?,
+            (Some(FLAGS), "IE") => cpu.int_enabled = value != 0,
+            _ => return Err(format!("{} can't be set", name)),
+        }
+        cpu.set_registers(&registers);
+
+        let shown = match name.as_str() {
+            "A" | "B" | "C" | "D" | "E" | "H" | "L" | "F" => format!("0x{:02X}", value as u8),
+            "BC" | "DE" | "HL" | "SP" | "PC" => memory_reference(value),
+            _ => ((value != 0) as u8).to_string(),
+        };
+        Ok(Json::object([("value", shown.into())]))
+    }
+
+    fn read_memory(&self, arguments:&Json) -> Result<Json, String> {
+        let cpu = &self.machine()?.cpu;
+        let reference = arguments.get("memoryReference").as_str().and_then(parse_reference).ok_or("bad memory reference")?;
+        let start = (reference as i64).saturating_add(arguments.get("offset").as_i64().unwrap_or(0)).clamp(0, 0x10000) as usize;
+        let count = (arguments.get("count").as_i64().unwrap_or(0).max(0) as usize).min(0x10000 - start);
+        let bytes = &cpu.ram[start..start + count];
+        Ok(Json::object([("address", memory_reference(start as u16).into()), ("data", base64(bytes).into())]))
+    }
+
+    fn disassemble(&self, arguments:&Json) -> Result<Json, String> {
+        let cpu = &self.machine()?.cpu;
+        let reference = arguments.get("memoryReference").as_str().and_then(parse_reference).ok_or("bad memory reference")?;
+        let base = reference.wrapping_add(arguments.get("offset").as_i64().unwrap_or(0) as u16);
+        // There are never more than 64K instructions to show or skip
+        let offset = arguments.get("instructionOffset").as_i64().unwrap_or(0).clamp(-0x10000, 0x10000);
+        let count = arguments.get("instructionCount").as_i64().unwrap_or(0).clamp(0, 0x10000) as usize;
+        let length = |address:u16| disassemble(&cpu.ram, address).length;
+
+        // Going backwards, decode forwards from far enough back that the
+        // instruction boundaries have usually fallen into place by `base`
+        let mut addresses:Vec<Option<u16>> = Vec::new();
+        if offset < 0 {
+            let back = offset.unsigned_abs() as usize;
+            let mut address = (base as usize).saturating_sub(back * 3) as u16;
+            let mut before = Vec::new();
+            while address < base {
+                before.push(Some(address));
+                address = address.saturating_add(length(address));
+            }
+            let skip = before.len().saturating_sub(back);
+            addresses.extend(std::iter::repeat_n(None, back.saturating_sub(before.len())));
+            addresses.extend(&before[skip..]);
+        }
+        let mut address = base;
+        for _ in 0..offset.max(0) {
+            address = address.wrapping_add(length(address));
+        }
+        while addresses.len() < count {
+            addresses.push(Some(address));
+            address = address.wrapping_add(length(address));
+        }
+        addresses.truncate(count);
+
+        let mut instructions = Vec::new();
+        for address in addresses {
+            let Some(address) = address else {
+                instructions.push(Json::object([("address", "0x0000".into()), ("instruction", "".into()), ("presentationHint", "invalid".into())]));
+                continue;
+            };
+            let instruction = disassemble(&cpu.ram, address);
+            let bytes:Vec<String> = (0..instruction.length).map(|offset| format!("{:02X}", cpu.ram[address.wrapping_add(offset) as usize])).collect();
+            let mut entry = Json::object([
+                ("address", memory_reference(address).into()),
+                ("instructionBytes", bytes.join(" ").into()),
+                ("instruction", instruction.format(|target| self.label(target)).into()),
+            ]);
+            if let Json::Object(members) = &mut entry {
+                if let Some(label) = self.label(address) {
+                    members.push(("symbol".to_string(), label.into()));
+                }
+                if let (Some(source), Some(line)) = (self.source_json(), self.source_line(address)) {
+                    members.push(("location".to_string(), source));
+                    members.push(("line".to_string(), (line as i64).into()));
+                }
+            }
+            instructions.push(entry);
+        }
+        Ok(Json::object([("instructions", instructions.into())]))
+    }
+
+    fn evaluate(&self, arguments:&Json) -> Result<Json, String> {
+        let expression = arguments.get("expression").as_str().unwrap_or("").trim();
+        let cpu = &self.machine()?.cpu;
+        let registers = cpu.registers();
+        let value = match expression.to_ascii_uppercase().as_str() {
+            "A" => registers.a as u16,
+            "B" => registers.b as u16,
+            "C" => registers.c as u16,
+            "D" => registers.d as u16,
+            "E" => registers.e as u16,
+            "H" => registers.h as u16,
+            "L" => registers.l as u16,
+            "F" => registers.flags as u16,
+            "BC" => registers.bc(),
+            "DE" => registers.de(),
+            "HL" => registers.hl(),
+            "SP" => registers.sp,
+            "PC" => cpu.pc,
+            name => {
+                let label = self.listing.as_ref().and_then(|listing| listing.labels.iter().find(|(_, label)| label.eq_ignore_ascii_case(name)).map(|(address, _)| *address));
+                label.or_else(|| parse_reference(expression)).ok_or(format!("can't evaluate {}", expression))?
+            }
+        };
+        Ok(Json::object([
+            ("result", format!("{} ({})", memory_reference(value), value).into()),
+            ("variablesReference", 0.into()),
+            ("memoryReference", memory_reference(value).into()),
+        ]))
+    }
+
+    fn resume(&mut self, run:Run) -> Result<Json, String> {
+        self.machine()?;
+        self.run = Some(run);
+        self.executed = 0;
+        Ok(Json::object([("allThreadsContinued", true.into())]))
+    }
+
+    fn step_kind(&self, over:bool) -> Run {
+        if over {
+            Run::StepOver { depth: self.frames.len() }
+        } else {
+            Run::Step
+        }
+    }
+
+    fn handle(&mut self, request:&Json) -> io::Result<()> {
+        if request.get("type").as_str() != Some("request") {
+            return Ok(());
+        }
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        let arguments = request.get("arguments");
+
+        let result = match command.as_str() {
+            "initialize" => Ok(Json::object([
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsFunctionBreakpoints", true.into()),
+                ("supportsSetVariable", true.into()),
+                ("supportsReadMemoryRequest", true.into()),
+                ("supportsDisassembleRequest", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsSteppingGranularity", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => self.launch(arguments),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(arguments)),
+            "setExceptionBreakpoints" => Ok(Json::Null),
+            "threads" => Ok(Json::object([("threads", vec![Json::object([("id", THREAD_ID.into()), ("name", "8080".into())])].into())])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(self.scopes()),
+            "variables" => self.variables(arguments.get("variablesReference").as_i64().unwrap_or(0)),
+            "setVariable" => self.set_variable(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => self.resume(Run::Continue),
+            "next" => self.resume(self.step_kind(true)),
+            "stepIn" => self.resume(self.step_kind(false)),
+            "stepOut" => self.resume(Run::StepOut { depth: self.frames.len() }),
+            "pause" => Ok(Json::Null),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("{} is not supported", command)),
+        };
+        let succeeded = result.is_ok();
+        self.respond(request, result)?;
+
+        match command.as_str() {
+            "initialize" => self.event("initialized", Json::object([]))?,
+            "launch" | "configurationDone" if succeeded => self.start()?,
+            "pause" if self.run.is_some() => self.stopped("pause", None)?,
+            "terminate" => self.event("terminated", Json::object([]))?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Starts the program once it's loaded and the editor has sent its
+    // breakpoints
+    fn start(&mut self) -> io::Result<()> {
+        if !self.launched || !self.configured || self.started {
+            return Ok(());
+        }
+        self.started = true;
+        if self.stop_on_entry {
+            self.stopped("entry", None)
+        } else {
+            self.run = Some(Run::Continue);
+            self.executed = 0;
+            Ok(())
+        }
+    }
+
+    // Runs one instruction, keeping the call frames up to date. Returns the
+    // fault message if the emulator panicked.
+    fn step_machine(&mut self) -> Result<(), String> {
+        let Some(machine) = self.machine.as_mut() else {
+            return Ok(());
+        };
+        let (pc, sp) = (machine.cpu.pc, machine.cpu.sp);
+        let op = machine.cpu.ram[pc as usize];
+        let call = matches!(op, 0xCD | 0xDD | 0xED | 0xFD) || op & 0xC7 == 0xC4 || op & 0xC7 == 0xC7;
+
+        if let Err(fault) = panic::catch_unwind(AssertUnwindSafe(|| machine.step())) {
+            let message = fault.downcast_ref::<String>().cloned().or_else(|| fault.downcast_ref::<&str>().map(|message| message.to_string()));
+            return Err(message.unwrap_or_else(|| "emulator fault".to_string()));
+        }
+
+        let cpu = &machine.cpu;
+        // Above the frame, allowing for the stack wrapping past 0xFFFF
+        while self.frames.last().is_some_and(|frame| cpu.sp.wrapping_sub(frame.stack) as i16 > 0) {
+            self.frames.pop();
+        }
+        // A conditional call that isn't taken leaves SP alone
+        if call && cpu.sp == sp.wrapping_sub(2) {
+            self.frames.push(CallFrame { call_site: pc, target: cpu.pc, stack: cpu.sp });
+        }
+        self.executed += 1;
+        Ok(())
+    }
+
+    // Runs the current resume for a while. Stops with an event when done.
+    fn run_slice(&mut self) -> io::Result<()> {
+        for _ in 0..SLICE {
+            let Some(run) = self.run else {
+                return Ok(());
+            };
+            let Some(machine) = self.machine.as_ref() else {
+                self.run = None;
+                return Ok(());
+            };
+
+            match machine.stop_reason() {
+                Some(Stop::Exited) => return self.terminated(0),
+                Some(Stop::Halted) => return self.stopped("pause", Some("Halted".to_string())),
+                Some(Stop::Stopped) => return self.stopped("pause", Some("Stopped".to_string())),
+                Some(Stop::WaitingForInput) => {
+                    self.console("waiting for console input; give it with the launch input argument")?;
+                    return self.stopped("pause", Some("Waiting for input".to_string()));
+                }
+                None => {}
+            }
+
+            if self.executed > 0 {
+                let depth = self.frames.len();
+                let finished = match run {
+                    Run::Continue => false,
+                    Run::Step => true,
+                    Run::StepOver { depth: target } => depth <= target,
+                    Run::StepOut { depth: target } => depth < target,
+                };
+                if finished {
+                    return self.stopped("step", None);
+                }
+                if self.is_breakpoint(machine.cpu.pc) {
+                    let reason = if self.line_breakpoints.contains(&machine.cpu.pc) { "breakpoint" } else if self.function_breakpoints.contains(&machine.cpu.pc) { "function breakpoint" } else { "instruction breakpoint" };
+                    return self.stopped(reason, None);
+                }
+            }
+
+            if let Err(message) = self.step_machine() {
+                self.console(&format!("fault: {}", message))?;
+                return self.stopped("exception", Some(message));
+            }
+        }
+        self.forward_output()
+    }
+
+    // Serves requests until the editor disconnects or the input ends
+    pub fn serve<R:BufRead + Send + 'static>(&mut self, mut input:R) -> io::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        while !self.done {
+            let message = if self.run.is_some() {
+                match receiver.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                }
+            };
+            match message {
+                Some(message) => self.handle(&message)?,
+                None => self.run_slice()?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+
+use crate::io::{cycles_since, IoDevice};
+
+// Intel 8251 USART
+// Port offset 0 is the data register, offset 1 is control (write) / status (read),
+// matching the usual wiring of C/D to A0.
+
+// Status register bits
+pub const STATUS_TXRDY:u8 = 0x01;
+pub const STATUS_RXRDY:u8 = 0x02;
+pub const STATUS_TXE:u8 = 0x04;
+pub const STATUS_PE:u8 = 0x08;
+pub const STATUS_OE:u8 = 0x10;
+pub const STATUS_FE:u8 = 0x20;
+pub const STATUS_SYNDET:u8 = 0x40;
+pub const STATUS_DSR:u8 = 0x80;
+
+// Command word bits
+const CMD_TXEN:u8 = 0x01;
+const CMD_DTR:u8 = 0x02;
+const CMD_RXE:u8 = 0x04;
+const CMD_SBRK:u8 = 0x08;
+const CMD_ER:u8 = 0x10;
+const CMD_RTS:u8 = 0x20;
+const CMD_IR:u8 = 0x40;
+const CMD_EH:u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ControlState {
+    Mode,
+    Sync1,
+    Sync2,
+    Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mode {
+    pub synchronous:bool,
+    pub baud_factor:u32, // 1, 16 or 64 (1 in sync mode)
+    pub char_bits:u8,    // 5 to 8
+    pub parity:bool,
+    pub even_parity:bool,
+    pub stop_half_bits:u8, // 2 = 1 stop bit, 3 = 1.5, 4 = 2
+    pub single_sync:bool,
+}
+
+impl Mode {
+    pub fn from_byte(value:u8) -> Self {
+        let synchronous = value & 0x03 == 0;
+        Self {
+            synchronous,
+            baud_factor: match value & 0x03 {
+                2 => 16,
+                3 => 64,
+                _ => 1,
+            },
+            char_bits: 5 + ((value >> 2) & 0x03),
+            parity: value & 0x10 != 0,
+            even_parity: value & 0x20 != 0,
+            stop_half_bits: match value >> 6 {
+                _ if synchronous => 0,
+                2 => 3,
+                3 => 4,
+                _ => 2,
+            },
+            single_sync: value & 0x80 != 0,
+        }
+    }
+
+    // Length of one character on the line, in half bit times
+    fn frame_half_bits(&self) -> u32 {
+        let data = self.char_bits as u32 + self.parity as u32;
+        if self.synchronous {
+            data * 2
+        } else {
+            (1 + data) * 2 + self.stop_half_bits as u32
+        }
+    }
+}
+
+pub struct Usart8251 {
+    cpu_hz:u32,
+    clock_hz:u32, // TxC/RxC input, 0 = characters move instantly
+
+    state:ControlState,
+    pub mode:Mode,
+    pub command:u8,
+    pub sync_chars:[u8; 2],
+
+    tx_buffer:Option<u8>,
+    tx_shift:Option<u8>,
+    tx_done:u32,
+
+    rx_data:u8,
+    rx_ready:bool,
+    rx_next:u32,
+    overrun:bool,
+    now:u32, // cycle counter at the last update
+
+    pub dsr:bool, // state of the DSR input, reported in status bit 7
+    pub cts:bool,
+
+    to_host:VecDeque<u8>,
+    from_host:VecDeque<u8>,
+}
+
+impl Usart8251 {
+    // `cpu_hz` is the CPU clock, `clock_hz` the frequency fed to TxC/RxC.
+    // The baud rate is clock_hz divided by the factor set in the mode word.
+    pub fn new(cpu_hz:u32, clock_hz:u32) -> Self {
+        Self {
+            cpu_hz,
+            clock_hz,
+            state: ControlState::Mode,
+            mode: Mode::from_byte(0x4E),
+            command: 0,
+            sync_chars: [0; 2],
+            tx_buffer: None,
+            tx_shift: None,
+            tx_done: 0,
+            rx_data: 0,
+            rx_ready: false,
+            rx_next: 0,
+            overrun: false,
+            now: 0,
+            dsr: true,
+            cts: true,
+            to_host: VecDeque::new(),
+            from_host: VecDeque::new(),
+        }
+    }
+
+    // A USART that moves characters without any line delay
+    pub fn instant() -> Self {
+        Self::new(0, 0)
+    }
+
+    pub fn reset(&mut self) {
+        self.state = ControlState::Mode;
+        self.command = 0;
+        self.tx_buffer = None;
+        self.tx_shift = None;
+        self.rx_ready = false;
+        self.overrun = false;
+    }
+
+    // CPU cycles needed to move one character over the line
+    pub fn char_cycles(&self) -> u32 {
+        if self.clock_hz == 0 {
+            return 0;
+        }
+        let half_bits = self.mode.frame_half_bits() as u64;
+        let cycles = self.cpu_hz as u64 * self.mode.baud_factor as u64 * half_bits / (2 * self.clock_hz as u64);
+        cycles as u32
+    }
+
+    fn char_mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.mode.char_bits)) as u8
+    }
+
+    fn tx_enabled(&self) -> bool {
+        self.command & CMD_TXEN != 0 && self.cts
+    }
+
+    fn rx_enabled(&self) -> bool {
+        self.command & CMD_RXE != 0
+    }
+
+    // Bring the transmitter and receiver up to date with the cycle counter
+    pub fn update(&mut self, cycles:u32) {
+        let char_cycles = self.char_cycles();
+
+        loop {
+            if let Some(byte) = self.tx_shift {
+                if cycles_since(cycles, self.tx_done) < 0 {
+                    break;
+                }
+                self.to_host.push_back(byte);
+                self.tx_shift = None;
+            }
+            if !self.tx_enabled() {
+                break;
+            }
+            match self.tx_buffer.take() {
+                Some(byte) => {
+                    // Back to back characters start when the previous one ends
+                    let start = if cycles_since(cycles, self.tx_done) > char_cycles as i32 {
+                        cycles
+                    } else {
+                        self.tx_done
+                    };
+                    self.tx_shift = Some(byte);
+                    self.tx_done = start.wrapping_add(char_cycles);
+                }
+                None => break,
+            }
+        }
+
+        // Characters arrive back to back at line speed whether or not the
+        // program has read the last one, and one landing on an unread
+        // character overruns it. Without a line clock the host waits for
+        // each character to be read instead.
+        while self.rx_enabled() && cycles_since(cycles, self.rx_next) >= 0 {
+            if self.rx_ready && char_cycles == 0 {
+                break;
+            }
+            let Some(byte) = self.from_host.pop_front() else {
+                break;
+            };
+            if self.rx_ready {
+                self.overrun = true;
+            }
+            self.rx_data = byte & self.char_mask();
+            self.rx_ready = true;
+            self.rx_next = self.rx_next.wrapping_add(char_cycles);
+        }
+        self.now = cycles;
+    }
+
+    pub fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.tx_buffer.is_none() {
+            status |= STATUS_TXRDY;
+        }
+        if self.rx_ready {
+            status |= STATUS_RXRDY;
+        }
+        if self.tx_buffer.is_none() && self.tx_shift.is_none() {
+            status |= STATUS_TXE;
+        }
+        if self.overrun {
+            status |= STATUS_OE;
+        }
+        if self.dsr {
+            status |= STATUS_DSR;
+        }
+        status
+    }
+
+    // State of the TxRDY and RxRDY output pins, for wiring to interrupts
+    pub fn txrdy_pin(&self) -> bool {
+        self.tx_buffer.is_none() && self.tx_enabled()
+    }
+
+    pub fn rxrdy_pin(&self) -> bool {
+        self.rx_ready
+    }
+
+    pub fn dtr(&self) -> bool {
+        self.command & CMD_DTR != 0
+    }
+
+    pub fn rts(&self) -> bool {
+        self.command & CMD_RTS != 0
+    }
+
+    pub fn sending_break(&self) -> bool {
+        self.command & CMD_SBRK != 0
+    }
+
+    pub fn hunt_mode(&self) -> bool {
+        self.command & CMD_EH != 0
+    }
+
+    // Host side: queue bytes for the 8080 program to receive. On an idle
+    // line the first one starts arriving at the last update.
+    pub fn send(&mut self, byte:u8) {
+        self.send_bytes(&[byte]);
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        if self.from_host.is_empty() && cycles_since(self.now, self.rx_next) >= 0 {
+            self.rx_next = self.now.wrapping_add(self.char_cycles());
+        }
+        self.from_host.extend(bytes);
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.from_host.len()
+    }
+
+    // Host side: take a byte the 8080 program has transmitted
+    pub fn receive(&mut self) -> Option<u8> {
+        self.to_host.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.to_host.drain(..).collect()
+    }
+
+    fn write_control(&mut self, value:u8) {
+        match self.state {
+            ControlState::Mode => {
+                self.mode = Mode::from_byte(value);
+                self.state = if self.mode.synchronous {
+                    ControlState::Sync1
+                } else {
+                    ControlState::Command
+                };
+            }
+            ControlState::Sync1 => {
+                self.sync_chars[0] = value;
+                self.state = if self.mode.single_sync {
+                    ControlState::Command
+                } else {
+                    ControlState::Sync2
+                };
+            }
+            ControlState::Sync2 => {
+                self.sync_chars[1] = value;
+                self.state = ControlState::Command;
+            }
+            ControlState::Command => {
+                if value & CMD_IR != 0 {
+                    self.reset();
+                    return;
+                }
+                if value & CMD_ER != 0 {
+                    self.overrun = false;
+                }
+                self.command = value & !(CMD_ER | CMD_IR);
+            }
+        }
+    }
+}
+
+impl IoDevice for Usart8251 {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        self.update(cycles);
+        match port & 1 {
+            0 => {
+                self.rx_ready = false;
+                self.rx_data
+            }
+            _ => self.status(),
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, cycles:u32) {
+        self.update(cycles);
+        match port & 1 {
+            0 => {
+                // Writing over a full buffer loses the old character
+                self.tx_buffer = Some(value & self.char_mask());
+            }
+            _ => self.write_control(value),
+        }
+        self.update(cycles);
+    }
+}
@@ -0,0 +1,533 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interrupt::{rst, InterruptLine, InterruptSource};
+use crate::io::{cycles_since, IoDevice};
+
+// Intel 8253/8254 programmable interval timer
+// Port offsets 0-2 are counters 0-2, offset 3 is the control word register.
+// Each counter's CLK input is derived from the CPU cycle counter at its own
+// frequency; GATE inputs are set by the host and OUT pins can be read back or
+// wired to the CPU interrupt input with `TimerInterrupt`.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Access {
+    Latch,
+    Lsb,
+    Msb,
+    LsbMsb,
+}
+
+pub struct Counter {
+    pub mode:u8,
+    pub bcd:bool,
+    access:Access,
+
+    clk_hz:u32,
+    clk_remainder:u64,
+
+    cr:u16, // count register as written
+    write_msb_next:bool,
+    null_count:bool,
+    has_count:bool, // a count has been written since the control word
+    ce:u32, // counting element, always binary internally
+    armed:bool, // count has been loaded and is running
+    load_pending:bool, // load CR into CE on next CLK
+    terminal:bool, // one-shot modes have reached terminal count
+    half_left:u32, // mode 3, pulses left in the current half period
+    strobe:bool, // OUT is low for a single CLK (modes 2, 4, 5)
+
+    latch:Option<u16>,
+    status_latch:Option<u8>,
+    read_msb_next:bool,
+
+    gate:bool,
+    out:bool,
+    rising_edges:u32,
+}
+
+impl Counter {
+    fn new(clk_hz:u32) -> Self {
+        Self {
+            mode: 0,
+            bcd: false,
+            access: Access::LsbMsb,
+            clk_hz,
+            clk_remainder: 0,
+            cr: 0,
+            write_msb_next: false,
+            null_count: true,
+            has_count: false,
+            ce: 0,
+            armed: false,
+            load_pending: false,
+            terminal: false,
+            half_left: 0,
+            strobe: false,
+            latch: None,
+            status_latch: None,
+            read_msb_next: false,
+            gate: true,
+            out: false,
+            rising_edges: 0,
+        }
+    }
+
+    fn modulus(&self) -> u32 {
+        if self.bcd { 10000 } else { 65536 }
+    }
+
+    // Initial count from the count register, 0 meaning the full range
+    fn initial(&self) -> u32 {
+        let value = if self.bcd { bcd_to_bin(self.cr) } else { self.cr as u32 };
+        if value == 0 { self.modulus() } else { value }
+    }
+
+    // Pulses until CE counts down to zero
+    fn remaining(&self) -> u32 {
+        if self.ce == 0 { self.modulus() } else { self.ce }
+    }
+
+    fn set_out(&mut self, level:bool) {
+        if level && !self.out {
+            self.rising_edges += 1;
+        }
+        self.out = level;
+    }
+
+    fn decrement(&mut self, pulses:u32) {
+        let modulus = self.modulus();
+        self.ce = (self.ce + modulus - pulses % modulus) % modulus;
+    }
+
+    fn write_control(&mut self, value:u8) {
+        let access = match (value >> 4) & 0x03 {
+            0 => Access::Latch,
+            1 => Access::Lsb,
+            2 => Access::Msb,
+            _ => Access::LsbMsb,
+        };
+
+        if access == Access::Latch {
+            self.latch_count();
+            return;
+        }
+
+        self.access = access;
+        self.mode = match (value >> 1) & 0x07 {
+            6 => 2,
+            7 => 3,
+            mode => mode,
+        };
+        self.bcd = value & 0x01 != 0;
+        self.write_msb_next = false;
+        self.read_msb_next = false;
+        self.latch = None;
+        self.status_latch = None;
+        self.armed = false;
+        self.load_pending = false;
+        self.strobe = false;
+        self.null_count = true;
+        self.has_count = false;
+        self.out = self.mode != 0;
+    }
+
+    fn latch_count(&mut self) {
+        if self.latch.is_none() {
+            self.latch = Some(self.current());
+        }
+    }
+
+    fn latch_status(&mut self) {
+        if self.status_latch.is_none() {
+            let rw = match self.access {
+                Access::Latch => 0,
+                Access::Lsb => 1,
+                Access::Msb => 2,
+                Access::LsbMsb => 3,
+            };
+            let status = (self.out as u8) << 7 | (self.null_count as u8) << 6 | rw << 4 | self.mode << 1 | self.bcd as u8;
+            self.status_latch = Some(status);
+        }
+    }
+
+    // Current count as the program would read it
+    fn current(&self) -> u16 {
+        let value = if self.mode == 3 && self.armed {
+            (self.half_left * 2) % self.modulus()
+        } else {
+            self.ce
+        };
+        if self.bcd { bin_to_bcd(value) } else { value as u16 }
+    }
+
+    fn write_count(&mut self, value:u8) {
+        match self.access {
+            Access::Lsb => self.cr = value as u16,
+            Access::Msb => self.cr = (value as u16) << 8,
+            _ => {
+                if self.write_msb_next {
+                    self.cr = (self.cr & 0x00FF) | (value as u16) << 8;
+                } else {
+                    self.cr = (self.cr & 0xFF00) | value as u16;
+                    self.write_msb_next = true;
+                    // Mode 0 stops counting between the two bytes
+                    if self.mode == 0 {
+                        self.set_out(false);
+                        self.armed = false;
+                    }
+                    return;
+                }
+                self.write_msb_next = false;
+            }
+        }
+        self.null_count = true;
+        self.has_count = true;
+
+        match self.mode {
+            0 => {
+                self.set_out(false);
+                self.load_pending = true;
+            }
+            4 => self.load_pending = true,
+            // Rate and square wave generators pick the new count up at the
+            // end of the current period
+            2 | 3 if !self.armed => self.load_pending = true,
+            // Modes 1 and 5 wait for a trigger on GATE
+            _ => {}
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if let Some(status) = self.status_latch.take() {
+            return status;
+        }
+
+        let value = self.latch.unwrap_or_else(|| self.current());
+        let byte = match self.access {
+            Access::Msb => (value >> 8) as u8,
+            Access::LsbMsb if self.read_msb_next => (value >> 8) as u8,
+            _ => value as u8,
+        };
+
+        let done = match self.access {
+            Access::LsbMsb => {
+                self.read_msb_next = !self.read_msb_next;
+                !self.read_msb_next
+            }
+            _ => true,
+        };
+        if done {
+            self.latch = None;
+        }
+        byte
+    }
+
+    fn set_gate(&mut self, level:bool) {
+        let rising = level && !self.gate;
+        self.gate = level;
+
+        match self.mode {
+            1 | 5 if rising && self.has_count => self.load_pending = true,
+            2 | 3 => {
+                if !level {
+                    self.set_out(true);
+                    self.strobe = false;
+                } else if rising && self.has_count {
+                    self.load_pending = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn load(&mut self) {
+        self.null_count = false;
+        self.armed = true;
+        self.terminal = false;
+        self.strobe = false;
+        match self.mode {
+            1 => {
+                self.ce = self.initial() % self.modulus();
+                self.set_out(false);
+            }
+            2 => {
+                self.ce = self.initial() % self.modulus();
+                self.set_out(true);
+            }
+            3 => {
+                self.half_left = self.initial().div_ceil(2);
+                self.set_out(true);
+            }
+            _ => self.ce = self.initial() % self.modulus(),
+        }
+    }
+
+    // Applies `pulses` CLK pulses
+    fn clock(&mut self, mut pulses:u32) {
+        while pulses > 0 {
+            if self.load_pending {
+                self.load_pending = false;
+                self.load();
+                pulses -= 1;
+                continue;
+            }
+
+            if self.strobe {
+                // End of the single clock low pulse
+                self.strobe = false;
+                self.set_out(true);
+                if self.mode == 2 {
+                    self.ce = self.initial() % self.modulus();
+                    self.null_count = false;
+                } else {
+                    self.decrement(1);
+                }
+                pulses -= 1;
+                continue;
+            }
+
+            let counting = match self.mode {
+                0 | 2 | 3 | 4 => self.gate,
+                _ => true,
+            };
+            if !self.armed || !counting {
+                return;
+            }
+            if self.terminal {
+                // One-shot modes keep counting down without further effect
+                self.decrement(pulses);
+                return;
+            }
+
+            match self.mode {
+                // Interrupt on terminal count / hardware one-shot
+                0 | 1 => {
+                    let step = pulses.min(self.remaining());
+                    self.decrement(step);
+                    pulses -= step;
+                    if self.ce == 0 {
+                        self.terminal = true;
+                        self.set_out(true);
+                    }
+                }
+                // Rate generator, OUT low while CE is 1
+                2 => {
+                    let period = self.initial();
+                    if pulses >= period && self.ce != 1 {
+                        // Whole periods leave the counter where it was
+                        self.rising_edges += pulses / period;
+                        pulses %= period;
+                        continue;
+                    }
+                    let step = pulses.min(self.remaining() - 1);
+                    self.decrement(step);
+                    pulses -= step;
+                    if self.ce == 1 {
+                        self.set_out(false);
+                        self.strobe = true;
+                    }
+                }
+                // Square wave, high for ceil(N/2) and low for floor(N/2)
+                3 => {
+                    let period = self.initial();
+                    if pulses >= period && period > 1 {
+                        self.rising_edges += pulses / period;
+                        pulses %= period;
+                        continue;
+                    }
+                    let step = pulses.min(self.half_left);
+                    self.half_left -= step;
+                    pulses -= step;
+                    if self.half_left == 0 {
+                        let level = !self.out;
+                        self.set_out(level);
+                        self.half_left = if level { period.div_ceil(2) } else { (period / 2).max(1) };
+                    }
+                }
+                // Software / hardware triggered strobe
+                _ => {
+                    let step = pulses.min(self.remaining());
+                    self.decrement(step);
+                    pulses -= step;
+                    if self.ce == 0 {
+                        self.terminal = true;
+                        self.set_out(false);
+                        self.strobe = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct Pit8253 {
+    cpu_hz:u32,
+    last_cycles:Option<u32>,
+    pub counters:[Counter; 3],
+}
+
+impl Pit8253 {
+    // All three counters are clocked at `clk_hz`, see `set_clock` to change one
+    pub fn new(cpu_hz:u32, clk_hz:u32) -> Self {
+        Self {
+            cpu_hz,
+            last_cycles: None,
+            counters: [Counter::new(clk_hz), Counter::new(clk_hz), Counter::new(clk_hz)],
+        }
+    }
+
+    pub fn set_clock(&mut self, counter:usize, clk_hz:u32) {
+        self.counters[counter].clk_hz = clk_hz;
+        self.counters[counter].clk_remainder = 0;
+    }
+
+    pub fn set_gate(&mut self, counter:usize, level:bool, cycles:u32) {
+        self.update(cycles);
+        self.counters[counter].set_gate(level);
+    }
+
+    pub fn out(&mut self, counter:usize, cycles:u32) -> bool {
+        self.update(cycles);
+        self.counters[counter].out
+    }
+
+    // Number of OUT rising edges since the last call
+    pub fn take_rising_edges(&mut self, counter:usize, cycles:u32) -> u32 {
+        self.update(cycles);
+        std::mem::take(&mut self.counters[counter].rising_edges)
+    }
+
+    // Feeds CLK pulses directly, for counters clocked by something other
+    // than the CPU (e.g. another counter's OUT)
+    pub fn clock(&mut self, counter:usize, pulses:u32) {
+        self.counters[counter].clock(pulses);
+    }
+
+    // Bring all counters up to date with the CPU cycle counter
+    pub fn update(&mut self, cycles:u32) {
+        let elapsed = match self.last_cycles {
+            Some(last) => cycles_since(cycles, last).max(0) as u64,
+            None => 0,
+        };
+        self.last_cycles = Some(cycles);
+        if elapsed == 0 || self.cpu_hz == 0 {
+            return;
+        }
+
+        for counter in self.counters.iter_mut() {
+            let ticks = elapsed * counter.clk_hz as u64 + counter.clk_remainder;
+            counter.clk_remainder = ticks % self.cpu_hz as u64;
+            let pulses = ticks / self.cpu_hz as u64;
+            counter.clock(pulses.min(u32::MAX as u64) as u32);
+        }
+    }
+
+    fn write_control(&mut self, value:u8) {
+        let select = (value >> 6) as usize;
+        if select < 3 {
+            self.counters[select].write_control(value);
+            return;
+        }
+
+        // 8254 read-back command, bits 1-3 select counters, active high
+        let latch_count = value & 0x20 == 0;
+        let latch_status = value & 0x10 == 0;
+        for (i, counter) in self.counters.iter_mut().enumerate() {
+            if value & (0x02 << i) != 0 {
+                if latch_count {
+                    counter.latch_count();
+                }
+                if latch_status {
+                    counter.latch_status();
+                }
+            }
+        }
+    }
+}
+
+impl IoDevice for Pit8253 {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        self.update(cycles);
+        match port & 0x03 {
+            3 => 0xFF,
+            counter => self.counters[counter as usize].read(),
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, cycles:u32) {
+        self.update(cycles);
+        match port & 0x03 {
+            3 => self.write_control(value),
+            counter => self.counters[counter as usize].write_count(value),
+        }
+    }
+}
+
+// Wires a counter's OUT pin to the CPU INT line, requesting RST `vector` on
+// each rising edge
+pub struct TimerInterrupt {
+    pit:Rc<RefCell<Pit8253>>,
+    counter:usize,
+    vector:u8,
+    pending:u32,
+}
+
+impl TimerInterrupt {
+    pub fn new(pit:Rc<RefCell<Pit8253>>, counter:usize, vector:u8) -> Self {
+        Self { pit, counter, vector, pending: 0 }
+    }
+}
+
+impl InterruptSource for TimerInterrupt {
+    fn pending(&mut self, cycles:u32) -> bool {
+        self.pending += self.pit.borrow_mut().take_rising_edges(self.counter, cycles);
+        self.pending > 0
+    }
+
+    fn acknowledge(&mut self, _cycles:u32) -> Vec<u8> {
+        // Edges that arrived while the CPU was busy collapse into one request
+        self.pending = 0;
+        vec![rst(self.vector)]
+    }
+}
+
+// A counter's OUT pin as an input for an interrupt controller
+pub struct TimerOut {
+    pit:Rc<RefCell<Pit8253>>,
+    counter:usize,
+}
+
+impl TimerOut {
+    pub fn new(pit:Rc<RefCell<Pit8253>>, counter:usize) -> Self {
+        Self { pit, counter }
+    }
+}
+
+impl InterruptLine for TimerOut {
+    fn level(&mut self, cycles:u32) -> bool {
+        self.pit.borrow_mut().out(self.counter, cycles)
+    }
+
+    fn rising_edges(&mut self, cycles:u32) -> u32 {
+        self.pit.borrow_mut().take_rising_edges(self.counter, cycles)
+    }
+}
+
+fn bcd_to_bin(value:u16) -> u32 {
+    let mut result = 0;
+    for shift in [12, 8, 4, 0] {
+        result = result * 10 + ((value >> shift) & 0x0F).min(9) as u32;
+    }
+    result
+}
+
+fn bin_to_bcd(value:u32) -> u16 {
+    let mut result = 0;
+    let mut value = value % 10000;
+    for shift in [0, 4, 8, 12] {
+        result |= ((value % 10) as u16) << shift;
+        value /= 10;
+    }
+    result
+}
@@ -0,0 +1,388 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interrupt::InterruptLine;
+use crate::io::IoDevice;
+
+// Intel 8255 programmable peripheral interface
+// Port offsets 0-2 are ports A, B and C, offset 3 is the control word.
+// The host sees the chip's pins through an input callback (sampled when the
+// 8080 reads an input port) and an output callback (fired when the 8080
+// writes an output port, and whenever the port C pins change).
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PpiPort {
+    A,
+    B,
+    C,
+}
+
+pub struct Ppi8255 {
+    pub control:u8,
+    latch:[u8; 3], // output latches for A, B and C
+    pins:[u8; 3], // input pin levels when no input callback is set
+
+    input_a:u8, // data strobed in, modes 1 and 2
+    input_b:u8,
+    ibf_a:bool,
+    obf_a:bool, // true while the output buffer is full (OBF pin low)
+    inte_a_out:bool, // INTE A in mode 1 output, INTE1 in mode 2
+    inte_a_in:bool, // INTE A in mode 1 input, INTE2 in mode 2
+    ibf_b:bool,
+    obf_b:bool,
+    inte_b:bool,
+
+    last_c:u8,
+    on_output:Option<Box<dyn FnMut(PpiPort, u8)>>,
+    on_input:Option<Box<dyn FnMut(PpiPort) -> u8>>,
+}
+
+impl Default for Ppi8255 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppi8255 {
+    pub fn new() -> Self {
+        Self {
+            // All ports input, mode 0
+            control: 0x9B,
+            latch: [0; 3],
+            pins: [0xFF; 3],
+            input_a: 0,
+            input_b: 0,
+            ibf_a: false,
+            obf_a: false,
+            inte_a_out: false,
+            inte_a_in: false,
+            ibf_b: false,
+            obf_b: false,
+            inte_b: false,
+            last_c: 0,
+            on_output: None,
+            on_input: None,
+        }
+    }
+
+    pub fn set_output_callback(&mut self, callback:Box<dyn FnMut(PpiPort, u8)>) {
+        self.on_output = Some(callback);
+    }
+
+    pub fn set_input_callback(&mut self, callback:Box<dyn FnMut(PpiPort) -> u8>) {
+        self.on_input = Some(callback);
+    }
+
+    // Host side: levels on the pins of a port, used without an input callback
+    pub fn set_pins(&mut self, port:PpiPort, value:u8) {
+        self.pins[port as usize] = value;
+    }
+
+    pub fn output_latch(&self, port:PpiPort) -> u8 {
+        self.latch[port as usize]
+    }
+
+    pub fn group_a_mode(&self) -> u8 {
+        match (self.control >> 5) & 0x03 {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        }
+    }
+
+    pub fn group_b_mode(&self) -> u8 {
+        (self.control >> 2) & 0x01
+    }
+
+    fn port_a_input(&self) -> bool {
+        self.control & 0x10 != 0
+    }
+
+    fn port_b_input(&self) -> bool {
+        self.control & 0x02 != 0
+    }
+
+    // Port C bits used as plain I/O and whether they are inputs
+    fn port_c_io(&self) -> (u8, u8) {
+        let mut io_bits = 0u8;
+        let mut input_bits = 0u8;
+
+        let upper = match self.group_a_mode() {
+            0 => 0xF0,
+            1 if self.port_a_input() => 0xC0,
+            1 => 0x30,
+            _ => 0x00,
+        };
+        io_bits |= upper;
+        if self.control & 0x08 != 0 {
+            input_bits |= upper;
+        }
+
+        // PC3 belongs to group A as INTR A in modes 1 and 2
+        let lower = match (self.group_a_mode(), self.group_b_mode()) {
+            (0, 0) => 0x0F,
+            (0, _) => 0x08,
+            (_, 0) => 0x07,
+            _ => 0x00,
+        };
+        io_bits |= lower;
+        if self.control & 0x01 != 0 {
+            input_bits |= lower;
+        }
+
+        (io_bits, input_bits)
+    }
+
+    pub fn intr_a(&self) -> bool {
+        match self.group_a_mode() {
+            0 => false,
+            1 if self.port_a_input() => self.inte_a_in && self.ibf_a,
+            1 => self.inte_a_out && !self.obf_a,
+            _ => (self.inte_a_out && !self.obf_a) || (self.inte_a_in && self.ibf_a),
+        }
+    }
+
+    pub fn intr_b(&self) -> bool {
+        match self.group_b_mode() {
+            0 => false,
+            _ if self.port_b_input() => self.inte_b && self.ibf_b,
+            _ => self.inte_b && !self.obf_b,
+        }
+    }
+
+    // Handshake and status bits driven onto port C in modes 1 and 2
+    fn port_c_status(&self) -> u8 {
+        let mut status = 0u8;
+        let a_mode = self.group_a_mode();
+
+        if a_mode != 0 {
+            if self.intr_a() {
+                status |= 0x08;
+            }
+            let input = a_mode == 2 || self.port_a_input();
+            let output = a_mode == 2 || !self.port_a_input();
+            if input {
+                status |= (self.ibf_a as u8) << 5 | (self.inte_a_in as u8) << 4;
+            }
+            if output {
+                status |= (!self.obf_a as u8) << 7 | (self.inte_a_out as u8) << 6;
+            }
+        }
+
+        if self.group_b_mode() != 0 {
+            status |= (self.inte_b as u8) << 2 | self.intr_b() as u8;
+            if self.port_b_input() {
+                status |= (self.ibf_b as u8) << 1;
+            } else {
+                status |= (!self.obf_b as u8) << 1;
+            }
+        }
+
+        status
+    }
+
+    fn sample(&mut self, port:PpiPort) -> u8 {
+        match self.on_input.as_mut() {
+            Some(callback) => callback(port),
+            None => self.pins[port as usize],
+        }
+    }
+
+    // Levels the chip drives on port C
+    pub fn port_c_pins(&self) -> u8 {
+        let (io_bits, input_bits) = self.port_c_io();
+        let outputs = io_bits & !input_bits;
+        // INTE bits are visible in the status read but never driven out
+        let handshake = !io_bits & !0x54;
+        (self.latch[2] & outputs) | (self.port_c_status() & handshake)
+    }
+
+    fn emit(&mut self, port:PpiPort, value:u8) {
+        if let Some(callback) = self.on_output.as_mut() {
+            callback(port, value);
+        }
+    }
+
+    fn port_c_changed(&mut self) {
+        let pins = self.port_c_pins();
+        if pins != self.last_c {
+            self.last_c = pins;
+            self.emit(PpiPort::C, pins);
+        }
+    }
+
+    // Host side: strobe data into port A (mode 1 input or mode 2)
+    pub fn strobe_a(&mut self, data:u8) {
+        self.input_a = data;
+        self.ibf_a = true;
+        self.port_c_changed();
+    }
+
+    pub fn strobe_b(&mut self, data:u8) {
+        self.input_b = data;
+        self.ibf_b = true;
+        self.port_c_changed();
+    }
+
+    // Host side: acknowledge port A output (mode 1 output or mode 2),
+    // returning the data the 8080 wrote
+    pub fn ack_a(&mut self) -> u8 {
+        self.obf_a = false;
+        self.port_c_changed();
+        self.latch[0]
+    }
+
+    pub fn ack_b(&mut self) -> u8 {
+        self.obf_b = false;
+        self.port_c_changed();
+        self.latch[1]
+    }
+
+    pub fn ibf_a(&self) -> bool {
+        self.ibf_a
+    }
+
+    pub fn obf_a(&self) -> bool {
+        self.obf_a
+    }
+
+    pub fn ibf_b(&self) -> bool {
+        self.ibf_b
+    }
+
+    pub fn obf_b(&self) -> bool {
+        self.obf_b
+    }
+
+    fn write_control(&mut self, value:u8) {
+        if value & 0x80 != 0 {
+            // Mode set resets all outputs and handshake flip-flops
+            self.control = value;
+            self.latch = [0; 3];
+            self.ibf_a = false;
+            self.obf_a = false;
+            self.inte_a_out = false;
+            self.inte_a_in = false;
+            self.ibf_b = false;
+            self.obf_b = false;
+            self.inte_b = false;
+            if !self.port_a_input() && self.group_a_mode() != 2 {
+                self.emit(PpiPort::A, 0);
+            }
+            if !self.port_b_input() {
+                self.emit(PpiPort::B, 0);
+            }
+            self.port_c_changed();
+            return;
+        }
+
+        // Bit set/reset, which also drives the INTE flip-flops in modes 1 and 2
+        let bit = (value >> 1) & 0x07;
+        let set = value & 0x01 != 0;
+        match (bit, self.group_a_mode(), self.port_a_input()) {
+            (4, 1, true) | (4, 2, _) => self.inte_a_in = set,
+            (6, 1, false) | (6, 2, _) => self.inte_a_out = set,
+            (2, _, _) if self.group_b_mode() == 1 => self.inte_b = set,
+            _ => {
+                if set {
+                    self.latch[2] |= 1 << bit;
+                } else {
+                    self.latch[2] &= !(1 << bit);
+                }
+            }
+        }
+        self.port_c_changed();
+    }
+}
+
+impl IoDevice for Ppi8255 {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        match port & 0x03 {
+            0 => match self.group_a_mode() {
+                0 if self.port_a_input() => self.sample(PpiPort::A),
+                0 => self.latch[0],
+                1 if !self.port_a_input() => self.latch[0],
+                _ => {
+                    self.ibf_a = false;
+                    self.port_c_changed();
+                    self.input_a
+                }
+            },
+            1 => match self.group_b_mode() {
+                0 if self.port_b_input() => self.sample(PpiPort::B),
+                _ if !self.port_b_input() => self.latch[1],
+                _ => {
+                    self.ibf_b = false;
+                    self.port_c_changed();
+                    self.input_b
+                }
+            },
+            2 => {
+                let (io_bits, input_bits) = self.port_c_io();
+                let pins = if input_bits != 0 { self.sample(PpiPort::C) } else { 0 };
+                (pins & input_bits) | (self.latch[2] & io_bits & !input_bits) | (self.port_c_status() & !io_bits)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        match port & 0x03 {
+            0 => {
+                self.latch[0] = value;
+                match self.group_a_mode() {
+                    0 if !self.port_a_input() => self.emit(PpiPort::A, value),
+                    1 if !self.port_a_input() => {
+                        self.obf_a = true;
+                        self.emit(PpiPort::A, value);
+                        self.port_c_changed();
+                    }
+                    2 => {
+                        self.obf_a = true;
+                        self.port_c_changed();
+                    }
+                    _ => {}
+                }
+            }
+            1 => {
+                self.latch[1] = value;
+                if !self.port_b_input() {
+                    if self.group_b_mode() == 1 {
+                        self.obf_b = true;
+                    }
+                    self.emit(PpiPort::B, value);
+                    self.port_c_changed();
+                }
+            }
+            2 => {
+                // Only the plain I/O bits of port C can be written directly
+                let (io_bits, _) = self.port_c_io();
+                self.latch[2] = (self.latch[2] & !io_bits) | (value & io_bits);
+                self.port_c_changed();
+            }
+            _ => self.write_control(value),
+        }
+    }
+}
+
+// INTR A or INTR B as an input for an interrupt controller
+pub struct PpiInterrupt {
+    ppi:Rc<RefCell<Ppi8255>>,
+    port:PpiPort,
+}
+
+impl PpiInterrupt {
+    pub fn new(ppi:Rc<RefCell<Ppi8255>>, port:PpiPort) -> Self {
+        Self { ppi, port }
+    }
+}
+
+impl InterruptLine for PpiInterrupt {
+    fn level(&mut self, _cycles:u32) -> bool {
+        let ppi = self.ppi.borrow();
+        match self.port {
+            PpiPort::B => ppi.intr_b(),
+            _ => ppi.intr_a(),
+        }
+    }
+}
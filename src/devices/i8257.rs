@@ -0,0 +1,231 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::dma::{BusMaster, DmaPeripheral};
+use crate::io::IoDevice;
+
+// Intel 8257 programmable DMA controller
+// Port offsets 0-7 are the address and terminal count registers of channels
+// 0-3 (even = address, odd = count), offset 8 is the mode set register
+// (write) / status register (read). Attach it to the CPU both as an I/O
+// device and as a bus master; peripherals are connected per channel.
+
+// Cycles the CPU loses for every byte moved (S1-S4)
+const CYCLES_PER_TRANSFER:u32 = 4;
+// Longest burst handled in a single bus grant
+const MAX_BURST:u32 = 0x4000;
+
+const MODE_ROTATE:u8 = 0x10;
+const MODE_TC_STOP:u8 = 0x40;
+const MODE_AUTOLOAD:u8 = 0x80;
+
+const STATUS_UPDATE:u8 = 0x10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransferType {
+    Verify,
+    Write, // peripheral to memory
+    Read, // memory to peripheral
+    Illegal,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Channel {
+    pub address:u16,
+    pub count:u16, // bits 0-13 = bytes - 1, bits 14-15 = transfer type
+}
+
+impl Channel {
+    pub fn transfer_type(&self) -> TransferType {
+        match self.count >> 14 {
+            0 => TransferType::Verify,
+            1 => TransferType::Write,
+            2 => TransferType::Read,
+            _ => TransferType::Illegal,
+        }
+    }
+}
+
+pub struct Dma8257 {
+    pub channels:[Channel; 4],
+    pub mode:u8,
+    status:u8,
+    msb_next:bool, // first/last flip-flop
+    highest_priority:usize,
+    peripherals:[Option<Rc<RefCell<dyn DmaPeripheral>>>; 4],
+    requests:[bool; 4], // DRQ lines driven by the host
+    pub stolen_cycles:u32,
+}
+
+impl Default for Dma8257 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dma8257 {
+    pub fn new() -> Self {
+        Self {
+            channels: [Channel::default(); 4],
+            mode: 0,
+            status: 0,
+            msb_next: false,
+            highest_priority: 0,
+            peripherals: Default::default(),
+            requests: [false; 4],
+            stolen_cycles: 0,
+        }
+    }
+
+    pub fn connect(&mut self, channel:usize, peripheral:Rc<RefCell<dyn DmaPeripheral>>) {
+        self.peripherals[channel & 3] = Some(peripheral);
+    }
+
+    // Host side: drive DRQ for a channel without a connected peripheral
+    pub fn set_dreq(&mut self, channel:usize, level:bool) {
+        self.requests[channel & 3] = level;
+    }
+
+    fn enabled(&self, channel:usize) -> bool {
+        self.mode & (1 << channel) != 0
+    }
+
+    fn requesting(&self, channel:usize, cycles:u32) -> bool {
+        if !self.enabled(channel) {
+            return false;
+        }
+        match &self.peripherals[channel] {
+            Some(peripheral) => peripheral.borrow_mut().dreq(cycles),
+            None => self.requests[channel],
+        }
+    }
+
+    fn next_request(&self, cycles:u32) -> Option<usize> {
+        (0..4)
+            .map(|i| (self.highest_priority + i) & 3)
+            .find(|&channel| self.requesting(channel, cycles))
+    }
+
+    // Moves one byte on `channel`, returns false if the transfer could not run
+    fn transfer(&mut self, channel:usize, ram:&mut [u8], cycles:u32) -> bool {
+        let Channel { address, count } = self.channels[channel];
+        let peripheral = self.peripherals[channel].clone();
+
+        match self.channels[channel].transfer_type() {
+            TransferType::Write => {
+                let value = match &peripheral {
+                    Some(peripheral) => peripheral.borrow_mut().dma_read(cycles),
+                    None => 0xFF,
+                };
+                ram[address as usize] = value;
+            }
+            TransferType::Read => {
+                let value = ram[address as usize];
+                if let Some(peripheral) = &peripheral {
+                    peripheral.borrow_mut().dma_write(value, cycles);
+                }
+            }
+            TransferType::Verify => {}
+            TransferType::Illegal => return false,
+        }
+
+        let remaining = count & 0x3FFF;
+        self.channels[channel].address = address.wrapping_add(1);
+        self.channels[channel].count = (count & 0xC000) | (remaining.wrapping_sub(1) & 0x3FFF);
+
+        if self.mode & MODE_ROTATE != 0 {
+            self.highest_priority = (channel + 1) & 3;
+        }
+
+        if remaining == 0 {
+            self.status |= 1 << channel;
+            if let Some(peripheral) = &peripheral {
+                peripheral.borrow_mut().terminal_count(cycles);
+            }
+            if channel == 2 && self.mode & MODE_AUTOLOAD != 0 {
+                // Channel 3 holds the parameters for the next block
+                self.channels[2] = self.channels[3];
+                self.status |= STATUS_UPDATE;
+            } else if self.mode & MODE_TC_STOP != 0 {
+                self.mode &= !(1 << channel);
+            }
+        }
+        true
+    }
+
+    fn write_register(&mut self, port:u8, value:u8) {
+        let channel = ((port >> 1) & 3) as usize;
+        let autoload = channel == 2 && self.mode & MODE_AUTOLOAD != 0;
+
+        // In autoload mode, channel 2 writes also go to channel 3
+        let targets:&[usize] = if autoload { &[2, 3] } else { &[channel] };
+        for &target in targets {
+            let register = if port & 1 == 0 {
+                &mut self.channels[target].address
+            } else {
+                &mut self.channels[target].count
+            };
+            *register = if self.msb_next {
+                (*register & 0x00FF) | (value as u16) << 8
+            } else {
+                (*register & 0xFF00) | value as u16
+            };
+        }
+        self.msb_next = !self.msb_next;
+    }
+
+    fn read_register(&mut self, port:u8) -> u8 {
+        let channel = &self.channels[((port >> 1) & 3) as usize];
+        let register = if port & 1 == 0 { channel.address } else { channel.count };
+        let value = if self.msb_next { (register >> 8) as u8 } else { register as u8 };
+        self.msb_next = !self.msb_next;
+        value
+    }
+}
+
+impl IoDevice for Dma8257 {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        if port & 0x08 != 0 {
+            // Reading status clears the TC bits
+            let status = self.status;
+            self.status &= STATUS_UPDATE;
+            status
+        } else {
+            self.read_register(port)
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        if port & 0x08 != 0 {
+            self.mode = value;
+            self.msb_next = false;
+            self.status &= !STATUS_UPDATE;
+        } else {
+            self.write_register(port, value);
+        }
+    }
+}
+
+impl BusMaster for Dma8257 {
+    fn hold(&mut self, cycles:u32) -> bool {
+        self.next_request(cycles).is_some()
+    }
+
+    // Keeps the bus for as long as any channel is requesting
+    fn bus_granted(&mut self, ram:&mut [u8], cycles:u32) -> u32 {
+        let mut used = 0;
+        while used < MAX_BURST * CYCLES_PER_TRANSFER {
+            let now = cycles.wrapping_add(used);
+            let channel = match self.next_request(now) {
+                Some(channel) => channel,
+                None => break,
+            };
+            if !self.transfer(channel, ram, now) {
+                break;
+            }
+            used += CYCLES_PER_TRANSFER;
+        }
+        self.stolen_cycles = self.stolen_cycles.wrapping_add(used);
+        used
+    }
+}
@@ -0,0 +1,369 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interrupt::{InterruptLine, InterruptSource};
+use crate::io::IoDevice;
+
+// Intel 8259A programmable interrupt controller
+// Port offset 0 is A0 = 0 (ICW1, OCW2, OCW3, IRR/ISR/poll reads) and offset 1
+// is A0 = 1 (ICW2-4, OCW1/IMR). In 8080 mode an acknowledge returns a CALL to
+// the vector address built from ICW1/ICW2; a master passes acknowledges for
+// cascaded inputs through to the slave that supplies the address.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum InitState {
+    Ready,
+    Icw2,
+    Icw3,
+    Icw4,
+}
+
+pub struct Pic8259 {
+    init:InitState,
+    pub icw1:u8,
+    pub icw2:u8,
+    pub icw3:u8,
+    pub icw4:u8,
+
+    pub irr:u8,
+    pub isr:u8,
+    pub imr:u8,
+
+    lowest_priority:u8,
+    rotate_on_aeoi:bool,
+    special_mask:bool,
+    read_isr:bool,
+    poll:bool,
+
+    levels:[bool; 8],
+    inputs:[Option<Box<dyn InterruptLine>>; 8],
+    slaves:[Option<Rc<RefCell<Pic8259>>>; 8],
+}
+
+impl Default for Pic8259 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pic8259 {
+    pub fn new() -> Self {
+        Self {
+            init: InitState::Ready,
+            icw1: 0,
+            icw2: 0,
+            icw3: 0,
+            icw4: 0,
+            irr: 0,
+            isr: 0,
+            imr: 0xFF,
+            lowest_priority: 7,
+            rotate_on_aeoi: false,
+            special_mask: false,
+            read_isr: false,
+            poll: false,
+            levels: [false; 8],
+            inputs: Default::default(),
+            slaves: Default::default(),
+        }
+    }
+
+    // Wires an interrupt request line to input IR `ir`
+    pub fn connect(&mut self, ir:u8, line:Box<dyn InterruptLine>) {
+        self.inputs[(ir & 7) as usize] = Some(line);
+    }
+
+    // Wires a slave controller's INT output to input IR `ir`
+    pub fn cascade(&mut self, ir:u8, slave:Rc<RefCell<Pic8259>>) {
+        self.slaves[(ir & 7) as usize] = Some(slave);
+    }
+
+    // Host side: drive an IR input that isn't connected to a line
+    pub fn set_irq(&mut self, ir:u8, level:bool) {
+        let ir = ir & 7;
+        self.set_level(ir, level, 0);
+    }
+
+    // Host side: a rising edge followed by the line dropping again
+    pub fn pulse_irq(&mut self, ir:u8) {
+        let ir = ir & 7;
+        self.set_level(ir, true, 0);
+        self.set_level(ir, false, 0);
+    }
+
+    fn level_triggered(&self) -> bool {
+        self.icw1 & 0x08 != 0
+    }
+
+    fn single(&self) -> bool {
+        self.icw1 & 0x02 != 0
+    }
+
+    fn auto_eoi(&self) -> bool {
+        self.icw4 & 0x02 != 0
+    }
+
+    fn mode_8086(&self) -> bool {
+        self.icw4 & 0x01 != 0
+    }
+
+    fn special_fully_nested(&self) -> bool {
+        self.icw4 & 0x10 != 0
+    }
+
+    fn set_level(&mut self, ir:u8, level:bool, edges:u32) {
+        let bit = 1 << ir;
+        let rising = edges > 0 || (level && !self.levels[ir as usize]);
+        self.levels[ir as usize] = level;
+
+        if self.level_triggered() {
+            if level {
+                self.irr |= bit;
+            } else {
+                self.irr &= !bit;
+            }
+        } else if rising {
+            self.irr |= bit;
+        }
+    }
+
+    fn sample(&mut self, cycles:u32) {
+        for ir in 0..8u8 {
+            if let Some(slave) = &self.slaves[ir as usize] {
+                if !self.single() && self.icw3 & (1 << ir) != 0 {
+                    // A slave holds INT until it is acknowledged
+                    if slave.borrow_mut().int_output(cycles) {
+                        self.irr |= 1 << ir;
+                    } else {
+                        self.irr &= !(1 << ir);
+                    }
+                    continue;
+                }
+            }
+            if let Some(line) = self.inputs[ir as usize].as_mut() {
+                let level = line.level(cycles);
+                let edges = line.rising_edges(cycles);
+                self.set_level(ir, level, edges);
+            }
+        }
+    }
+
+    // Priority order starting from the highest priority input
+    fn priority_order(&self) -> impl Iterator<Item = u8> {
+        let first = (self.lowest_priority + 1) & 7;
+        (0..8).map(move |i| (first + i) & 7)
+    }
+
+    // Highest priority request that isn't masked or blocked by a level in service
+    fn highest_request(&self) -> Option<u8> {
+        let requests = self.irr & !self.imr;
+        for ir in self.priority_order() {
+            let bit = 1 << ir;
+            let cascaded = self.slaves[ir as usize].is_some() && self.special_fully_nested();
+            if self.isr & bit != 0 && !self.special_mask && !cascaded {
+                return None;
+            }
+            if requests & bit != 0 {
+                return Some(ir);
+            }
+        }
+        None
+    }
+
+    fn highest_in_service(&self) -> Option<u8> {
+        self.priority_order().find(|ir| self.isr & (1 << ir) != 0)
+    }
+
+    // State of the INT output pin
+    pub fn int_output(&mut self, cycles:u32) -> bool {
+        if self.init != InitState::Ready {
+            return false;
+        }
+        self.sample(cycles);
+        self.highest_request().is_some()
+    }
+
+    fn vector_address(&self, ir:u8) -> u16 {
+        let low = if self.icw1 & 0x04 != 0 {
+            (self.icw1 & 0xE0) | (ir << 2)
+        } else {
+            (self.icw1 & 0xC0) | (ir << 3)
+        };
+        (self.icw2 as u16) << 8 | low as u16
+    }
+
+    // First INTA pulse: resolve the request and mark it in service
+    fn begin_acknowledge(&mut self, cycles:u32) -> Option<u8> {
+        self.sample(cycles);
+        let ir = self.highest_request()?;
+        let bit = 1 << ir;
+        self.isr |= bit;
+        if !self.level_triggered() {
+            self.irr &= !bit;
+        }
+        Some(ir)
+    }
+
+    fn end_acknowledge(&mut self, ir:u8) {
+        if self.auto_eoi() {
+            self.isr &= !(1 << ir);
+            if self.rotate_on_aeoi {
+                self.lowest_priority = ir;
+            }
+        }
+    }
+
+    // The vector bytes this controller puts on the bus. A request that went
+    // away before the acknowledge is reported as IR7 without setting ISR.
+    fn acknowledge_bytes(&mut self, cycles:u32) -> Vec<u8> {
+        let ir = self.begin_acknowledge(cycles);
+        let vector_ir = ir.unwrap_or(7);
+
+        let slave = match ir {
+            Some(ir) if !self.single() && self.icw3 & (1 << ir) != 0 => self.slaves[ir as usize].clone(),
+            _ => None,
+        };
+
+        let bytes = match slave {
+            Some(slave) => slave.borrow_mut().acknowledge_bytes(cycles),
+            None if self.mode_8086() => vec![(self.icw2 & 0xF8) | vector_ir],
+            None => {
+                let address = self.vector_address(vector_ir);
+                vec![0xCD, address as u8, (address >> 8) as u8]
+            }
+        };
+
+        if let Some(ir) = ir {
+            self.end_acknowledge(ir);
+        }
+        bytes
+    }
+
+    fn write_a0(&mut self, value:u8) {
+        if value & 0x10 != 0 {
+            // ICW1 restarts the initialization sequence
+            self.icw1 = value;
+            self.icw4 = 0;
+            self.init = InitState::Icw2;
+            self.imr = 0;
+            self.isr = 0;
+            self.irr = 0;
+            self.levels = [false; 8];
+            self.lowest_priority = 7;
+            self.rotate_on_aeoi = false;
+            self.special_mask = false;
+            self.read_isr = false;
+            self.poll = false;
+            return;
+        }
+
+        if value & 0x08 == 0 {
+            self.write_ocw2(value);
+        } else {
+            // OCW3
+            if value & 0x40 != 0 {
+                self.special_mask = value & 0x20 != 0;
+            }
+            if value & 0x02 != 0 {
+                self.read_isr = value & 0x01 != 0;
+            }
+            self.poll = value & 0x04 != 0;
+        }
+    }
+
+    fn write_ocw2(&mut self, value:u8) {
+        let level = value & 0x07;
+        match value >> 5 {
+            // Non-specific EOI, optionally rotating
+            0b001 | 0b101 => {
+                if let Some(ir) = self.highest_in_service() {
+                    self.isr &= !(1 << ir);
+                    if value & 0x80 != 0 {
+                        self.lowest_priority = ir;
+                    }
+                }
+            }
+            // Specific EOI, optionally rotating
+            0b011 | 0b111 => {
+                self.isr &= !(1 << level);
+                if value & 0x80 != 0 {
+                    self.lowest_priority = level;
+                }
+            }
+            0b100 => self.rotate_on_aeoi = true,
+            0b000 => self.rotate_on_aeoi = false,
+            0b110 => self.lowest_priority = level,
+            _ => {}
+        }
+    }
+
+    fn write_a1(&mut self, value:u8) {
+        match self.init {
+            InitState::Icw2 => {
+                self.icw2 = value;
+                self.init = if !self.single() {
+                    InitState::Icw3
+                } else if self.icw1 & 0x01 != 0 {
+                    InitState::Icw4
+                } else {
+                    InitState::Ready
+                };
+            }
+            InitState::Icw3 => {
+                self.icw3 = value;
+                self.init = if self.icw1 & 0x01 != 0 {
+                    InitState::Icw4
+                } else {
+                    InitState::Ready
+                };
+            }
+            InitState::Icw4 => {
+                self.icw4 = value;
+                self.init = InitState::Ready;
+            }
+            InitState::Ready => self.imr = value,
+        }
+    }
+
+    fn read_poll(&mut self, cycles:u32) -> u8 {
+        self.poll = false;
+        match self.begin_acknowledge(cycles) {
+            Some(ir) => {
+                self.end_acknowledge(ir);
+                0x80 | ir
+            }
+            None => 0,
+        }
+    }
+}
+
+impl IoDevice for Pic8259 {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        if port & 1 != 0 {
+            return self.imr;
+        }
+        if self.poll {
+            return self.read_poll(cycles);
+        }
+        self.sample(cycles);
+        if self.read_isr { self.isr } else { self.irr }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        if port & 1 != 0 {
+            self.write_a1(value);
+        } else {
+            self.write_a0(value);
+        }
+    }
+}
+
+impl InterruptSource for Pic8259 {
+    fn pending(&mut self, cycles:u32) -> bool {
+        self.int_output(cycles)
+    }
+
+    fn acknowledge(&mut self, cycles:u32) -> Vec<u8> {
+        self.acknowledge_bytes(cycles)
+    }
+}
@@ -0,0 +1,525 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::dma::DmaPeripheral;
+use crate::interrupt::InterruptLine;
+use crate::io::{cycles_since, IoDevice};
+
+// Intel 8275 programmable CRT controller
+// Port offset 0 is the parameter register (A0 = 0), offset 1 is the
+// command (write) / status (read) register. Character rows are fetched into
+// the row buffers over DMA (connect it to an 8257 channel) during the row
+// before they are shown, and each row is drawn into the host framebuffer
+// through a character ROM when it is displayed.
+
+pub const STATUS_FO:u8 = 0x01;
+pub const STATUS_DU:u8 = 0x02;
+pub const STATUS_VE:u8 = 0x04;
+pub const STATUS_IC:u8 = 0x08;
+pub const STATUS_LP:u8 = 0x10;
+pub const STATUS_IR:u8 = 0x20;
+pub const STATUS_IE:u8 = 0x40;
+
+// Special codes in the character stream
+const END_OF_ROW:u8 = 0xF0;
+const END_OF_ROW_STOP_DMA:u8 = 0xF1;
+const END_OF_SCREEN:u8 = 0xF2;
+const END_OF_SCREEN_STOP_DMA:u8 = 0xF3;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct FieldAttribute {
+    pub underline:bool,
+    pub reverse:bool,
+    pub gpa:u8,
+    pub blink:bool,
+    pub highlight:bool,
+}
+
+impl FieldAttribute {
+    fn from_code(code:u8) -> Self {
+        Self {
+            underline: code & 0x20 != 0,
+            reverse: code & 0x10 != 0,
+            gpa: (code >> 2) & 0x03,
+            blink: code & 0x02 != 0,
+            highlight: code & 0x01 != 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Command {
+    None,
+    Reset,
+    LoadCursor,
+    ReadLightPen,
+}
+
+pub struct Crt8275 {
+    cpu_hz:u32,
+    char_clock_hz:u32,
+
+    // Reset parameters
+    pub spaced_rows:bool,
+    pub chars_per_row:usize,
+    pub vretrace_rows:usize,
+    pub rows_per_frame:usize,
+    pub underline_line:usize,
+    pub lines_per_row:usize,
+    pub line_offset_mode:bool,
+    pub transparent_attributes:bool,
+    pub cursor_format:u8,
+    pub hretrace_chars:usize,
+
+    // Start display parameters
+    pub burst_space:u8,
+    pub burst_count:u8,
+
+    pub cursor_char:usize,
+    pub cursor_row:usize,
+    light_pen:(u8, u8),
+
+    command:Command,
+    params:Vec<u8>,
+    read_params:Vec<u8>,
+    status:u8,
+    displaying:bool,
+
+    last_cycles:Option<u32>,
+    clock_remainder:u64,
+    clocks_into_row:u64,
+    row_slot:usize,
+    pub frame_count:u32,
+
+    fill:Vec<u8>,
+    fill_active:bool,
+    fill_done:bool,
+    burst_left:u8, // bytes before the next burst space
+    burst_wait:u64, // character clocks until DMA requests resume
+    dma_stopped:bool, // end of screen, stop DMA was seen this frame
+    end_of_screen:bool,
+    attribute:FieldAttribute,
+
+    char_rom:Vec<u8>,
+    rom_stride:usize,
+    char_width:usize,
+    framebuffer:Vec<u32>,
+    pub background:u32,
+    pub foreground:u32,
+    pub highlight:u32,
+}
+
+impl Crt8275 {
+    // `char_clock_hz` is the character clock (dot clock / character width)
+    pub fn new(cpu_hz:u32, char_clock_hz:u32) -> Self {
+        Self {
+            cpu_hz,
+            char_clock_hz,
+            spaced_rows: false,
+            chars_per_row: 80,
+            vretrace_rows: 1,
+            rows_per_frame: 25,
+            underline_line: 9,
+            lines_per_row: 10,
+            line_offset_mode: false,
+            transparent_attributes: true,
+            cursor_format: 0,
+            hretrace_chars: 2,
+            burst_space: 0,
+            burst_count: 1,
+            cursor_char: 0,
+            cursor_row: 0,
+            light_pen: (0, 0),
+            command: Command::None,
+            params: Vec::new(),
+            read_params: Vec::new(),
+            status: 0,
+            displaying: false,
+            last_cycles: None,
+            clock_remainder: 0,
+            clocks_into_row: 0,
+            row_slot: 0,
+            frame_count: 0,
+            fill: Vec::new(),
+            fill_active: false,
+            fill_done: false,
+            burst_left: 1,
+            burst_wait: 0,
+            dma_stopped: false,
+            end_of_screen: false,
+            attribute: FieldAttribute::default(),
+            char_rom: Vec::new(),
+            rom_stride: 8,
+            char_width: 8,
+            framebuffer: vec![0; 80 * 8 * 25 * 10],
+            background: 0xFF000000,
+            foreground: 0xFFAAAAAA,
+            highlight: 0xFFFFFFFF,
+        }
+    }
+
+    // `stride` bytes per character, one byte per line; dot x of a line is
+    // bit (width - 1 - x), so 6 dot fonts live in the low bits
+    pub fn set_char_rom(&mut self, rom:Vec<u8>, stride:usize, width:usize) {
+        self.char_rom = rom;
+        self.rom_stride = stride.max(1);
+        self.char_width = width.clamp(1, 8);
+        self.resize();
+    }
+
+    pub fn width(&self) -> usize {
+        self.chars_per_row * self.char_width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows_per_frame * self.lines_per_row
+    }
+
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    // Host side: position the light pen hit (character, row)
+    pub fn set_light_pen(&mut self, char:u8, row:u8) {
+        self.light_pen = (char, row);
+        self.status |= STATUS_LP;
+    }
+
+    // State of the IRQ output pin
+    pub fn irq(&self) -> bool {
+        self.status & STATUS_IE != 0 && self.status & STATUS_IR != 0
+    }
+
+    fn resize(&mut self) {
+        self.framebuffer = vec![self.background; self.width() * self.height()];
+    }
+
+    fn row_clocks(&self) -> u64 {
+        ((self.chars_per_row + self.hretrace_chars) * self.lines_per_row) as u64
+    }
+
+    fn total_rows(&self) -> usize {
+        self.rows_per_frame + self.vretrace_rows
+    }
+
+    // Characters a row needs in its buffer
+    fn fill_len(&self) -> usize {
+        if self.transparent_attributes {
+            self.fill.iter().filter(|&&code| !is_field_attribute(code)).count()
+        } else {
+            self.fill.len()
+        }
+    }
+
+    fn start_fill(&mut self) {
+        self.fill.clear();
+        self.fill_active = true;
+        self.fill_done = self.dma_stopped;
+        self.burst_left = self.burst_count;
+        self.burst_wait = 0;
+    }
+
+    // Bring the raster position up to date with the CPU cycle counter
+    pub fn update(&mut self, cycles:u32) {
+        let elapsed = match self.last_cycles {
+            Some(last) => cycles_since(cycles, last).max(0) as u64,
+            None => 0,
+        };
+        self.last_cycles = Some(cycles);
+        if elapsed == 0 || self.cpu_hz == 0 || !self.displaying {
+            return;
+        }
+
+        let clocks = elapsed * self.char_clock_hz as u64 + self.clock_remainder;
+        self.clock_remainder = clocks % self.cpu_hz as u64;
+        self.clocks_into_row += clocks / self.cpu_hz as u64;
+        self.burst_wait = self.burst_wait.saturating_sub(clocks / self.cpu_hz as u64);
+
+        let row_clocks = self.row_clocks().max(1);
+        while self.clocks_into_row >= row_clocks && self.displaying {
+            self.clocks_into_row -= row_clocks;
+            self.row_slot += 1;
+            if self.row_slot >= self.total_rows() {
+                self.row_slot = 0;
+                self.frame_count = self.frame_count.wrapping_add(1);
+            }
+            self.row_start();
+        }
+    }
+
+    fn row_start(&mut self) {
+        let slot = self.row_slot;
+        let display_rows = self.rows_per_frame;
+
+        if slot == 0 {
+            self.attribute = FieldAttribute::default();
+            self.end_of_screen = false;
+        }
+
+        if slot < display_rows {
+            if self.fill_active && !self.fill_done {
+                // The row wasn't fetched in time
+                self.status |= STATUS_DU;
+                self.end_of_screen = true;
+            }
+            self.fill_active = false;
+            let row = std::mem::take(&mut self.fill);
+            self.render_row(slot, &row);
+
+            if slot == display_rows - 1 {
+                self.status |= STATUS_IR;
+            } else {
+                self.start_fill();
+            }
+        }
+
+        // The first row of the next frame is fetched during the last retrace row
+        if slot == self.total_rows() - 1 {
+            self.dma_stopped = false;
+            self.start_fill();
+        }
+    }
+
+    fn render_row(&mut self, row:usize, data:&[u8]) {
+        let blink_on = self.frame_count & 0x10 == 0;
+        let cursor_on = self.cursor_format & 0x02 != 0 || self.frame_count & 0x08 == 0;
+        let mut codes = data.iter().copied();
+        let mut end_of_row = false;
+
+        for position in 0..self.chars_per_row {
+            let mut code = None;
+            if !end_of_row && !self.end_of_screen {
+                loop {
+                    match codes.next() {
+                        Some(END_OF_ROW) | Some(END_OF_ROW_STOP_DMA) => end_of_row = true,
+                        Some(END_OF_SCREEN) | Some(END_OF_SCREEN_STOP_DMA) => self.end_of_screen = true,
+                        Some(value) if is_field_attribute(value) => {
+                            self.attribute = FieldAttribute::from_code(value);
+                            // Non-transparent attributes take up a blank position
+                            if !self.transparent_attributes {
+                                break;
+                            }
+                            continue;
+                        }
+                        Some(value) if value < 0x80 => code = Some(value),
+                        // Character attribute codes are shown blank
+                        Some(_) => {}
+                        None => end_of_row = true,
+                    }
+                    break;
+                }
+            }
+
+            let attribute = self.attribute;
+            let cursor = cursor_on && row == self.cursor_row && position == self.cursor_char;
+            for line in 0..self.lines_per_row {
+                let mut dots = match code {
+                    Some(code) if !attribute.blink || blink_on => self.rom_line(code, line),
+                    _ => 0,
+                };
+                let full = ((1u16 << self.char_width) - 1) as u8;
+                if attribute.underline && line == self.underline_line {
+                    dots = full;
+                }
+                if attribute.reverse {
+                    dots ^= full;
+                }
+                if cursor {
+                    match self.cursor_format & 0x01 {
+                        0 => dots ^= full,
+                        _ if line == self.underline_line => dots = full,
+                        _ => {}
+                    }
+                }
+
+                let colour = if attribute.highlight { self.highlight } else { self.foreground };
+                let y = row * self.lines_per_row + line;
+                let start = y * self.width() + position * self.char_width;
+                for x in 0..self.char_width {
+                    let lit = dots & (1 << (self.char_width - 1 - x)) != 0;
+                    self.framebuffer[start + x] = if lit { colour } else { self.background };
+                }
+            }
+        }
+    }
+
+    fn rom_line(&self, code:u8, line:usize) -> u8 {
+        let line = if self.line_offset_mode {
+            (line + self.lines_per_row - 1) % self.lines_per_row
+        } else {
+            line
+        };
+        if line >= self.rom_stride {
+            return 0;
+        }
+        self.char_rom.get(code as usize * self.rom_stride + line).copied().unwrap_or(0)
+    }
+
+    fn write_command(&mut self, value:u8) {
+        if self.command != Command::None && self.command != Command::ReadLightPen {
+            self.status |= STATUS_IC;
+        }
+        self.command = Command::None;
+        self.params.clear();
+
+        match value >> 5 {
+            0 => {
+                self.command = Command::Reset;
+                self.displaying = false;
+                self.status &= !(STATUS_IE | STATUS_VE);
+            }
+            1 => {
+                self.burst_space = match (value >> 2) & 0x07 {
+                    0 => 0,
+                    space => space * 8 - 1,
+                };
+                self.burst_count = 1 << (value & 0x03);
+                self.burst_left = self.burst_count;
+                self.displaying = true;
+                self.status |= STATUS_IE | STATUS_VE;
+            }
+            2 => {
+                self.displaying = false;
+                self.status &= !STATUS_VE;
+            }
+            3 => {
+                self.command = Command::ReadLightPen;
+                self.read_params = vec![self.light_pen.0, self.light_pen.1];
+            }
+            4 => self.command = Command::LoadCursor,
+            5 => self.status |= STATUS_IE,
+            6 => self.status &= !STATUS_IE,
+            _ => {
+                // Preset counters, restart at the top of the frame
+                self.row_slot = self.total_rows() - 1;
+                self.clocks_into_row = 0;
+                self.start_fill();
+            }
+        }
+    }
+
+    fn write_parameter(&mut self, value:u8) {
+        self.params.push(value);
+        match (self.command, self.params.len()) {
+            (Command::Reset, 4) => {
+                let p = [self.params[0], self.params[1], self.params[2], self.params[3]];
+                self.spaced_rows = p[0] & 0x80 != 0;
+                self.chars_per_row = (p[0] & 0x7F) as usize + 1;
+                self.vretrace_rows = (p[1] >> 6) as usize + 1;
+                self.rows_per_frame = (p[1] & 0x3F) as usize + 1;
+                self.underline_line = (p[2] >> 4) as usize;
+                self.lines_per_row = (p[2] & 0x0F) as usize + 1;
+                self.line_offset_mode = p[3] & 0x80 != 0;
+                self.transparent_attributes = p[3] & 0x40 == 0;
+                self.cursor_format = (p[3] >> 4) & 0x03;
+                self.hretrace_chars = ((p[3] & 0x0F) as usize + 1) * 2;
+                self.row_slot = self.total_rows() - 1;
+                self.clocks_into_row = 0;
+                self.resize();
+                self.command = Command::None;
+            }
+            (Command::LoadCursor, 2) => {
+                self.cursor_char = self.params[0] as usize;
+                self.cursor_row = self.params[1] as usize;
+                self.command = Command::None;
+            }
+            (Command::None, _) | (Command::ReadLightPen, _) => self.status |= STATUS_IC,
+            _ => {}
+        }
+    }
+}
+
+impl IoDevice for Crt8275 {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        self.update(cycles);
+        if port & 1 != 0 {
+            // Reading status clears the interrupt request and error bits
+            let status = self.status;
+            self.status &= !(STATUS_IR | STATUS_LP | STATUS_IC | STATUS_DU | STATUS_FO);
+            return status;
+        }
+        if self.read_params.is_empty() {
+            self.status |= STATUS_IC;
+            0xFF
+        } else {
+            let value = self.read_params.remove(0);
+            if self.read_params.is_empty() {
+                self.command = Command::None;
+                self.status &= !STATUS_LP;
+            }
+            value
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, cycles:u32) {
+        self.update(cycles);
+        if port & 1 != 0 {
+            self.write_command(value);
+        } else {
+            self.write_parameter(value);
+        }
+    }
+}
+
+impl DmaPeripheral for Crt8275 {
+    fn dreq(&mut self, cycles:u32) -> bool {
+        self.update(cycles);
+        self.displaying && self.fill_active && !self.fill_done && self.burst_wait == 0
+    }
+
+    fn dma_read(&mut self, _cycles:u32) -> u8 {
+        0xFF
+    }
+
+    fn dma_write(&mut self, value:u8, _cycles:u32) {
+        if !self.fill_active || self.fill_done {
+            self.status |= STATUS_FO;
+            return;
+        }
+        self.fill.push(value);
+        // Requests pause for the burst space after every burst, when there
+        // is a clock to time it by
+        self.burst_left = self.burst_left.saturating_sub(1);
+        if self.burst_left == 0 {
+            self.burst_left = self.burst_count;
+            if self.cpu_hz != 0 {
+                self.burst_wait = self.burst_space as u64;
+            }
+        }
+        match value {
+            END_OF_ROW_STOP_DMA => self.fill_done = true,
+            END_OF_SCREEN_STOP_DMA => {
+                self.fill_done = true;
+                self.dma_stopped = true;
+            }
+            _ => self.fill_done = self.fill_len() >= self.chars_per_row,
+        }
+    }
+}
+
+// The IRQ pin as an input for an interrupt controller
+pub struct CrtInterrupt {
+    crt:Rc<RefCell<Crt8275>>,
+}
+
+impl CrtInterrupt {
+    pub fn new(crt:Rc<RefCell<Crt8275>>) -> Self {
+        Self { crt }
+    }
+}
+
+impl InterruptLine for CrtInterrupt {
+    fn level(&mut self, cycles:u32) -> bool {
+        let mut crt = self.crt.borrow_mut();
+        crt.update(cycles);
+        crt.irq()
+    }
+}
+
+fn is_field_attribute(code:u8) -> bool {
+    code & 0xC0 == 0x80
+}
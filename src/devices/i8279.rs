@@ -0,0 +1,383 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::interrupt::InterruptLine;
+use crate::io::IoDevice;
+
+// Intel 8279 programmable keyboard/display interface
+// Port offset 0 is the data register (A0 = 0), offset 1 is the command
+// (write) / status (read) register. Keys are pressed from the host side and
+// land in the 8 byte FIFO (or sensor RAM in sensor matrix mode) as the
+// scanning hardware would report them; the 16 byte display RAM can be read
+// back by the host to see what the monitor ROM is showing.
+
+const FIFO_SIZE:usize = 8;
+
+pub const STATUS_FULL:u8 = 0x08;
+pub const STATUS_UNDERRUN:u8 = 0x10;
+pub const STATUS_OVERRUN:u8 = 0x20;
+pub const STATUS_SENSOR:u8 = 0x40;
+pub const STATUS_DISPLAY_UNAVAILABLE:u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyboardMode {
+    EncodedLockout,
+    DecodedLockout,
+    EncodedRollover,
+    DecodedRollover,
+    EncodedSensor,
+    DecodedSensor,
+    StrobedEncoded,
+    StrobedDecoded,
+}
+
+impl KeyboardMode {
+    fn from_bits(bits:u8) -> Self {
+        match bits & 0x07 {
+            0 => KeyboardMode::EncodedLockout,
+            1 => KeyboardMode::DecodedLockout,
+            2 => KeyboardMode::EncodedRollover,
+            3 => KeyboardMode::DecodedRollover,
+            4 => KeyboardMode::EncodedSensor,
+            5 => KeyboardMode::DecodedSensor,
+            6 => KeyboardMode::StrobedEncoded,
+            _ => KeyboardMode::StrobedDecoded,
+        }
+    }
+
+    pub fn sensor_matrix(&self) -> bool {
+        matches!(self, KeyboardMode::EncodedSensor | KeyboardMode::DecodedSensor)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ReadTarget {
+    Fifo,
+    Display,
+}
+
+pub struct Kdc8279 {
+    pub keyboard_mode:KeyboardMode,
+    pub display_chars:usize, // 8 or 16
+    pub right_entry:bool,
+    pub prescaler:u8,
+
+    display:[u8; 16],
+    display_address:usize,
+    display_auto_increment:bool,
+    inhibit:u8, // bit 1 = upper nibble (A), bit 0 = lower nibble (B)
+    blank:u8, // same layout as `inhibit`
+    blank_code:u8,
+
+    fifo:VecDeque<u8>,
+    sensor:[u8; 8],
+    sensor_address:usize,
+    read_auto_increment:bool,
+    read_target:ReadTarget,
+    status:u8,
+    irq:bool,
+    special_error:bool,
+
+    keys_down:Vec<(u8, u8)>,
+    pub shift:bool,
+    pub control:bool,
+}
+
+impl Default for Kdc8279 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kdc8279 {
+    pub fn new() -> Self {
+        Self {
+            keyboard_mode: KeyboardMode::EncodedLockout,
+            display_chars: 8,
+            right_entry: false,
+            prescaler: 31,
+            display: [0; 16],
+            display_address: 0,
+            display_auto_increment: false,
+            inhibit: 0,
+            blank: 0,
+            blank_code: 0,
+            fifo: VecDeque::new(),
+            sensor: [0; 8],
+            sensor_address: 0,
+            read_auto_increment: false,
+            read_target: ReadTarget::Fifo,
+            status: 0,
+            irq: false,
+            special_error: false,
+            keys_down: Vec::new(),
+            shift: false,
+            control: false,
+        }
+    }
+
+    pub fn display_ram(&self) -> &[u8] {
+        &self.display[..self.display_chars]
+    }
+
+    // Display contents as the segments would show them, with blanking applied
+    pub fn visible_display(&self) -> Vec<u8> {
+        self.display_ram()
+            .iter()
+            .map(|&value| {
+                let mut value = value;
+                if self.blank & 0x02 != 0 {
+                    value = (value & 0x0F) | (self.blank_code & 0xF0);
+                }
+                if self.blank & 0x01 != 0 {
+                    value = (value & 0xF0) | (self.blank_code & 0x0F);
+                }
+                value
+            })
+            .collect()
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    pub fn fifo_len(&self) -> usize {
+        self.fifo.len()
+    }
+
+    fn push_fifo(&mut self, value:u8) {
+        if self.fifo.len() >= FIFO_SIZE {
+            self.status |= STATUS_OVERRUN;
+            return;
+        }
+        self.fifo.push_back(value);
+        self.irq = true;
+    }
+
+    // Host side: a key at scan `row` / return line `column` goes down.
+    // In sensor matrix mode this sets the bit in sensor RAM, otherwise the
+    // key code enters the FIFO with the current shift and control state.
+    pub fn key_down(&mut self, row:u8, column:u8) {
+        let (row, column) = (row & 7, column & 7);
+        if self.keys_down.contains(&(row, column)) {
+            return;
+        }
+
+        if self.keyboard_mode.sensor_matrix() {
+            self.keys_down.push((row, column));
+            self.set_sensor(row, column, true);
+            return;
+        }
+
+        match self.keyboard_mode {
+            KeyboardMode::EncodedLockout | KeyboardMode::DecodedLockout => {
+                // Two key lockout ignores a key pressed while another is held
+                if !self.keys_down.is_empty() {
+                    self.keys_down.push((row, column));
+                    return;
+                }
+            }
+            _ => {
+                if self.special_error && !self.keys_down.is_empty() {
+                    self.status |= STATUS_SENSOR;
+                }
+            }
+        }
+        self.keys_down.push((row, column));
+        let code = (self.control as u8) << 7 | (self.shift as u8) << 6 | row << 3 | column;
+        self.push_fifo(code);
+    }
+
+    pub fn key_up(&mut self, row:u8, column:u8) {
+        let (row, column) = (row & 7, column & 7);
+        self.keys_down.retain(|&key| key != (row, column));
+        if self.keyboard_mode.sensor_matrix() {
+            self.set_sensor(row, column, false);
+        }
+    }
+
+    // Host side: press and release a key
+    pub fn key_press(&mut self, row:u8, column:u8) {
+        self.key_down(row, column);
+        self.key_up(row, column);
+    }
+
+    // Host side: data strobed in on the return lines in strobed input mode
+    pub fn strobe(&mut self, value:u8) {
+        self.push_fifo(value);
+    }
+
+    fn set_sensor(&mut self, row:u8, column:u8, closed:bool) {
+        let before = self.sensor[row as usize];
+        if closed {
+            self.sensor[row as usize] |= 1 << column;
+        } else {
+            self.sensor[row as usize] &= !(1 << column);
+        }
+        if self.sensor[row as usize] != before {
+            self.status |= STATUS_SENSOR;
+            self.irq = true;
+        }
+    }
+
+    pub fn status(&self) -> u8 {
+        let mut status = self.status & (STATUS_UNDERRUN | STATUS_OVERRUN | STATUS_SENSOR);
+        if self.keyboard_mode.sensor_matrix() {
+            status &= !(STATUS_UNDERRUN | STATUS_OVERRUN);
+        } else {
+            status |= self.fifo.len() as u8 & 0x07;
+            if self.fifo.len() >= FIFO_SIZE {
+                status |= STATUS_FULL;
+            }
+        }
+        status
+    }
+
+    fn write_display(&mut self, value:u8) {
+        let size = self.display_chars;
+        let mask = match self.inhibit & 0x03 {
+            0 => 0xFF,
+            1 => 0xF0,
+            2 => 0x0F,
+            _ => 0x00,
+        };
+
+        if self.right_entry {
+            self.display.copy_within(1..size, 0);
+            self.display[size - 1] = (self.display[size - 1] & !mask) | (value & mask);
+            return;
+        }
+
+        let address = self.display_address % size;
+        self.display[address] = (self.display[address] & !mask) | (value & mask);
+        if self.display_auto_increment {
+            self.display_address = (address + 1) % size;
+        }
+    }
+
+    fn clear(&mut self, value:u8) {
+        // CD1:CD0 pick the blank code, CD2 or CA clear the display with it
+        self.blank_code = match (value >> 2) & 0x03 {
+            0b10 => 0x20,
+            0b11 => 0xFF,
+            _ => 0x00,
+        };
+        let clear_display = value & 0x10 != 0 || value & 0x01 != 0;
+        if clear_display {
+            self.display = [self.blank_code; 16];
+            self.display_address = 0;
+        }
+        if value & 0x02 != 0 || value & 0x01 != 0 {
+            self.fifo.clear();
+            self.status = 0;
+            self.irq = false;
+            self.sensor_address = 0;
+        }
+    }
+
+    fn write_command(&mut self, value:u8) {
+        match value >> 5 {
+            0 => {
+                self.keyboard_mode = KeyboardMode::from_bits(value);
+                self.display_chars = if value & 0x08 != 0 { 16 } else { 8 };
+                self.right_entry = value & 0x10 != 0;
+                self.keys_down.clear();
+            }
+            1 => self.prescaler = value & 0x1F,
+            2 => {
+                self.read_target = ReadTarget::Fifo;
+                self.read_auto_increment = value & 0x10 != 0;
+                self.sensor_address = (value & 0x07) as usize;
+            }
+            3 => {
+                self.read_target = ReadTarget::Display;
+                self.display_auto_increment = value & 0x10 != 0;
+                self.display_address = (value & 0x0F) as usize;
+            }
+            4 => {
+                self.display_auto_increment = value & 0x10 != 0;
+                self.display_address = (value & 0x0F) as usize;
+            }
+            5 => {
+                self.inhibit = (value >> 2) & 0x03;
+                self.blank = value & 0x03;
+            }
+            6 => self.clear(value),
+            _ => {
+                // End interrupt / error mode set
+                if self.keyboard_mode.sensor_matrix() {
+                    self.irq = false;
+                    self.status &= !STATUS_SENSOR;
+                }
+                self.special_error = value & 0x10 != 0;
+            }
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        match self.read_target {
+            ReadTarget::Display => {
+                let address = self.display_address % self.display_chars;
+                if self.display_auto_increment {
+                    self.display_address = (address + 1) % self.display_chars;
+                }
+                self.display[address]
+            }
+            ReadTarget::Fifo if self.keyboard_mode.sensor_matrix() => {
+                let value = self.sensor[self.sensor_address];
+                if self.read_auto_increment {
+                    self.sensor_address = (self.sensor_address + 1) & 7;
+                } else {
+                    self.irq = false;
+                }
+                value
+            }
+            ReadTarget::Fifo => match self.fifo.pop_front() {
+                Some(value) => {
+                    self.irq = !self.fifo.is_empty();
+                    value
+                }
+                None => {
+                    self.status |= STATUS_UNDERRUN;
+                    0xFF
+                }
+            },
+        }
+    }
+}
+
+impl IoDevice for Kdc8279 {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        if port & 1 != 0 {
+            self.status()
+        } else {
+            self.read_data()
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        if port & 1 != 0 {
+            self.write_command(value);
+        } else {
+            self.write_display(value);
+        }
+    }
+}
+
+// The IRQ pin as an input for an interrupt controller
+pub struct KdcInterrupt {
+    kdc:Rc<RefCell<Kdc8279>>,
+}
+
+impl KdcInterrupt {
+    pub fn new(kdc:Rc<RefCell<Kdc8279>>) -> Self {
+        Self { kdc }
+    }
+}
+
+impl InterruptLine for KdcInterrupt {
+    fn level(&mut self, _cycles:u32) -> bool {
+        self.kdc.borrow().irq()
+    }
+}
@@ -0,0 +1,30 @@
+// Fujitsu MB14241 shift register, as used on Midway's 8080 boards (Space
+// Invaders and friends) to shift sprites into position
+// Each data write pushes a byte into the top of a 16 bit register, and the
+// result is the 8 bits that start `amount` bits below its top. How the three
+// operations map onto ports depends on the board, so the chip is driven
+// through methods rather than attached to the I/O bus directly.
+
+#[derive(Default)]
+pub struct Mb14241 {
+    register:u16,
+    amount:u8,
+}
+
+impl Mb14241 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_amount(&mut self, value:u8) {
+        self.amount = value & 0x07;
+    }
+
+    pub fn write_data(&mut self, value:u8) {
+        self.register = (value as u16) << 8 | self.register >> 8;
+    }
+
+    pub fn result(&self) -> u8 {
+        (self.register >> (8 - self.amount)) as u8
+    }
+}
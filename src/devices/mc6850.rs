@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::interrupt::InterruptLine;
+use crate::io::IoDevice;
+
+// Motorola 6850 ACIA
+// Port offset 0 is control (write) / status (read), offset 1 is the data
+// register, matching RS to A0. Characters move without line delay: a byte
+// queued by the host is in the receive register as soon as the previous one
+// has been read, and transmitted bytes reach the host immediately.
+
+// Status register bits
+pub const STATUS_RDRF:u8 = 0x01;
+pub const STATUS_TDRE:u8 = 0x02;
+pub const STATUS_DCD:u8 = 0x04;
+pub const STATUS_CTS:u8 = 0x08;
+pub const STATUS_FE:u8 = 0x10;
+pub const STATUS_OVRN:u8 = 0x20;
+pub const STATUS_PE:u8 = 0x40;
+pub const STATUS_IRQ:u8 = 0x80;
+
+const CONTROL_MASTER_RESET:u8 = 0x03;
+const CONTROL_RX_INT:u8 = 0x80;
+
+pub struct Acia6850 {
+    pub control:u8,
+    in_reset:bool,
+
+    rx_data:u8,
+    rx_ready:bool,
+
+    pub dcd:bool, // Data carrier lost when true, reported in status bit 2
+    pub cts:bool, // Clear to send input, reported inverted in status bit 3
+
+    to_host:VecDeque<u8>,
+    from_host:VecDeque<u8>,
+}
+
+impl Default for Acia6850 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Acia6850 {
+    pub fn new() -> Self {
+        Self {
+            control: CONTROL_MASTER_RESET,
+            in_reset: true,
+            rx_data: 0,
+            rx_ready: false,
+            dcd: false,
+            cts: true,
+            to_host: VecDeque::new(),
+            from_host: VecDeque::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.control = CONTROL_MASTER_RESET;
+        self.in_reset = true;
+        self.rx_ready = false;
+    }
+
+    fn char_mask(&self) -> u8 {
+        // Word select 0-3 are 7 bit formats, 4-7 are 8 bit
+        if self.control & 0x10 != 0 {
+            0xFF
+        } else {
+            0x7F
+        }
+    }
+
+    fn rx_interrupt_enabled(&self) -> bool {
+        self.control & CONTROL_RX_INT != 0
+    }
+
+    fn tx_interrupt_enabled(&self) -> bool {
+        (self.control >> 5) & 0x03 == 0x01
+    }
+
+    // Moves the next host byte into the receive register once it's free
+    fn update(&mut self) {
+        if self.in_reset || self.rx_ready {
+            return;
+        }
+        if let Some(byte) = self.from_host.pop_front() {
+            self.rx_data = byte & self.char_mask();
+            self.rx_ready = true;
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        if self.in_reset {
+            return false;
+        }
+        (self.rx_interrupt_enabled() && self.rx_ready) || (self.tx_interrupt_enabled() && self.cts)
+    }
+
+    pub fn status(&self) -> u8 {
+        if self.in_reset {
+            return 0;
+        }
+        let mut status = 0;
+        if self.rx_ready {
+            status |= STATUS_RDRF;
+        }
+        if self.cts {
+            status |= STATUS_TDRE;
+        } else {
+            status |= STATUS_CTS;
+        }
+        if self.dcd {
+            status |= STATUS_DCD;
+        }
+        if self.irq() {
+            status |= STATUS_IRQ;
+        }
+        status
+    }
+
+    // RTS output, low unless the transmit control bits are 10
+    pub fn rts(&self) -> bool {
+        (self.control >> 5) & 0x03 != 0x02
+    }
+
+    pub fn sending_break(&self) -> bool {
+        (self.control >> 5) & 0x03 == 0x03
+    }
+
+    // Host side: queue bytes for the 8080 program to receive
+    pub fn send(&mut self, byte:u8) {
+        self.from_host.push_back(byte);
+        self.update();
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        self.from_host.extend(bytes);
+        self.update();
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.from_host.len() + self.rx_ready as usize
+    }
+
+    // Host side: take a byte the 8080 program has transmitted
+    pub fn receive(&mut self) -> Option<u8> {
+        self.to_host.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.to_host.drain(..).collect()
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let value = self.rx_data;
+        self.rx_ready = false;
+        self.update();
+        value
+    }
+
+    pub fn write_data(&mut self, value:u8) {
+        if self.in_reset || !self.cts {
+            return;
+        }
+        self.to_host.push_back(value & self.char_mask());
+    }
+
+    pub fn write_control(&mut self, value:u8) {
+        self.control = value;
+        if value & 0x03 == CONTROL_MASTER_RESET {
+            self.in_reset = true;
+            self.rx_ready = false;
+            return;
+        }
+        self.in_reset = false;
+        self.update();
+    }
+}
+
+impl IoDevice for Acia6850 {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        match port & 1 {
+            0 => self.status(),
+            _ => self.read_data(),
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        match port & 1 {
+            0 => self.write_control(value),
+            _ => self.write_data(value),
+        }
+    }
+}
+
+// The IRQ pin as an input for an interrupt controller
+pub struct AciaInterrupt {
+    acia:Rc<RefCell<Acia6850>>,
+}
+
+impl AciaInterrupt {
+    pub fn new(acia:Rc<RefCell<Acia6850>>) -> Self {
+        Self { acia }
+    }
+}
+
+impl InterruptLine for AciaInterrupt {
+    fn level(&mut self, _cycles:u32) -> bool {
+        self.acia.borrow().irq()
+    }
+}
@@ -0,0 +1,274 @@
+use crate::disk::{DiskGeometry, DiskImage};
+use crate::io::IoDevice;
+
+// MITS 88-DCDD Altair floppy disk controller
+// Normally at ports 0x08-0x0A: offset 0 is drive select (out) / status (in),
+// offset 1 is disk control (out) / sector position (in), offset 2 is the
+// data port. Status bits are active low on the bus. The sector position
+// advances every time it is polled, so software waiting for a sector never
+// has to burn real rotation time. Images are the standard .DSK layout of 77
+// tracks x 32 sectors x 137 bytes.
+
+pub const DRIVES:usize = 16;
+
+// Status flags, inverted when read
+pub const STATUS_ENWD:u8 = 0x01; // Enter new write data
+pub const STATUS_MOVE_HEAD:u8 = 0x02;
+pub const STATUS_HEAD:u8 = 0x04; // Head loaded
+const STATUS_UNUSED:u8 = 0x18; // Always read as 0
+pub const STATUS_INTE:u8 = 0x20;
+pub const STATUS_TRACK0:u8 = 0x40;
+pub const STATUS_NRDA:u8 = 0x80; // New read data available
+
+// Disk control bits
+const CONTROL_STEP_IN:u8 = 0x01;
+const CONTROL_STEP_OUT:u8 = 0x02;
+const CONTROL_HEAD_LOAD:u8 = 0x04;
+const CONTROL_HEAD_UNLOAD:u8 = 0x08;
+const CONTROL_INT_ENABLE:u8 = 0x10;
+const CONTROL_INT_DISABLE:u8 = 0x20;
+const CONTROL_WRITE_ENABLE:u8 = 0x80;
+
+pub struct Mits88Dcdd {
+    pub drives:Vec<Option<DiskImage>>,
+    selected:Option<usize>,
+    flags:[u8; DRIVES],
+    track:[usize; DRIVES],
+
+    sector:Option<u8>,
+    buffer:Vec<u8>,
+    position:usize,
+    buffer_loaded:bool,
+    dirty:bool,
+}
+
+impl Default for Mits88Dcdd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mits88Dcdd {
+    pub fn new() -> Self {
+        Self {
+            drives: (0..DRIVES).map(|_| None).collect(),
+            selected: None,
+            flags: [0; DRIVES],
+            track: [0; DRIVES],
+            sector: None,
+            buffer: vec![0; DiskGeometry::ALTAIR_8.sector_size],
+            position: 0,
+            buffer_loaded: false,
+            dirty: false,
+        }
+    }
+
+    pub fn insert(&mut self, drive:usize, image:DiskImage) {
+        self.drives[drive % DRIVES] = Some(image);
+    }
+
+    pub fn eject(&mut self, drive:usize) -> Option<DiskImage> {
+        self.flush();
+        self.drives[drive % DRIVES].take()
+    }
+
+    // Host side: the selected drive and the head position on it
+    pub fn position(&self) -> Option<(usize, usize, Option<u8>)> {
+        self.selected.map(|drive| (drive, self.track[drive], self.sector))
+    }
+
+    fn sector_size(&self) -> usize {
+        self.selected
+            .and_then(|drive| self.drives[drive].as_ref())
+            .map(|disk| disk.geometry.sector_size)
+            .unwrap_or(DiskGeometry::ALTAIR_8.sector_size)
+    }
+
+    fn sectors(&self) -> u8 {
+        self.selected
+            .and_then(|drive| self.drives[drive].as_ref())
+            .map(|disk| disk.geometry.sectors as u8)
+            .unwrap_or(32)
+    }
+
+    // Writes back a partially or fully written sector
+    fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        if let (Some(drive), Some(sector)) = (self.selected, self.sector) {
+            let track = self.track[drive];
+            if let Some(disk) = self.drives[drive].as_mut() {
+                disk.write_sector(track, 0, sector, &self.buffer);
+            }
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.flush();
+        self.buffer_loaded = false;
+        self.position = 0;
+    }
+
+    fn update_track0(&mut self, drive:usize) {
+        if self.track[drive] == 0 {
+            self.flags[drive] |= STATUS_TRACK0;
+        } else {
+            self.flags[drive] &= !STATUS_TRACK0;
+        }
+    }
+
+    fn select(&mut self, value:u8) {
+        self.invalidate();
+        if value & 0x80 != 0 {
+            if let Some(drive) = self.selected.take() {
+                self.flags[drive] = 0;
+            }
+            return;
+        }
+
+        let drive = (value & 0x0F) as usize;
+        if self.drives[drive].is_none() {
+            // Selecting an empty drive leaves nothing selected
+            self.selected = None;
+            return;
+        }
+        self.selected = Some(drive);
+        self.flags[drive] = STATUS_MOVE_HEAD | STATUS_UNUSED;
+        self.sector = None;
+        self.update_track0(drive);
+    }
+
+    fn control(&mut self, value:u8) {
+        let drive = match self.selected {
+            Some(drive) => drive,
+            None => return,
+        };
+        let tracks = self.drives[drive].as_ref().map(|disk| disk.geometry.tracks).unwrap_or(77);
+
+        if value & (CONTROL_STEP_IN | CONTROL_STEP_OUT) != 0 {
+            self.invalidate();
+            self.sector = None;
+            if value & CONTROL_STEP_IN != 0 {
+                self.track[drive] = (self.track[drive] + 1).min(tracks - 1);
+            }
+            if value & CONTROL_STEP_OUT != 0 {
+                self.track[drive] = self.track[drive].saturating_sub(1);
+            }
+            self.update_track0(drive);
+        }
+
+        if value & CONTROL_HEAD_LOAD != 0 {
+            self.flags[drive] |= STATUS_HEAD | STATUS_NRDA;
+        }
+        if value & CONTROL_HEAD_UNLOAD != 0 {
+            self.invalidate();
+            self.flags[drive] &= !(STATUS_HEAD | STATUS_NRDA);
+            self.sector = None;
+        }
+        if value & CONTROL_INT_ENABLE != 0 {
+            self.flags[drive] |= STATUS_INTE;
+        }
+        if value & CONTROL_INT_DISABLE != 0 {
+            self.flags[drive] &= !STATUS_INTE;
+        }
+        if value & CONTROL_WRITE_ENABLE != 0 {
+            self.invalidate();
+            self.buffer.iter_mut().for_each(|byte| *byte = 0);
+            self.flags[drive] |= STATUS_ENWD;
+        }
+    }
+
+    fn sector_position(&mut self) -> u8 {
+        let drive = match self.selected {
+            Some(drive) => drive,
+            None => return 0xFF,
+        };
+        if self.flags[drive] & STATUS_HEAD == 0 {
+            return 0xFF;
+        }
+
+        self.invalidate();
+        let next = match self.sector {
+            Some(sector) => (sector + 1) % self.sectors(),
+            None => 0,
+        };
+        self.sector = Some(next);
+        // Bit 0 low = sector true
+        0xC0 | ((next << 1) & 0x3E)
+    }
+
+    fn load_buffer(&mut self) {
+        let size = self.sector_size();
+        self.buffer = vec![0; size];
+        if let (Some(drive), Some(sector)) = (self.selected, self.sector) {
+            let track = self.track[drive];
+            if let Some(data) = self.drives[drive].as_ref().and_then(|disk| disk.read_sector(track, 0, sector)) {
+                self.buffer.copy_from_slice(data);
+            }
+        }
+        self.buffer_loaded = true;
+        self.position = 0;
+    }
+
+    fn read_data(&mut self) -> u8 {
+        if self.selected.is_none() {
+            return 0xFF;
+        }
+        if !self.buffer_loaded {
+            self.load_buffer();
+        }
+        let value = self.buffer.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        value
+    }
+
+    fn write_data(&mut self, value:u8) {
+        let drive = match self.selected {
+            Some(drive) => drive,
+            None => return,
+        };
+        if self.flags[drive] & STATUS_ENWD == 0 {
+            return;
+        }
+        let size = self.sector_size();
+        if self.buffer.len() != size {
+            self.buffer = vec![0; size];
+        }
+        if self.position < size {
+            self.buffer[self.position] = value;
+            self.position += 1;
+            self.dirty = true;
+        }
+        if self.position >= size {
+            self.flush();
+            self.flags[drive] &= !STATUS_ENWD;
+        }
+    }
+
+    fn status(&self) -> u8 {
+        match self.selected {
+            Some(drive) => !self.flags[drive],
+            None => 0xFF,
+        }
+    }
+}
+
+impl IoDevice for Mits88Dcdd {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        match port {
+            0 => self.status(),
+            1 => self.sector_position(),
+            _ => self.read_data(),
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        match port {
+            0 => self.select(value),
+            1 => self.control(value),
+            _ => self.write_data(value),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::devices::mc6850::Acia6850;
+use crate::interrupt::InterruptLine;
+use crate::io::IoDevice;
+
+// MITS 88-SIO and 88-2SIO Altair serial boards
+// Both are attached at whatever base the board's address jumpers select.
+// The 88-SIO uses two ports: offset 0 is status (read) / interrupt control
+// (write), offset 1 is data. The 88-2SIO carries two 6850 ACIAs at offsets
+// 0-1 and 2-3, each laid out as control/status then data.
+
+// Usual jumper settings
+pub const SIO_BASE:u8 = 0x00;
+pub const ACR_BASE:u8 = 0x06; // 88-ACR cassette interface, an 88-SIO underneath
+pub const TWO_SIO_BASE:u8 = 0x10;
+
+// Interrupt control bits written to the 88-SIO status port
+const SIO_RX_INT:u8 = 0x01;
+const SIO_TX_INT:u8 = 0x02;
+
+// 88-SIO status bits. The board reports them active low; the polarity and
+// positions can be changed to match boards strapped differently.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SioStatus {
+    pub rx_ready:u8,
+    pub tx_ready:u8,
+    pub active_low:bool,
+}
+
+impl SioStatus {
+    pub const MITS:SioStatus = SioStatus { rx_ready: 0x01, tx_ready: 0x80, active_low: true };
+}
+
+pub struct Mits88Sio {
+    pub status_bits:SioStatus,
+    interrupt_control:u8,
+
+    rx_data:u8,
+    rx_ready:bool,
+
+    to_host:VecDeque<u8>,
+    from_host:VecDeque<u8>,
+}
+
+impl Default for Mits88Sio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mits88Sio {
+    pub fn new() -> Self {
+        Self::with_status(SioStatus::MITS)
+    }
+
+    pub fn with_status(status_bits:SioStatus) -> Self {
+        Self {
+            status_bits,
+            interrupt_control: 0,
+            rx_data: 0,
+            rx_ready: false,
+            to_host: VecDeque::new(),
+            from_host: VecDeque::new(),
+        }
+    }
+
+    fn update(&mut self) {
+        if self.rx_ready {
+            return;
+        }
+        if let Some(byte) = self.from_host.pop_front() {
+            self.rx_data = byte;
+            self.rx_ready = true;
+        }
+    }
+
+    pub fn status(&self) -> u8 {
+        let bits = self.status_bits;
+        // Output is always ready since transmitted bytes go straight out
+        let mut status = bits.tx_ready;
+        if self.rx_ready {
+            status |= bits.rx_ready;
+        }
+        if bits.active_low {
+            status ^= bits.rx_ready | bits.tx_ready;
+        }
+        status
+    }
+
+    pub fn irq(&self) -> bool {
+        (self.interrupt_control & SIO_RX_INT != 0 && self.rx_ready) || self.interrupt_control & SIO_TX_INT != 0
+    }
+
+    // Host side: queue bytes for the 8080 program to receive
+    pub fn send(&mut self, byte:u8) {
+        self.from_host.push_back(byte);
+        self.update();
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        self.from_host.extend(bytes);
+        self.update();
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.from_host.len() + self.rx_ready as usize
+    }
+
+    // Host side: take a byte the 8080 program has transmitted
+    pub fn receive(&mut self) -> Option<u8> {
+        self.to_host.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.to_host.drain(..).collect()
+    }
+}
+
+impl IoDevice for Mits88Sio {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        match port & 1 {
+            0 => self.status(),
+            _ => {
+                let value = self.rx_data;
+                self.rx_ready = false;
+                self.update();
+                value
+            }
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        match port & 1 {
+            0 => self.interrupt_control = value & (SIO_RX_INT | SIO_TX_INT),
+            _ => self.to_host.push_back(value),
+        }
+    }
+}
+
+pub struct Mits88TwoSio {
+    pub channels:[Acia6850; 2],
+}
+
+impl Default for Mits88TwoSio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mits88TwoSio {
+    pub fn new() -> Self {
+        Self { channels: [Acia6850::new(), Acia6850::new()] }
+    }
+
+    // Channel 0 is the console port on most Altair setups
+    pub fn channel(&mut self, channel:usize) -> &mut Acia6850 {
+        &mut self.channels[channel & 1]
+    }
+}
+
+impl IoDevice for Mits88TwoSio {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        self.channels[(port >> 1) as usize & 1].input(port & 1, cycles)
+    }
+
+    fn output(&mut self, port:u8, value:u8, cycles:u32) {
+        self.channels[(port >> 1) as usize & 1].output(port & 1, value, cycles);
+    }
+}
+
+// Interrupt output of an 88-SIO
+pub struct SioInterrupt {
+    sio:Rc<RefCell<Mits88Sio>>,
+}
+
+impl SioInterrupt {
+    pub fn new(sio:Rc<RefCell<Mits88Sio>>) -> Self {
+        Self { sio }
+    }
+}
+
+impl InterruptLine for SioInterrupt {
+    fn level(&mut self, _cycles:u32) -> bool {
+        self.sio.borrow().irq()
+    }
+}
+
+// Interrupt output of one 88-2SIO channel
+pub struct TwoSioInterrupt {
+    sio:Rc<RefCell<Mits88TwoSio>>,
+    channel:usize,
+}
+
+impl TwoSioInterrupt {
+    pub fn new(sio:Rc<RefCell<Mits88TwoSio>>, channel:usize) -> Self {
+        Self { sio, channel: channel & 1 }
+    }
+}
+
+impl InterruptLine for TwoSioInterrupt {
+    fn level(&mut self, _cycles:u32) -> bool {
+        self.sio.borrow().channels[self.channel].irq()
+    }
+}
@@ -0,0 +1,26 @@
+// Peripheral chips that plug into the CPU's I/O port bus
+pub mod i8251;
+pub mod i8253;
+pub mod i8255;
+pub mod i8257;
+pub mod i8259;
+pub mod i8275;
+pub mod i8279;
+pub mod mb14241;
+pub mod mc6850;
+pub mod mits_88dcdd;
+pub mod mits_88sio;
+pub mod wd179x;
+
+pub use i8251::Usart8251;
+pub use i8253::{Pit8253, TimerInterrupt, TimerOut};
+pub use i8255::{Ppi8255, PpiInterrupt, PpiPort};
+pub use i8257::Dma8257;
+pub use i8259::Pic8259;
+pub use i8275::{Crt8275, CrtInterrupt};
+pub use i8279::{Kdc8279, KdcInterrupt};
+pub use mb14241::Mb14241;
+pub use mc6850::{Acia6850, AciaInterrupt};
+pub use mits_88dcdd::Mits88Dcdd;
+pub use mits_88sio::{Mits88Sio, Mits88TwoSio, SioInterrupt, SioStatus, TwoSioInterrupt};
+pub use wd179x::{Fdc179x, FdcInterrupt, FdcModel};
@@ -0,0 +1,600 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::disk::DiskImage;
+use crate::dma::DmaPeripheral;
+use crate::interrupt::InterruptLine;
+use crate::io::{cycles_since, IoDevice};
+
+// Western Digital WD1771 / FD179x floppy disk controller
+// Port offsets 0-3 are status/command, track, sector and data. Offset 4 is
+// the board's drive select latch in this model: bits 0-1 select the drive,
+// bit 2 selects side 1. All mechanical delays (stepping, head settling,
+// rotation and byte transfer) are counted in CPU cycles, and a data byte the
+// CPU doesn't take or supply within a byte time of DRQ is lost.
+
+pub const STATUS_BUSY:u8 = 0x01;
+pub const STATUS_INDEX:u8 = 0x02; // Type I
+pub const STATUS_DRQ:u8 = 0x02; // Type II / III
+pub const STATUS_TRACK0:u8 = 0x04; // Type I
+pub const STATUS_LOST_DATA:u8 = 0x04; // Type II / III
+pub const STATUS_CRC_ERROR:u8 = 0x08;
+pub const STATUS_SEEK_ERROR:u8 = 0x10; // Type I
+pub const STATUS_NOT_FOUND:u8 = 0x10; // Type II / III
+pub const STATUS_HEAD_LOADED:u8 = 0x20; // Type I
+pub const STATUS_WRITE_PROTECT:u8 = 0x40;
+pub const STATUS_NOT_READY:u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FdcModel {
+    Wd1771,
+    Fd1791, // inverted data bus
+    Fd1793,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ReadKind {
+    Sector { multiple:bool },
+    Address,
+    Track,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WriteKind {
+    Sector { multiple:bool },
+    Track,
+}
+
+enum Phase {
+    Idle,
+    Seek { done_at:u32 },
+    Read { kind:ReadKind, data:Vec<u8>, pos:usize, next_at:u32 },
+    Write { kind:WriteKind, data:Vec<u8>, len:usize, next_at:u32 },
+}
+
+pub struct Fdc179x {
+    pub model:FdcModel,
+    cpu_hz:u32,
+    pub byte_us:u32, // time to transfer one byte, 32 for 8" FM
+    pub rpm:u32,
+
+    pub drives:[Option<DiskImage>; 4],
+    pub selected:usize,
+    pub side:usize,
+    head_track:[usize; 4],
+
+    pub track:u8,
+    pub sector:u8,
+    pub data:u8,
+    command:u8,
+    type_one:bool,
+    step_out:bool,
+
+    phase:Phase,
+    flags:u8, // sticky error bits of the last command
+    drq:bool,
+    intrq:bool,
+    head_loaded:bool,
+    interrupt_on_index:bool,
+}
+
+impl Fdc179x {
+    pub fn new(model:FdcModel, cpu_hz:u32) -> Self {
+        Self {
+            model,
+            cpu_hz,
+            byte_us: 32,
+            rpm: 360,
+            drives: Default::default(),
+            selected: 0,
+            side: 0,
+            head_track: [0; 4],
+            track: 0,
+            sector: 1,
+            data: 0,
+            command: 0,
+            type_one: true,
+            step_out: false,
+            phase: Phase::Idle,
+            flags: 0,
+            drq: false,
+            intrq: false,
+            head_loaded: false,
+            interrupt_on_index: false,
+        }
+    }
+
+    pub fn insert(&mut self, drive:usize, image:DiskImage) {
+        self.drives[drive & 3] = Some(image);
+    }
+
+    pub fn eject(&mut self, drive:usize) -> Option<DiskImage> {
+        self.drives[drive & 3].take()
+    }
+
+    pub fn select(&mut self, drive:usize, side:usize) {
+        self.selected = drive & 3;
+        self.side = side & 1;
+    }
+
+    pub fn intrq(&self) -> bool {
+        self.intrq
+    }
+
+    pub fn drq(&self) -> bool {
+        self.drq
+    }
+
+    pub fn busy(&self) -> bool {
+        !matches!(self.phase, Phase::Idle)
+    }
+
+    fn ms(&self, ms:u32) -> u32 {
+        (self.cpu_hz as u64 * ms as u64 / 1000) as u32
+    }
+
+    fn byte_cycles(&self) -> u32 {
+        (self.cpu_hz as u64 * self.byte_us as u64 / 1_000_000).max(1) as u32
+    }
+
+    fn revolution_cycles(&self) -> u32 {
+        (self.cpu_hz as u64 * 60 / self.rpm.max(1) as u64).max(1) as u32
+    }
+
+    fn step_ms(&self) -> u32 {
+        let rates = match self.model {
+            FdcModel::Wd1771 => [6, 6, 10, 20],
+            _ => [6, 12, 20, 30],
+        };
+        rates[(self.command & 0x03) as usize]
+    }
+
+    fn disk(&self) -> Option<&DiskImage> {
+        self.drives[self.selected].as_ref()
+    }
+
+    fn head(&self) -> usize {
+        self.head_track[self.selected]
+    }
+
+    fn index_pulse(&self, cycles:u32) -> bool {
+        self.disk().is_some() && cycles % self.revolution_cycles() < self.ms(4).max(1)
+    }
+
+    pub fn status(&self, cycles:u32) -> u8 {
+        let mut status = self.flags;
+        if self.disk().is_none() {
+            status |= STATUS_NOT_READY;
+        }
+        if self.busy() {
+            status |= STATUS_BUSY;
+        }
+
+        if self.type_one {
+            if self.disk().is_some_and(|disk| disk.write_protected) {
+                status |= STATUS_WRITE_PROTECT;
+            }
+            if self.head_loaded {
+                status |= STATUS_HEAD_LOADED;
+            }
+            if self.head() == 0 {
+                status |= STATUS_TRACK0;
+            }
+            if self.index_pulse(cycles) {
+                status |= STATUS_INDEX;
+            }
+        } else if self.drq {
+            status |= STATUS_DRQ;
+        }
+        status
+    }
+
+    fn finish(&mut self) {
+        self.phase = Phase::Idle;
+        self.drq = false;
+        self.intrq = true;
+    }
+
+    // Bring the current command up to date with the cycle counter
+    pub fn update(&mut self, cycles:u32) {
+        if self.interrupt_on_index && self.index_pulse(cycles) {
+            self.intrq = true;
+        }
+
+        let byte_cycles = self.byte_cycles();
+        let mut write_done = false;
+        match &mut self.phase {
+            Phase::Idle => {}
+            Phase::Seek { done_at } => {
+                if cycles_since(cycles, *done_at) >= 0 {
+                    self.finish();
+                }
+            }
+            Phase::Read { data, pos, next_at, .. } => {
+                if *pos >= data.len() {
+                    self.finish();
+                } else if cycles_since(cycles, *next_at) >= 0 {
+                    // Bytes the CPU didn't take in time are overwritten by the
+                    // ones after them
+                    let missed = (cycles_since(cycles, *next_at) as u32 / byte_cycles) as usize;
+                    if missed > 0 {
+                        self.flags |= STATUS_LOST_DATA;
+                        *pos = (*pos + missed).min(data.len() - 1);
+                        *next_at = next_at.wrapping_add(missed as u32 * byte_cycles);
+                    }
+                    self.data = data[*pos];
+                    self.drq = true;
+                }
+            }
+            Phase::Write { data, len, next_at, .. } => {
+                if cycles_since(cycles, *next_at) >= 0 {
+                    // Zeros are written for bytes the CPU didn't supply in time
+                    let missed = (cycles_since(cycles, *next_at) as u32 / byte_cycles) as usize;
+                    if missed > 0 {
+                        self.flags |= STATUS_LOST_DATA;
+                        let zeros = missed.min(*len - data.len());
+                        data.resize(data.len() + zeros, 0);
+                        *next_at = next_at.wrapping_add(missed as u32 * byte_cycles);
+                        write_done = data.len() >= *len;
+                    }
+                    self.drq = true;
+                }
+            }
+        }
+        if write_done {
+            self.drq = false;
+            self.end_write(cycles);
+        }
+    }
+
+    fn read_data(&mut self, cycles:u32) -> u8 {
+        self.update(cycles);
+        if !self.drq {
+            return self.data;
+        }
+        self.drq = false;
+
+        let byte_cycles = self.byte_cycles();
+        let (kind, done) = match &mut self.phase {
+            Phase::Read { kind, data, pos, next_at } => {
+                *pos += 1;
+                *next_at = cycles.wrapping_add(byte_cycles);
+                (*kind, *pos >= data.len())
+            }
+            _ => return self.data,
+        };
+
+        if done {
+            match kind {
+                ReadKind::Sector { multiple: true } => {
+                    self.sector = self.sector.wrapping_add(1);
+                    self.start_read_sector(true, cycles);
+                }
+                _ => self.finish(),
+            }
+        }
+        self.data
+    }
+
+    fn write_data(&mut self, value:u8, cycles:u32) {
+        self.update(cycles);
+        self.data = value;
+        if !self.drq {
+            return;
+        }
+        self.drq = false;
+
+        let byte_cycles = self.byte_cycles();
+        let full = match &mut self.phase {
+            Phase::Write { data, len, next_at, .. } => {
+                data.push(value);
+                *next_at = cycles.wrapping_add(byte_cycles);
+                data.len() >= *len
+            }
+            _ => return,
+        };
+        if full {
+            self.end_write(cycles);
+        }
+    }
+
+    // Stores the bytes of a finished write
+    fn end_write(&mut self, cycles:u32) {
+        let (kind, data) = match std::mem::replace(&mut self.phase, Phase::Idle) {
+            Phase::Write { kind, data, .. } => (kind, data),
+            _ => return,
+        };
+        match kind {
+            WriteKind::Sector { multiple } => {
+                let (track, side, sector) = (self.head(), self.side, self.sector);
+                let written = match self.drives[self.selected].as_mut() {
+                    Some(disk) => disk.write_sector(track, side, sector, &data),
+                    None => false,
+                };
+                if !written {
+                    self.flags |= STATUS_NOT_FOUND;
+                    self.finish();
+                } else if multiple {
+                    self.sector = self.sector.wrapping_add(1);
+                    self.start_write_sector(true, cycles);
+                } else {
+                    self.finish();
+                }
+            }
+            WriteKind::Track => {
+                self.format_track(&data);
+                self.finish();
+            }
+        }
+    }
+
+    // Picks sector IDs and data out of a raw FM track written by the program
+    fn format_track(&mut self, raw:&[u8]) {
+        let (track, side) = (self.head(), self.side);
+        let disk = match self.drives[self.selected].as_mut() {
+            Some(disk) => disk,
+            None => return,
+        };
+        let size = disk.geometry.sector_size;
+
+        let mut i = 0;
+        let mut id:Option<u8> = None;
+        while i < raw.len() {
+            match raw[i] {
+                0xFE if i + 4 < raw.len() => {
+                    id = Some(raw[i + 3]);
+                    i += 5;
+                }
+                0xFB | 0xF8 if id.is_some() => {
+                    let end = (i + 1 + size).min(raw.len());
+                    let sector = id.take().unwrap_or(0);
+                    disk.write_sector(track, side, sector, &raw[i + 1..end]);
+                    i = end;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    // With the C flag of a 179x sector command set, the side number in the
+    // ID field has to match the S flag. IDs carry the side they're on.
+    fn side_matches(&self) -> bool {
+        self.model == FdcModel::Wd1771 || self.command & 0x02 == 0 || ((self.command >> 3) & 1) as usize == self.side
+    }
+
+    fn start_read_sector(&mut self, multiple:bool, cycles:u32) {
+        let (track, side, sector) = (self.head(), self.side, self.sector);
+        let data = self.disk().and_then(|disk| disk.read_sector(track, side, sector)).map(|data| data.to_vec());
+        match data {
+            Some(data) if self.track as usize == track && self.side_matches() => {
+                let next_at = cycles.wrapping_add(self.byte_cycles() * 8);
+                self.phase = Phase::Read { kind: ReadKind::Sector { multiple }, data, pos: 0, next_at };
+            }
+            _ => {
+                // A multiple sector read normally ends here, after the last sector
+                self.flags |= STATUS_NOT_FOUND;
+                self.finish();
+            }
+        }
+    }
+
+    fn start_write_sector(&mut self, multiple:bool, cycles:u32) {
+        let (track, side, sector) = (self.head(), self.side, self.sector);
+        let (exists, protected, len) = match self.disk() {
+            Some(disk) => (disk.has_sector(track, side, sector), disk.write_protected, disk.geometry.sector_size),
+            None => (false, false, 0),
+        };
+        if protected {
+            self.flags |= STATUS_WRITE_PROTECT;
+            self.finish();
+        } else if !exists || self.track as usize != track || !self.side_matches() {
+            self.flags |= STATUS_NOT_FOUND;
+            self.finish();
+        } else {
+            let next_at = cycles.wrapping_add(self.byte_cycles() * 2);
+            self.phase = Phase::Write { kind: WriteKind::Sector { multiple }, data: Vec::new(), len, next_at };
+        }
+    }
+
+    fn write_command(&mut self, value:u8, cycles:u32) {
+        self.update(cycles);
+        self.intrq = false;
+
+        // Force interrupt is accepted while busy
+        if value & 0xF0 == 0xD0 {
+            self.phase = Phase::Idle;
+            self.drq = false;
+            self.type_one = true;
+            self.interrupt_on_index = value & 0x04 != 0;
+            if value & 0x08 != 0 {
+                self.intrq = true;
+            }
+            return;
+        }
+        if self.busy() {
+            return;
+        }
+
+        self.command = value;
+        self.flags = 0;
+        self.drq = false;
+
+        if value & 0x80 == 0 {
+            self.type_one_command(value, cycles);
+            return;
+        }
+
+        self.type_one = false;
+        self.head_loaded = true;
+        if self.disk().is_none() {
+            self.finish();
+            return;
+        }
+        let start = if value & 0x04 != 0 { cycles.wrapping_add(self.ms(15)) } else { cycles };
+        match value >> 4 {
+            0x8 | 0x9 => self.start_read_sector(value & 0x10 != 0, start),
+            0xA | 0xB => self.start_write_sector(value & 0x10 != 0, start),
+            0xC => {
+                let track = self.head();
+                let (side, first, size) = match self.disk() {
+                    Some(disk) => (self.side as u8, disk.geometry.first_sector, disk.geometry.sector_size),
+                    None => (0, 1, 128),
+                };
+                let length_code = match size {
+                    256 => 1,
+                    512 => 2,
+                    1024 => 3,
+                    _ => 0,
+                };
+                // The track address ends up in the sector register
+                self.sector = track as u8;
+                let data = vec![track as u8, side, first, length_code, 0, 0];
+                self.phase = Phase::Read { kind: ReadKind::Address, data, pos: 0, next_at: start };
+            }
+            0xE => {
+                let (track, side) = (self.head(), self.side);
+                let mut data = Vec::new();
+                if let Some(disk) = self.disk() {
+                    let first = disk.geometry.first_sector;
+                    for sector in first..first + disk.geometry.sectors as u8 {
+                        if let Some(sector_data) = disk.read_sector(track, side, sector) {
+                            data.extend_from_slice(sector_data);
+                        }
+                    }
+                }
+                self.phase = Phase::Read { kind: ReadKind::Track, data, pos: 0, next_at: start };
+            }
+            _ => {
+                let protected = self.disk().is_some_and(|disk| disk.write_protected);
+                if protected {
+                    self.flags |= STATUS_WRITE_PROTECT;
+                    self.finish();
+                    return;
+                }
+                // One revolution's worth of raw bytes
+                let len = (self.revolution_cycles() / self.byte_cycles()) as usize;
+                self.phase = Phase::Write { kind: WriteKind::Track, data: Vec::new(), len, next_at: start };
+            }
+        }
+    }
+
+    fn type_one_command(&mut self, value:u8, cycles:u32) {
+        self.type_one = true;
+        self.head_loaded = value & 0x08 != 0;
+
+        // Steps the head takes, positive is towards the hub
+        let steps:i32 = match value >> 4 {
+            // Restore, step out until the track 0 sensor
+            0x0 => {
+                self.track = 0;
+                -(self.head() as i32)
+            }
+            // Seek, step until the track register matches the data register
+            0x1 => {
+                let delta = self.data as i32 - self.track as i32;
+                self.track = self.data;
+                delta
+            }
+            // Step, step in, step out
+            step => {
+                match step >> 1 {
+                    2 => self.step_out = false,
+                    3 => self.step_out = true,
+                    _ => {}
+                }
+                let delta = if self.step_out { -1 } else { 1 };
+                if value & 0x10 != 0 {
+                    self.track = (self.track as i32 + delta) as u8;
+                }
+                delta
+            }
+        };
+        if steps != 0 {
+            self.step_out = steps < 0;
+        }
+
+        let max_track = self.disk().map(|disk| disk.geometry.tracks + 3).unwrap_or(80) as i32;
+        let target = (self.head() as i32 + steps).clamp(0, max_track) as usize;
+        self.head_track[self.selected] = target;
+
+        let mut done_at = cycles.wrapping_add(steps.unsigned_abs() * self.ms(self.step_ms()));
+        if value & 0x04 != 0 {
+            // Verify the track register against the ID under the head
+            done_at = done_at.wrapping_add(self.ms(15));
+            let valid = self.disk().is_some_and(|disk| target < disk.geometry.tracks);
+            if !valid || self.track as usize != target {
+                self.flags |= STATUS_SEEK_ERROR;
+            }
+        }
+        self.phase = Phase::Seek { done_at };
+    }
+
+    // The FD1791 has an inverted data bus; the select latch is on the board
+    fn bus(&self, port:u8, value:u8) -> u8 {
+        if self.model == FdcModel::Fd1791 && port & 0x07 < 4 { !value } else { value }
+    }
+}
+
+impl IoDevice for Fdc179x {
+    fn input(&mut self, port:u8, cycles:u32) -> u8 {
+        self.update(cycles);
+        let value = match port & 0x07 {
+            0 => {
+                self.intrq = false;
+                self.status(cycles)
+            }
+            1 => self.track,
+            2 => self.sector,
+            3 => self.read_data(cycles),
+            _ => (self.selected as u8) | (self.side as u8) << 2,
+        };
+        self.bus(port, value)
+    }
+
+    fn output(&mut self, port:u8, value:u8, cycles:u32) {
+        let value = self.bus(port, value);
+        match port & 0x07 {
+            0 => self.write_command(value, cycles),
+            1 => self.track = value,
+            2 => self.sector = value,
+            3 => self.write_data(value, cycles),
+            _ => self.select((value & 0x03) as usize, ((value >> 2) & 1) as usize),
+        }
+    }
+}
+
+impl DmaPeripheral for Fdc179x {
+    fn dreq(&mut self, cycles:u32) -> bool {
+        self.update(cycles);
+        self.drq
+    }
+
+    fn dma_read(&mut self, cycles:u32) -> u8 {
+        let value = self.read_data(cycles);
+        self.bus(3, value)
+    }
+
+    fn dma_write(&mut self, value:u8, cycles:u32) {
+        let value = self.bus(3, value);
+        self.write_data(value, cycles);
+    }
+}
+
+// The INTRQ pin as an input for an interrupt controller
+pub struct FdcInterrupt {
+    fdc:Rc<RefCell<Fdc179x>>,
+}
+
+impl FdcInterrupt {
+    pub fn new(fdc:Rc<RefCell<Fdc179x>>) -> Self {
+        Self { fdc }
+    }
+}
+
+impl InterruptLine for FdcInterrupt {
+    fn level(&mut self, cycles:u32) -> bool {
+        let mut fdc = self.fdc.borrow_mut();
+        fdc.update(cycles);
+        fdc.intrq()
+    }
+}
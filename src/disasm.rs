@@ -0,0 +1,139 @@
+// 8080 disassembler
+// Intel mnemonics in the style of DDT: upper case, hex operands without a
+// suffix. Undocumented opcodes decode as the instruction the 8080 actually
+// runs for them, marked with a '*' (e.g. 0xCB is *JMP).
+
+const REGISTERS:[&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+const PAIRS:[&str; 4] = ["B", "D", "H", "SP"];
+const STACK_PAIRS:[&str; 4] = ["B", "D", "H", "PSW"];
+const CONDITIONS:[&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU:[&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+const ALU_IMMEDIATE:[&str; 8] = ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+const ROTATES:[&str; 8] = ["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    None,
+    Byte(u8),
+    Word(u16),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub mnemonic:String,
+    // Register operands, e.g. "A" or "H"; a value operand follows after a comma
+    pub registers:String,
+    pub operand:Operand,
+    pub length:u16,
+}
+
+impl Instruction {
+    // `symbol` may name a 16 bit operand, e.g. a jump target
+    pub fn format<F:Fn(u16) -> Option<String>>(&self, symbol:F) -> String {
+        let value = match self.operand {
+            Operand::None => String::new(),
+            Operand::Byte(byte) => format!("{:02X}", byte),
+            Operand::Word(word) => symbol(word).map(|name| format!(".{}", name)).unwrap_or_else(|| format!("{:04X}", word)),
+        };
+        let separator = if !self.registers.is_empty() && !value.is_empty() { "," } else { "" };
+        let operands = format!("{}{}{}", self.registers, separator, value);
+        if operands.is_empty() {
+            self.mnemonic.clone()
+        } else {
+            format!("{:<4} {}", self.mnemonic, operands)
+        }
+    }
+
+    // The address a jump or call would go to
+    pub fn target(&self) -> Option<u16> {
+        match (self.operand, self.mnemonic.trim_start_matches('*').as_bytes().first()) {
+            (Operand::Word(word), Some(b'J' | b'C')) => Some(word),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.format(|_| None))
+    }
+}
+
+// Decodes the instruction starting with `bytes[0]`; missing bytes read as 0
+pub fn decode(bytes:&[u8]) -> Instruction {
+    let byte = |index:usize| bytes.get(index).copied().unwrap_or(0);
+    let op = byte(0);
+    let word = byte(1) as u16 | (byte(2) as u16) << 8;
+    let destination = ((op >> 3) & 7) as usize;
+    let source = (op & 7) as usize;
+    let pair = ((op >> 4) & 3) as usize;
+
+    let instruction = |mnemonic:&str, registers:&str, operand:Operand| {
+        let length = match operand {
+            Operand::None => 1,
+            Operand::Byte(_) => 2,
+            Operand::Word(_) => 3,
+        };
+        Instruction { mnemonic: mnemonic.to_string(), registers: registers.to_string(), operand, length }
+    };
+    let none = Operand::None;
+    let immediate = Operand::Byte(byte(1));
+    let address = Operand::Word(word);
+
+    match op {
+        0x76 => instruction("HLT", "", none),
+        0x40..=0x7F => instruction("MOV", &format!("{},{}", REGISTERS[destination], REGISTERS[source]), none),
+        0x80..=0xBF => instruction(ALU[destination], REGISTERS[source], none),
+        0x00..=0x3F => match op & 0x0F {
+            0x00 | 0x08 => instruction(if op == 0x00 { "NOP" } else { "*NOP" }, "", none),
+            0x01 => instruction("LXI", PAIRS[pair], address),
+            0x09 => instruction("DAD", PAIRS[pair], none),
+            0x02 | 0x0A => {
+                let load = op & 0x08 != 0;
+                match pair {
+                    0 | 1 => instruction(if load { "LDAX" } else { "STAX" }, PAIRS[pair], none),
+                    2 => instruction(if load { "LHLD" } else { "SHLD" }, "", address),
+                    _ => instruction(if load { "LDA" } else { "STA" }, "", address),
+                }
+            }
+            0x03 => instruction("INX", PAIRS[pair], none),
+            0x0B => instruction("DCX", PAIRS[pair], none),
+            _ => match source {
+                4 => instruction("INR", REGISTERS[destination], none),
+                5 => instruction("DCR", REGISTERS[destination], none),
+                6 => instruction("MVI", REGISTERS[destination], immediate),
+                _ => instruction(ROTATES[destination], "", none),
+            },
+        },
+        0xC9 => instruction("RET", "", none),
+        0xD9 => instruction("*RET", "", none),
+        0xE9 => instruction("PCHL", "", none),
+        0xF9 => instruction("SPHL", "", none),
+        0xC3 => instruction("JMP", "", address),
+        0xCB => instruction("*JMP", "", address),
+        0xD3 => instruction("OUT", "", immediate),
+        0xDB => instruction("IN", "", immediate),
+        0xE3 => instruction("XTHL", "", none),
+        0xEB => instruction("XCHG", "", none),
+        0xF3 => instruction("DI", "", none),
+        0xFB => instruction("EI", "", none),
+        0xCD => instruction("CALL", "", address),
+        0xDD | 0xED | 0xFD => instruction("*CALL", "", address),
+        _ => match source {
+            0 => instruction(&format!("R{}", CONDITIONS[destination]), "", none),
+            1 => instruction("POP", STACK_PAIRS[pair], none),
+            2 => instruction(&format!("J{}", CONDITIONS[destination]), "", address),
+            4 => instruction(&format!("C{}", CONDITIONS[destination]), "", address),
+            5 => instruction("PUSH", STACK_PAIRS[pair], none),
+            6 => instruction(ALU_IMMEDIATE[destination], "", immediate),
+            _ => instruction("RST", &destination.to_string(), none),
+        },
+    }
+}
+
+// Decodes the instruction at `address` in a 64K memory image, wrapping at
+// the top of memory
+pub fn disassemble(memory:&[u8], address:u16) -> Instruction {
+    let bytes:Vec<u8> = (0..3).map(|offset| memory[address.wrapping_add(offset) as usize % memory.len()]).collect();
+    decode(&bytes)
+}
@@ -0,0 +1,148 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Raw sector disk images: sectors stored back to back, track by track, with
+// both sides of a cylinder together. Writes go straight through to the file
+// the image was opened from.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskGeometry {
+    pub tracks:usize,
+    pub sides:usize,
+    pub sectors:usize, // per track and side
+    pub sector_size:usize,
+    pub first_sector:u8,
+}
+
+impl DiskGeometry {
+    // 8" single sided single density, the standard CP/M distribution format
+    pub const IBM_3740:DiskGeometry = DiskGeometry { tracks: 77, sides: 1, sectors: 26, sector_size: 128, first_sector: 1 };
+    // 5.25" single sided, 18 x 128 byte sectors
+    pub const MINI_SSSD:DiskGeometry = DiskGeometry { tracks: 40, sides: 1, sectors: 18, sector_size: 128, first_sector: 1 };
+    // 5.25" single sided double density, 18 x 256 byte sectors
+    pub const MINI_SSDD:DiskGeometry = DiskGeometry { tracks: 40, sides: 1, sectors: 18, sector_size: 256, first_sector: 1 };
+    // 5.25" double sided double density, 9 x 512 byte sectors
+    pub const MINI_DSDD:DiskGeometry = DiskGeometry { tracks: 40, sides: 2, sectors: 9, sector_size: 512, first_sector: 1 };
+    // MITS Altair 8" floppy, 32 x 137 byte sectors numbered from 0
+    pub const ALTAIR_8:DiskGeometry = DiskGeometry { tracks: 77, sides: 1, sectors: 32, sector_size: 137, first_sector: 0 };
+
+    pub const KNOWN:[DiskGeometry; 5] = [
+        DiskGeometry::IBM_3740,
+        DiskGeometry::MINI_SSSD,
+        DiskGeometry::MINI_SSDD,
+        DiskGeometry::MINI_DSDD,
+        DiskGeometry::ALTAIR_8,
+    ];
+
+    pub fn track_size(&self) -> usize {
+        self.sectors * self.sector_size
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.tracks * self.sides * self.track_size()
+    }
+
+    // Known geometry with exactly `size` bytes
+    pub fn from_size(size:usize) -> Option<DiskGeometry> {
+        DiskGeometry::KNOWN.iter().copied().find(|geometry| geometry.total_size() == size)
+    }
+}
+
+pub struct DiskImage {
+    pub geometry:DiskGeometry,
+    pub write_protected:bool,
+    data:Vec<u8>,
+    file:Option<File>,
+}
+
+impl DiskImage {
+    // Unformatted (0xE5 filled) in-memory image
+    pub fn blank(geometry:DiskGeometry) -> Self {
+        Self::from_bytes(Vec::new(), geometry)
+    }
+
+    // In-memory image, padded with 0xE5 or truncated to the geometry size
+    pub fn from_bytes(mut data:Vec<u8>, geometry:DiskGeometry) -> Self {
+        data.resize(geometry.total_size(), 0xE5);
+        Self { geometry, write_protected: false, data, file: None }
+    }
+
+    // Opens an image file for reading and writing, falling back to read only
+    pub fn open<P:AsRef<Path>>(path:P, geometry:DiskGeometry) -> io::Result<Self> {
+        let (mut file, write_protected) = match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(file) => (file, false),
+            Err(_) => (File::open(&path)?, true),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut image = Self::from_bytes(data, geometry);
+        image.write_protected = write_protected;
+        image.file = Some(file);
+        Ok(image)
+    }
+
+    // Opens an image file, picking the geometry from its size
+    pub fn open_detect<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+        let size = std::fs::metadata(&path)?.len() as usize;
+        let geometry = DiskGeometry::from_size(size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown disk image size {}", size)))?;
+        Self::open(path, geometry)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn offset(&self, track:usize, side:usize, sector:u8) -> Option<usize> {
+        let geometry = &self.geometry;
+        let index = (sector as usize).checked_sub(geometry.first_sector as usize)?;
+        if track >= geometry.tracks || side >= geometry.sides || index >= geometry.sectors {
+            return None;
+        }
+        Some(((track * geometry.sides + side) * geometry.sectors + index) * geometry.sector_size)
+    }
+
+    pub fn has_sector(&self, track:usize, side:usize, sector:u8) -> bool {
+        self.offset(track, side, sector).is_some()
+    }
+
+    pub fn read_sector(&self, track:usize, side:usize, sector:u8) -> Option<&[u8]> {
+        let offset = self.offset(track, side, sector)?;
+        Some(&self.data[offset..offset + self.geometry.sector_size])
+    }
+
+    // Returns false if the sector doesn't exist, the image is write protected
+    // or the backing file couldn't be written
+    pub fn write_sector(&mut self, track:usize, side:usize, sector:u8, data:&[u8]) -> bool {
+        if self.write_protected {
+            return false;
+        }
+        let offset = match self.offset(track, side, sector) {
+            Some(offset) => offset,
+            None => return false,
+        };
+
+        let size = self.geometry.sector_size;
+        let sector_data = &mut self.data[offset..offset + size];
+        let count = data.len().min(size);
+        sector_data[..count].copy_from_slice(&data[..count]);
+        sector_data[count..].fill(0);
+
+        match self.file.as_mut() {
+            Some(file) => {
+                let result = file
+                    .seek(SeekFrom::Start(offset as u64))
+                    .and_then(|_| file.write_all(&self.data[offset..offset + size]));
+                result.is_ok()
+            }
+            None => true,
+        }
+    }
+
+    // Writes the whole image to `path`
+    pub fn save<P:AsRef<Path>>(&self, path:P) -> io::Result<()> {
+        std::fs::write(path, &self.data)
+    }
+}
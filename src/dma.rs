@@ -0,0 +1,19 @@
+// Devices that take the memory bus away from the CPU. Before each
+// instruction the CPU checks whether any attached bus master is asserting
+// HOLD; if so it grants the bus (HLDA) and the master performs its memory
+// cycles directly on RAM, returning how many CPU cycles it kept the bus.
+pub trait BusMaster {
+    fn hold(&mut self, cycles:u32) -> bool;
+    fn bus_granted(&mut self, ram:&mut [u8], cycles:u32) -> u32;
+}
+
+// The peripheral end of a DMA channel (DRQ/DACK)
+pub trait DmaPeripheral {
+    fn dreq(&mut self, cycles:u32) -> bool;
+    // DMA write cycle, the peripheral supplies the byte stored to memory
+    fn dma_read(&mut self, cycles:u32) -> u8;
+    // DMA read cycle, the peripheral receives a byte fetched from memory
+    fn dma_write(&mut self, value:u8, cycles:u32);
+    // Terminal count reached on the channel serving this peripheral
+    fn terminal_count(&mut self, _cycles:u32) {}
+}
@@ -0,0 +1,559 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::machine::{Machine, Stop};
+use crate::CPU;
+
+// GDB remote serial protocol stub
+// Serves one debugger session over a TCP socket or a pipe. Registers are
+// the 16 bit pairs AF BC DE HL SP PC (GDB numbers 0-5), little endian, as
+// described by the target.xml the stub hands out. Breakpoints (Z0, Z1)
+// never touch memory; they are checked against PC before each instruction.
+// The CPU has no bus hooks, so watchpoints (Z2-Z4) work from the memory
+// accesses the instruction at PC is about to make. Guest console output is
+// forwarded to the debugger as 'O' packets.
+
+const REGISTER_COUNT:usize = 6;
+// How often a running target looks for a Ctrl-C from the debugger
+const INTERRUPT_POLL:u64 = 0x4000;
+
+const TARGET_XML:&str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.i8080.cpu">
+    <reg name="af" bitsize="16" type="int"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="data_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// A byte stream to the debugger
+pub trait Connection: Read + Write {
+    // True when the debugger has sent a Ctrl-C since the last call. Must not
+    // block; streams that can't tell just never report one.
+    fn interrupted(&mut self) -> bool {
+        false
+    }
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> bool {
+        let mut byte = [0u8];
+        if self.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let pending = matches!(self.peek(&mut byte), Ok(1)) && byte[0] == 0x03;
+        if pending {
+            let _ = self.read(&mut byte);
+        }
+        let _ = self.set_nonblocking(false);
+        pending
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub kind:WatchKind,
+    pub address:u16,
+    pub length:u16,
+}
+
+// A memory access an instruction makes, apart from its own fetch
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Access {
+    address:u16,
+    length:u16,
+    write:bool,
+}
+
+fn condition(cpu:&CPU, code:u8) -> bool {
+    let flags = cpu.flags();
+    let set = match code >> 1 {
+        0 => flags & 0x40 != 0,
+        1 => flags & 0x01 != 0,
+        2 => flags & 0x04 != 0,
+        _ => flags & 0x80 != 0,
+    };
+    set == (code & 1 != 0)
+}
+
+// The data accesses the instruction at PC will make
+fn accesses(cpu:&CPU) -> Vec<Access> {
+    let op = cpu.ram[cpu.pc as usize];
+    let word = cpu.ram[cpu.pc.wrapping_add(1) as usize] as u16 | (cpu.ram[cpu.pc.wrapping_add(2) as usize] as u16) << 8;
+    let hl = (cpu.h as u16) << 8 | cpu.l as u16;
+    let read = |address:u16, length:u16| Access { address, length, write: false };
+    let write = |address:u16, length:u16| Access { address, length, write: true };
+    let push = write(cpu.sp.wrapping_sub(2), 2);
+    let pop = read(cpu.sp, 2);
+
+    match op {
+        0x02 => vec![write((cpu.b as u16) << 8 | cpu.c as u16, 1)],
+        0x12 => vec![write((cpu.d as u16) << 8 | cpu.e as u16, 1)],
+        0x0A => vec![read((cpu.b as u16) << 8 | cpu.c as u16, 1)],
+        0x1A => vec![read((cpu.d as u16) << 8 | cpu.e as u16, 1)],
+        0x22 => vec![write(word, 2)],
+        0x2A => vec![read(word, 2)],
+        0x32 => vec![write(word, 1)],
+        0x3A => vec![read(word, 1)],
+        0x34 | 0x35 => vec![read(hl, 1), write(hl, 1)],
+        0x36 => vec![write(hl, 1)],
+        0x76 => vec![],
+        0x40..=0x7F if op & 0x07 == 6 => vec![read(hl, 1)],
+        0x70..=0x77 => vec![write(hl, 1)],
+        0x80..=0xBF if op & 0x07 == 6 => vec![read(hl, 1)],
+        0xC9 | 0xD9 | 0xC1 | 0xD1 | 0xE1 | 0xF1 => vec![pop],
+        0xC5 | 0xD5 | 0xE5 | 0xF5 | 0xCD | 0xDD | 0xED | 0xFD => vec![push],
+        0xE3 => vec![pop, write(cpu.sp, 2)],
+        _ => match op & 0x07 {
+            0 if op >= 0xC0 && condition(cpu, (op >> 3) & 7) => vec![pop],
+            4 if op >= 0xC0 && condition(cpu, (op >> 3) & 7) => vec![push],
+            7 if op >= 0xC0 => vec![push],
+            _ => vec![],
+        },
+    }
+}
+
+fn to_hex(bytes:&[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text:&str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok()).collect()
+}
+
+fn parse_number(text:&str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+// "addr,length" as used by m, M, Z and z
+fn parse_range(text:&str) -> Option<(u16, u16)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_number(address)? as u16, parse_number(length)? as u16))
+}
+
+pub struct GdbServer<C:Connection> {
+    pub machine:Machine,
+    pub breakpoints:BTreeSet<u16>,
+    pub watchpoints:Vec<Watchpoint>,
+    connection:C,
+    input:Vec<u8>,
+    no_ack:bool,
+    // The debugger understands swbreak stop reasons
+    swbreak:bool,
+    last_reply:Vec<u8>,
+}
+
+impl<C:Connection> GdbServer<C> {
+    pub fn new(machine:Machine, connection:C) -> Self {
+        Self {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            connection,
+            input: Vec::new(),
+            no_ack: false,
+            swbreak: false,
+            last_reply: Vec::new(),
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.input.is_empty() {
+            let mut buffer = [0u8; 1024];
+            let count = loop {
+                match self.connection.read(&mut buffer) {
+                    Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            if count == 0 {
+                return Ok(None);
+            }
+            self.input.extend_from_slice(&buffer[..count]);
+        }
+        Ok(Some(self.input.remove(0)))
+    }
+
+    // A Ctrl-C that arrived along with the last packet
+    fn buffered_interrupt(&mut self) -> bool {
+        match self.input.iter().position(|byte| *byte == 0x03) {
+            Some(index) => {
+                self.input.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Reads the next packet, acknowledging it. None when the debugger hung up.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let Some(byte) = self.next_byte()? else {
+                return Ok(None);
+            };
+            match byte {
+                b'$' => {}
+                b'-' => {
+                    let reply = self.last_reply.clone();
+                    self.connection.write_all(&reply)?;
+                    self.connection.flush()?;
+                    continue;
+                }
+                // Acks, and Ctrl-C while already stopped
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                let Some(byte) = self.next_byte()? else {
+                    return Ok(None);
+                };
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                data.push(byte);
+            }
+            let mut checksum = [0u8; 2];
+            for digit in checksum.iter_mut() {
+                let Some(byte) = self.next_byte()? else {
+                    return Ok(None);
+                };
+                *digit = byte;
+            }
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+
+            if !self.no_ack {
+                if expected != Some(sum) {
+                    self.connection.write_all(b"-")?;
+                    self.connection.flush()?;
+                    continue;
+                }
+                self.connection.write_all(b"+")?;
+            }
+
+            // Undo the escaping binary data is sent with
+            let mut packet = Vec::with_capacity(data.len());
+            let mut bytes = data.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => packet.push(bytes.next().unwrap_or(0) ^ 0x20),
+                    _ => packet.push(byte),
+                }
+            }
+            return Ok(Some(packet));
+        }
+    }
+
+    fn send(&mut self, data:&[u8]) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for byte in data {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                packet.extend([b'}', byte ^ 0x20]);
+            } else {
+                packet.push(*byte);
+            }
+        }
+        let sum = packet[1..].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet.extend(format!("#{:02x}", sum).bytes());
+        self.connection.write_all(&packet)?;
+        self.connection.flush()?;
+        self.last_reply = packet;
+        Ok(())
+    }
+
+    fn forward_output(&mut self) -> io::Result<()> {
+        let output = self.machine.take_output();
+        if !output.is_empty() {
+            self.send(format!("O{}", to_hex(&output)).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read_register(&self, index:usize) -> Option<u16> {
+        let cpu = &self.machine.cpu;
+        let registers = cpu.registers();
+        match index {
+            0 => Some((registers.a as u16) << 8 | registers.flags as u16),
+            1 => Some(registers.bc()),
+            2 => Some(registers.de()),
+            3 => Some(registers.hl()),
+            4 => Some(registers.sp),
+            5 => Some(cpu.pc),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, index:usize, value:u16) -> bool {
+        let cpu = &mut self.machine.cpu;
+        let mut registers = cpu.registers();
+        match index {
+            0 => {
+                registers.a = (value >> 8) as u8;
+                registers.flags = value as u8;
+            }
+            1 => registers.set_bc(value),
+            2 => registers.set_de(value),
+            3 => registers.set_hl(value),
+            4 => registers.sp = value,
+            5 => {
+                cpu.pc = value;
+                cpu.halted = false;
+                return true;
+            }
+            _ => return false,
+        }
+        cpu.set_registers(&registers);
+        true
+    }
+
+    // The watchpoint the next instruction will trigger, with the address
+    // GDB should be told about
+    fn watch_hit(&self) -> Option<(WatchKind, u16)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        for access in accesses(&self.machine.cpu) {
+            for watchpoint in &self.watchpoints {
+                let triggers = match watchpoint.kind {
+                    WatchKind::Write => access.write,
+                    WatchKind::Read => !access.write,
+                    WatchKind::Access => true,
+                };
+                let start = watchpoint.address as u32;
+                let end = start + watchpoint.length.max(1) as u32;
+                let overlap = (0..access.length as u32).map(|offset| access.address.wrapping_add(offset as u16) as u32).find(|address| (start..end).contains(address));
+                if triggers {
+                    if let Some(address) = overlap {
+                        return Some((watchpoint.kind, address as u16));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn stop_reply(&self, stop:Stop) -> String {
+        match stop {
+            Stop::Exited => "W00".to_string(),
+            Stop::Halted | Stop::Stopped | Stop::WaitingForInput => "S05".to_string(),
+        }
+    }
+
+    // Runs until something stops the target and returns the stop reply.
+    // `single` runs at most one instruction.
+    fn resume(&mut self, single:bool) -> io::Result<String> {
+        let mut executed = 0u64;
+        loop {
+            if let Some(stop) = self.machine.stop_reason() {
+                if executed == 0 || !single || stop == Stop::Exited {
+                    return Ok(self.stop_reply(stop));
+                }
+            }
+            if executed > 0 {
+                if single {
+                    return Ok("S05".to_string());
+                }
+                if self.breakpoints.contains(&self.machine.cpu.pc) {
+                    return Ok(if self.swbreak { "T05swbreak:;" } else { "S05" }.to_string());
+                }
+                if executed.is_multiple_of(INTERRUPT_POLL) && (self.buffered_interrupt() || self.connection.interrupted()) {
+                    return Ok("S02".to_string());
+                }
+            }
+
+            let hit = self.watch_hit();
+            let machine = &mut self.machine;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| machine.step()));
+            self.forward_output()?;
+            if result.is_err() {
+                // Unimplemented opcode or an emulator fault
+                return Ok("S04".to_string());
+            }
+            executed += 1;
+
+            if let Some((kind, address)) = hit {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                return Ok(format!("T05{}:{:04x};", name, address));
+            }
+        }
+    }
+
+    fn breakpoint(&mut self, insert:bool, arguments:&str) -> &'static str {
+        let mut fields = arguments.splitn(2, ',');
+        let kind = fields.next().unwrap_or("");
+        let Some((address, length)) = fields.next().and_then(parse_range) else {
+            return "E01";
+        };
+        let kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                return "OK";
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return "",
+        };
+        let watchpoint = Watchpoint { kind, address, length };
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else if let Some(index) = self.watchpoints.iter().position(|existing| *existing == watchpoint) {
+            self.watchpoints.remove(index);
+        }
+        "OK"
+    }
+
+    // Handles one packet. None ends the session.
+    fn handle(&mut self, packet:&[u8]) -> io::Result<Option<Vec<u8>>> {
+        let text = String::from_utf8_lossy(packet).into_owned();
+        // The command is one byte, which needn't be ASCII in a bad packet
+        let command = packet.first().copied().unwrap_or(0);
+        let arguments = String::from_utf8_lossy(packet.get(1..).unwrap_or_default()).into_owned();
+
+        let reply = match command {
+            b'?' => match self.machine.stop_reason() {
+                Some(Stop::Exited) => "W00".to_string(),
+                _ => "S05".to_string(),
+            },
+            b'g' => {
+                let bytes:Vec<u8> = (0..REGISTER_COUNT).flat_map(|index| self.read_register(index).unwrap_or(0).to_le_bytes()).collect();
+                to_hex(&bytes)
+            }
+            b'G' => match from_hex(&arguments) {
+                Some(bytes) if bytes.len() >= REGISTER_COUNT * 2 => {
+                    for index in 0..REGISTER_COUNT {
+                        self.write_register(index, u16::from_le_bytes([bytes[index * 2], bytes[index * 2 + 1]]));
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            b'p' => match parse_number(&arguments).and_then(|index| self.read_register(index as usize)) {
+                Some(value) => to_hex(&value.to_le_bytes()),
+                None => "E01".to_string(),
+            },
+            b'P' => {
+                let register = arguments.split_once('=').and_then(|(index, value)| Some((parse_number(index)? as usize, from_hex(value)?)));
+                match register {
+                    Some((index, bytes)) if bytes.len() == 2 && self.write_register(index, u16::from_le_bytes([bytes[0], bytes[1]])) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            b'm' => match parse_range(&arguments) {
+                Some((address, length)) => {
+                    let bytes:Vec<u8> = (0..length).map(|offset| self.machine.cpu.ram[address.wrapping_add(offset) as usize]).collect();
+                    to_hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            b'M' => {
+                let write = arguments.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+                match write {
+                    Some(((address, length), bytes)) if bytes.len() == length as usize => {
+                        for (offset, byte) in bytes.iter().enumerate() {
+                            self.machine.cpu.ram[address.wrapping_add(offset as u16) as usize] = *byte;
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            b'X' => {
+                // Binary data may hold any byte, so split the raw packet
+                let colon = packet.iter().position(|byte| *byte == b':');
+                let write = colon.and_then(|colon| Some((parse_range(std::str::from_utf8(&packet[1..colon]).ok()?)?, &packet[colon + 1..])));
+                match write {
+                    Some(((address, length), bytes)) if bytes.len() == length as usize => {
+                        for (offset, byte) in bytes.iter().enumerate() {
+                            self.machine.cpu.ram[address.wrapping_add(offset as u16) as usize] = *byte;
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            b'c' | b's' => {
+                if let Some(address) = parse_number(&arguments) {
+                    self.write_register(5, address as u16);
+                }
+                self.resume(command == b's')?
+            }
+            b'Z' | b'z' => self.breakpoint(command == b'Z', &arguments).to_string(),
+            b'H' | b'T' => "OK".to_string(),
+            b'D' => {
+                self.send(b"OK")?;
+                return Ok(None);
+            }
+            b'k' => return Ok(None),
+            _ => self.query(&text),
+        };
+        Ok(Some(reply.into_bytes()))
+    }
+
+    // General queries and v packets
+    fn query(&mut self, text:&str) -> String {
+        if let Some(features) = text.strip_prefix("qSupported") {
+            self.swbreak = features.split([':', ';']).any(|feature| feature == "swbreak+");
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+".to_string();
+        }
+        if let Some(range) = text.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',').and_then(|(offset, length)| Some((parse_number(offset)? as usize, parse_number(length)? as usize))) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = (start + length).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            return format!("{}{}", marker, &TARGET_XML[start..end]);
+        }
+        match text {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qOffsets" => "Text=0;Data=0;Bss=0".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // Serves the debugger until it detaches, kills the target or hangs up
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.send(&reply)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::fmt::Write;
+
+use crate::CPU;
+
+// Intel HEX images
+// Reads data (00), end of file (01), extended segment/linear address (02/04)
+// and start address (03/05) records. Every record is checksummed, a byte
+// written twice is an error and so is anything outside the 64K address
+// space. Parsing happens before anything touches memory, so a bad file
+// leaves the CPU as it was.
+
+const DATA:u8 = 0x00;
+const END_OF_FILE:u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS:u8 = 0x02;
+const START_SEGMENT_ADDRESS:u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS:u8 = 0x04;
+const START_LINEAR_ADDRESS:u8 = 0x05;
+
+const RECORD_SIZE:usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HexError {
+    // Missing colon, bad hex digit or a length that doesn't match the line
+    Syntax { line:usize },
+    Checksum { line:usize, expected:u8, actual:u8 },
+    UnsupportedRecord { line:usize, kind:u8 },
+    // Data or start address beyond 0xFFFF
+    OutOfRange { line:usize, address:u32 },
+    // A byte already written by an earlier record
+    Overlap { line:usize, address:u16 },
+    MissingEndOfFile,
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HexError::Syntax { line } => write!(f, "line {}: malformed record", line),
+            HexError::Checksum { line, expected, actual } => {
+                write!(f, "line {}: checksum is {:02x}, expected {:02x}", line, actual, expected)
+            }
+            HexError::UnsupportedRecord { line, kind } => write!(f, "line {}: unsupported record type {:02x}", line, kind),
+            HexError::OutOfRange { line, address } => write!(f, "line {}: address {:x} is outside 64K", line, address),
+            HexError::Overlap { line, address } => write!(f, "line {}: {:04x} was already loaded", line, address),
+            HexError::MissingEndOfFile => write!(f, "no end of file record"),
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HexImage {
+    // Contiguous runs of data in file order
+    pub segments:Vec<(u16, Vec<u8>)>,
+    pub start:Option<u16>,
+}
+
+impl HexImage {
+    pub fn parse(text:&str) -> Result<Self, HexError> {
+        let mut image = HexImage::default();
+        let mut loaded = vec![false; 0x10000];
+        let mut base:u32 = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record = decode(line).ok_or(HexError::Syntax { line: number })?;
+            if record.len() < 5 || record.len() != record[0] as usize + 5 {
+                return Err(HexError::Syntax { line: number });
+            }
+            let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if sum != 0 {
+                let actual = record[record.len() - 1];
+                return Err(HexError::Checksum { line: number, expected: actual.wrapping_sub(sum), actual });
+            }
+
+            let offset = (record[1] as u32) << 8 | record[2] as u32;
+            let data = &record[4..record.len() - 1];
+            let word = |data:&[u8]| -> Result<u32, HexError> {
+                match data {
+                    [high, low] => Ok((*high as u32) << 8 | *low as u32),
+                    _ => Err(HexError::Syntax { line: number }),
+                }
+            };
+
+            match record[3] {
+                DATA => {
+                    let address = base + offset;
+                    // Near the top of the 4 GiB linear space this overflows a u32
+                    let end = address as u64 + data.len() as u64;
+                    if end > 0x10000 {
+                        return Err(HexError::OutOfRange { line: number, address: u32::try_from(end - 1).unwrap_or(u32::MAX) });
+                    }
+                    let address = address as u16;
+                    for index in 0..data.len() {
+                        let target = address as usize + index;
+                        if loaded[target] {
+                            return Err(HexError::Overlap { line: number, address: target as u16 });
+                        }
+                        loaded[target] = true;
+                    }
+                    image.add(address, data);
+                }
+                END_OF_FILE => return Ok(image),
+                EXTENDED_SEGMENT_ADDRESS => base = word(data)? << 4,
+                EXTENDED_LINEAR_ADDRESS => base = word(data)? << 16,
+                START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => {
+                    if data.len() != 4 {
+                        return Err(HexError::Syntax { line: number });
+                    }
+                    let high = word(&data[..2])?;
+                    let low = word(&data[2..])?;
+                    let address = if record[3] == START_SEGMENT_ADDRESS { (high << 4) + low } else { high << 16 | low };
+                    if address > 0xFFFF {
+                        return Err(HexError::OutOfRange { line: number, address });
+                    }
+                    image.start = Some(address as u16);
+                }
+                kind => return Err(HexError::UnsupportedRecord { line: number, kind }),
+            }
+        }
+        Err(HexError::MissingEndOfFile)
+    }
+
+    fn add(&mut self, address:u16, data:&[u8]) {
+        if let Some((last, bytes)) = self.segments.last_mut() {
+            if *last as usize + bytes.len() == address as usize {
+                bytes.extend_from_slice(data);
+                return;
+            }
+        }
+        self.segments.push((address, data.to_vec()));
+    }
+
+    pub fn load_into(&self, ram:&mut [u8]) {
+        for (address, data) in &self.segments {
+            let start = *address as usize;
+            ram[start..start + data.len()].copy_from_slice(data);
+        }
+    }
+}
+
+fn decode(line:&str) -> Option<Vec<u8>> {
+    let digits = line.strip_prefix(':')?;
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len()).step_by(2).map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok()).collect()
+}
+
+fn record(output:&mut String, kind:u8, address:u16, data:&[u8]) {
+    let mut sum = (data.len() as u8).wrapping_add((address >> 8) as u8).wrapping_add(address as u8).wrapping_add(kind);
+    let _ = write!(output, ":{:02X}{:04X}{:02X}", data.len(), address, kind);
+    for byte in data {
+        sum = sum.wrapping_add(*byte);
+        let _ = write!(output, "{:02X}", byte);
+    }
+    let _ = writeln!(output, "{:02X}", sum.wrapping_neg());
+}
+
+// Writes `data`, to be loaded at `address`, as 16 byte data records followed
+// by a start linear address record when `start` is given
+pub fn write_hex(data:&[u8], address:u16, start:Option<u16>) -> String {
+    assert!(address as usize + data.len() <= 0x10000, "data runs past 0xFFFF");
+    let mut output = String::new();
+    for (index, chunk) in data.chunks(RECORD_SIZE).enumerate() {
+        record(&mut output, DATA, address + (index * RECORD_SIZE) as u16, chunk);
+    }
+    if let Some(start) = start {
+        record(&mut output, START_LINEAR_ADDRESS, 0, &[0, 0, (start >> 8) as u8, start as u8]);
+    }
+    record(&mut output, END_OF_FILE, 0, &[]);
+    output
+}
+
+impl CPU {
+    // Loads an Intel HEX image and sets PC from its start address record, if
+    // it has one. Memory is left alone when the image is rejected.
+    pub fn load_hex(&mut self, text:&str) -> Result<Option<u16>, HexError> {
+        let image = HexImage::parse(text)?;
+        image.load_into(&mut self.ram);
+        if let Some(start) = image.start {
+            self.pc = start;
+        }
+        Ok(image.start)
+    }
+
+    // `length` bytes of memory from `address` as Intel HEX, stopping at the
+    // end of memory
+    pub fn dump_hex(&self, address:u16, length:usize, start:Option<u16>) -> String {
+        let end = (address as usize + length).min(self.ram.len());
+        write_hex(&self.ram[address as usize..end], address, start)
+    }
+}
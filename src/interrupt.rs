@@ -0,0 +1,73 @@
+// Anything that can drive the 8080 INT line. Before each instruction the CPU
+// asks every attached source whether it is requesting an interrupt; when
+// interrupts are enabled the first pending source is acknowledged and the
+// instruction it places on the data bus (normally RST n, or a 3 byte CALL
+// from an 8259) is executed in place of the next fetch.
+pub trait InterruptSource {
+    fn pending(&mut self, cycles:u32) -> bool;
+    fn acknowledge(&mut self, cycles:u32) -> Vec<u8>;
+}
+
+// Opcode for RST n
+pub fn rst(vector:u8) -> u8 {
+    0xC7 | ((vector & 0x07) << 3)
+}
+
+// An interrupt request output (8253 OUT, 8251 RxRDY, ...) that can be wired
+// to an interrupt controller input.
+pub trait InterruptLine {
+    fn level(&mut self, cycles:u32) -> bool;
+
+    // Rising edges since the last call. Lines whose pulses can be shorter
+    // than an instruction should report them here so they are not missed.
+    fn rising_edges(&mut self, _cycles:u32) -> u32 {
+        0
+    }
+}
+
+impl<F: FnMut(u32) -> bool> InterruptLine for F {
+    fn level(&mut self, cycles:u32) -> bool {
+        self(cycles)
+    }
+}
+
+// Wires a line straight to the CPU INT input (no interrupt controller),
+// requesting RST `vector`. Level triggered lines keep requesting for as long
+// as they are high, edge triggered lines request once per rising edge.
+pub struct RstInterrupt {
+    line:Box<dyn InterruptLine>,
+    vector:u8,
+    edge_triggered:bool,
+    last_level:bool,
+    pending:u32,
+}
+
+impl RstInterrupt {
+    pub fn level(line:Box<dyn InterruptLine>, vector:u8) -> Self {
+        Self { line, vector, edge_triggered: false, last_level: false, pending: 0 }
+    }
+
+    pub fn edge(line:Box<dyn InterruptLine>, vector:u8) -> Self {
+        Self { line, vector, edge_triggered: true, last_level: false, pending: 0 }
+    }
+}
+
+impl InterruptSource for RstInterrupt {
+    fn pending(&mut self, cycles:u32) -> bool {
+        let level = self.line.level(cycles);
+        let edges = self.line.rising_edges(cycles);
+        if !self.edge_triggered {
+            return level;
+        }
+        if edges > 0 || (level && !self.last_level) {
+            self.pending += 1;
+        }
+        self.last_level = level;
+        self.pending > 0
+    }
+
+    fn acknowledge(&mut self, _cycles:u32) -> Vec<u8> {
+        self.pending = 0;
+        vec![rst(self.vector)]
+    }
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Anything that sits on the 8080 I/O ports. `port` is the offset from the
+// base the device was attached at, `cycles` is the CPU cycle counter at the
+// time of the access so devices can model their own timing.
+pub trait IoDevice {
+    fn input(&mut self, port:u8, cycles:u32) -> u8;
+    fn output(&mut self, port:u8, value:u8, cycles:u32);
+}
+
+struct IoMapping {
+    base:u8,
+    count:u8,
+    device:Rc<RefCell<dyn IoDevice>>,
+}
+
+#[derive(Default)]
+pub struct IoBus {
+    mappings:Vec<IoMapping>,
+}
+
+impl IoBus {
+    pub fn new() -> Self {
+        Self { mappings: Vec::new() }
+    }
+
+    // Maps `count` consecutive ports starting at `base` onto `device`.
+    // Devices attached later take priority over earlier ones.
+    pub fn attach(&mut self, base:u8, count:u8, device:Rc<RefCell<dyn IoDevice>>) {
+        self.mappings.push(IoMapping { base, count, device });
+    }
+
+    pub fn detach(&mut self, base:u8) {
+        self.mappings.retain(|mapping| mapping.base != base);
+    }
+
+    pub fn clear(&mut self) {
+        self.mappings.clear();
+    }
+
+    fn find(&self, port:u8) -> Option<(&IoMapping, u8)> {
+        self.mappings.iter().rev().find_map(|mapping| {
+            let offset = port.wrapping_sub(mapping.base);
+            if offset < mapping.count {
+                Some((mapping, offset))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Nothing drives the data bus for an unmapped port, so it reads 0xFF
+    pub fn input(&self, port:u8, cycles:u32) -> u8 {
+        match self.find(port) {
+            Some((mapping, offset)) => mapping.device.borrow_mut().input(offset, cycles),
+            None => 0xFF,
+        }
+    }
+
+    // Returns false when nothing is mapped at `port`
+    pub fn output(&self, port:u8, value:u8, cycles:u32) -> bool {
+        match self.find(port) {
+            Some((mapping, offset)) => {
+                mapping.device.borrow_mut().output(offset, value, cycles);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Signed distance between two points on the wrapping cycle counter
+pub fn cycles_since(now:u32, then:u32) -> i32 {
+    now.wrapping_sub(then) as i32
+}
@@ -0,0 +1,672 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::trap::TrapAction;
+use crate::CPU;
+
+// Intel ISIS-II system call emulation
+// Programs call 0x0040 with the function number in C and DE pointing at a
+// parameter block of 16 bit words. A CPU trap there services those calls in
+// Rust, mapping :F0: to :F9: onto host directories, and another answers the
+// monitor's MEMCK entry at 0xF81B. The command line is read by the program
+// through :CI: just as under the real ISIS CLI. EXIT, and a :CI: read with no
+// complete line queued, stop the CPU at 0x0040; clearing `CPU::stopped` after
+// queueing input retries the read.
+
+const SYSTEM_CALL:u16 = 0x0040;
+const MEMCK:u16 = 0xF81B;
+
+// Connections 0 and 1 are always open
+pub const CONSOLE_OUT:u16 = 0;
+pub const CONSOLE_IN:u16 = 1;
+const MAX_CONNECTIONS:usize = 8;
+
+// ISIS error numbers
+pub const ERROR_BAD_AFTN:u16 = 2;
+pub const ERROR_TOO_MANY_FILES:u16 = 3;
+pub const ERROR_BAD_FILENAME:u16 = 4;
+pub const ERROR_BAD_DEVICE:u16 = 5;
+pub const ERROR_WRITE_PROTECT:u16 = 14;
+pub const ERROR_NO_SUCH_FILE:u16 = 13;
+pub const ERROR_SEEK_NOT_DISK:u16 = 19;
+pub const ERROR_SEEK_BACK:u16 = 20;
+pub const ERROR_CANT_RESCAN:u16 = 21;
+pub const ERROR_BAD_ACCESS:u16 = 22;
+pub const ERROR_NO_FILENAME:u16 = 23;
+pub const ERROR_BAD_SEEK:u16 = 27;
+pub const ERROR_BAD_CALL:u16 = 33;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Device {
+    Disk(usize),
+    ConsoleIn,
+    ConsoleOut,
+    List,
+    Bucket,
+}
+
+impl Device {
+    // Device numbers as reported by SPATH
+    fn number(&self) -> u8 {
+        match self {
+            Device::Disk(drive) => *drive as u8,
+            Device::List => 24,
+            Device::Bucket => 26,
+            Device::ConsoleIn => 27,
+            Device::ConsoleOut => 28,
+        }
+    }
+}
+
+// A parsed ISIS path such as ":F1:PROG.PLM"
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct IsisPath {
+    device:Device,
+    name:String, // "PROG.PLM", empty for devices
+}
+
+enum Connection {
+    Disk { file:File, access:u16 },
+    ConsoleIn,
+    ConsoleOut,
+    List,
+    Bucket,
+}
+
+pub struct Isis {
+    pub memory_top:u16,
+
+    drives:Vec<Option<PathBuf>>,
+    connections:Vec<Option<Connection>>,
+
+    console_in:VecDeque<u8>,
+    console_out:VecDeque<u8>,
+    list_out:Vec<u8>,
+    line:Vec<u8>,
+    line_position:usize,
+    waiting:bool,
+    exited:bool,
+}
+
+impl Default for Isis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Isis {
+    pub fn new() -> Self {
+        let mut connections:Vec<Option<Connection>> = (0..MAX_CONNECTIONS).map(|_| None).collect();
+        connections[CONSOLE_OUT as usize] = Some(Connection::ConsoleOut);
+        connections[CONSOLE_IN as usize] = Some(Connection::ConsoleIn);
+        Self {
+            memory_top: 0xF7FF,
+            drives: (0..10).map(|_| None).collect(),
+            connections,
+            console_in: VecDeque::new(),
+            console_out: VecDeque::new(),
+            list_out: Vec::new(),
+            line: Vec::new(),
+            line_position: 0,
+            waiting: false,
+            exited: false,
+        }
+    }
+
+    // Maps :F0: to :F9: onto a host directory
+    pub fn map_drive<P:AsRef<Path>>(&mut self, drive:usize, directory:P) {
+        self.drives[drive % 10] = Some(directory.as_ref().to_path_buf());
+    }
+
+    // Host side console
+    pub fn send(&mut self, byte:u8) {
+        self.console_in.push_back(byte);
+    }
+
+    pub fn send_bytes(&mut self, bytes:&[u8]) {
+        self.console_in.extend(bytes);
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        self.console_out.pop_front()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.console_out.drain(..).collect()
+    }
+
+    pub fn take_list_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.list_out)
+    }
+
+    // True while a :CI: read is blocked waiting for a complete line
+    pub fn waiting_for_input(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    // Writes the system call entry and an EXIT call at 0x0000 and traps the
+    // entry points
+    pub fn install(isis:&Rc<RefCell<Isis>>, cpu:&mut CPU) {
+        cpu.ram[0x0000..0x0005].copy_from_slice(&[0x0E, 0x09, 0xCD, SYSTEM_CALL as u8, (SYSTEM_CALL >> 8) as u8]);
+        cpu.ram[SYSTEM_CALL as usize] = 0xC9;
+        cpu.ram[MEMCK as usize] = 0xC9;
+        isis.borrow_mut().exited = false;
+
+        let handler = isis.clone();
+        cpu.set_trap(SYSTEM_CALL, move |cpu| handler.borrow_mut().system_call(cpu));
+        let handler = isis.clone();
+        cpu.set_trap(MEMCK, move |cpu| {
+            let memory_top = handler.borrow().memory_top;
+            cpu.a = memory_top as u8;
+            cpu.b = (memory_top >> 8) as u8;
+            TrapAction::Return
+        });
+    }
+
+    // Loads an absolute object file and starts it with `command_line` (the
+    // program name followed by its arguments) waiting on :CI:
+    pub fn load_program(isis:&Rc<RefCell<Isis>>, cpu:&mut CPU, object:&[u8], command_line:&str) -> io::Result<()> {
+        Self::install(isis, cpu);
+        let mut isis = isis.borrow_mut();
+        let entry = load_absolute(&mut cpu.ram, object, 0)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "object file has no entry point"))?;
+
+        let mut line = command_line.trim_end().as_bytes().to_vec();
+        line.extend_from_slice(b"\r\n");
+        // The CLI has already consumed the program name
+        isis.line_position = line.iter().position(|&byte| byte == b' ').unwrap_or(line.len() - 2);
+        isis.line = line;
+
+        cpu.sp = isis.memory_top.wrapping_add(1).wrapping_sub(2);
+        cpu.ram[cpu.sp as usize] = 0;
+        cpu.ram[cpu.sp as usize + 1] = 0;
+        cpu.pc = entry;
+        cpu.halted = false;
+        Ok(())
+    }
+
+    fn system_call(&mut self, cpu:&mut CPU) -> TrapAction {
+        let block = (cpu.d as u16) << 8 | cpu.e as u16;
+        let parameter = |cpu:&CPU, index:u16| word(cpu, block.wrapping_add(index * 2));
+        self.waiting = false;
+
+        // Parameter index of the status pointer for each call
+        let (status_index, result) = match cpu.c {
+            0 => {
+                let (aft_pointer, path, access) = (parameter(cpu, 0), parameter(cpu, 1), parameter(cpu, 2));
+                let result = self.open(cpu, path, access);
+                if let Ok(aft) = result {
+                    set_word(cpu, aft_pointer, aft);
+                }
+                (4, result.map(|_| ()))
+            }
+            1 => (1, self.close(parameter(cpu, 0))),
+            2 => {
+                let path = parameter(cpu, 0);
+                (1, self.delete(cpu, path))
+            }
+            3 => {
+                let (aft, buffer, count, actual_pointer) = (parameter(cpu, 0), parameter(cpu, 1), parameter(cpu, 2), parameter(cpu, 3));
+                match self.read(cpu, aft, buffer, count) {
+                    Some(result) => {
+                        set_word(cpu, actual_pointer, *result.as_ref().unwrap_or(&0));
+                        (4, result.map(|_| ()))
+                    }
+                    None => {
+                        self.waiting = true;
+                        return TrapAction::Stop;
+                    }
+                }
+            }
+            4 => {
+                let (aft, buffer, count) = (parameter(cpu, 0), parameter(cpu, 1), parameter(cpu, 2));
+                (3, self.write(cpu, aft, buffer, count))
+            }
+            5 => {
+                let (aft, mode, block_pointer, byte_pointer) = (parameter(cpu, 0), parameter(cpu, 1), parameter(cpu, 2), parameter(cpu, 3));
+                (4, self.seek(cpu, aft, mode, block_pointer, byte_pointer))
+            }
+            6 => {
+                let (path, bias, return_switch, entry_pointer) = (parameter(cpu, 0), parameter(cpu, 1), parameter(cpu, 2), parameter(cpu, 3));
+                match self.load(cpu, path, bias) {
+                    Ok(entry) => {
+                        set_word(cpu, entry_pointer, entry);
+                        let status_pointer = parameter(cpu, 4);
+                        set_word(cpu, status_pointer, 0);
+                        if return_switch != 0 {
+                            // Chain to the loaded program
+                            cpu.pc = entry;
+                            return TrapAction::Continue;
+                        }
+                        (4, Ok(()))
+                    }
+                    Err(error) => (4, Err(error)),
+                }
+            }
+            7 => {
+                let (old, new) = (parameter(cpu, 0), parameter(cpu, 1));
+                (2, self.rename(cpu, old, new))
+            }
+            8 => {
+                let (input, output) = (parameter(cpu, 0), parameter(cpu, 1));
+                (2, self.consol(cpu, input, output))
+            }
+            9 => {
+                self.exited = true;
+                return TrapAction::Stop;
+            }
+            10 => {
+                let path = parameter(cpu, 0);
+                let result = self.disk_path(cpu, path).and_then(|path| match path.exists() {
+                    true => Ok(()),
+                    false => Err(ERROR_NO_SUCH_FILE),
+                });
+                (3, result)
+            }
+            11 => {
+                let aft = parameter(cpu, 0);
+                let result = match self.connection(aft) {
+                    Ok(Connection::ConsoleIn) => Ok(()),
+                    Ok(_) => Err(ERROR_CANT_RESCAN),
+                    Err(error) => Err(error),
+                };
+                if result.is_ok() {
+                    self.line_position = 0;
+                }
+                (1, result)
+            }
+            12 => {
+                let number = parameter(cpu, 0);
+                let sp = cpu.sp;
+                let caller = word(cpu, sp).wrapping_sub(3);
+                let message = format!("\r\nERROR {} USER PC {:04X}\r\n", number, caller);
+                self.console_out.extend(message.bytes());
+                (1, Ok(()))
+            }
+            13 => {
+                let (aft, buffer) = (parameter(cpu, 0), parameter(cpu, 1));
+                let name:&[u8] = if aft == CONSOLE_IN { b":CI: " } else { b":CO: " };
+                for (index, byte) in name.iter().enumerate() {
+                    cpu.ram[buffer.wrapping_add(index as u16) as usize] = *byte;
+                }
+                (2, Ok(()))
+            }
+            14 => {
+                let (path, info) = (parameter(cpu, 0), parameter(cpu, 1));
+                (2, self.spath(cpu, path, info))
+            }
+            _ => {
+                // Unknown calls have no known status parameter to report in
+                return TrapAction::Return;
+            }
+        };
+
+        let status_pointer = parameter(cpu, status_index);
+        set_word(cpu, status_pointer, result.err().unwrap_or(0));
+        TrapAction::Return
+    }
+
+    fn parse_path(&self, cpu:&CPU, address:u16) -> Result<IsisPath, u16> {
+        let mut text = String::new();
+        let mut address = address;
+        loop {
+            let byte = cpu.ram[address as usize];
+            if !(byte.is_ascii_alphanumeric() || byte == b'.' || byte == b':') || text.len() >= 15 {
+                break;
+            }
+            text.push(byte.to_ascii_uppercase() as char);
+            address = address.wrapping_add(1);
+        }
+        if text.is_empty() {
+            return Err(ERROR_NO_FILENAME);
+        }
+
+        let (device, name) = if let Some(rest) = text.strip_prefix(':') {
+            let end = rest.find(':').ok_or(ERROR_BAD_FILENAME)?;
+            let device = match &rest[..end] {
+                "CI" | "TI" => Device::ConsoleIn,
+                "CO" | "TO" => Device::ConsoleOut,
+                "LP" => Device::List,
+                "BB" => Device::Bucket,
+                name if name.len() == 2 && name.starts_with('F') => {
+                    let drive = name[1..].parse::<usize>().map_err(|_| ERROR_BAD_DEVICE)?;
+                    Device::Disk(drive)
+                }
+                _ => return Err(ERROR_BAD_DEVICE),
+            };
+            (device, rest[end + 1..].to_string())
+        } else {
+            (Device::Disk(0), text)
+        };
+
+        if let Device::Disk(_) = device {
+            let (base, extension) = match name.find('.') {
+                Some(dot) => (&name[..dot], &name[dot + 1..]),
+                None => (name.as_str(), ""),
+            };
+            if base.is_empty() {
+                return Err(ERROR_NO_FILENAME);
+            }
+            if base.len() > 6 || extension.len() > 3 || extension.contains(['.', ':']) || base.contains(':') {
+                return Err(ERROR_BAD_FILENAME);
+            }
+        }
+        Ok(IsisPath { device, name })
+    }
+
+    // Host path for a disk file, matched case insensitively if it exists
+    fn host_path(&self, path:&IsisPath) -> Result<PathBuf, u16> {
+        let drive = match path.device {
+            Device::Disk(drive) => drive,
+            _ => return Err(ERROR_BAD_DEVICE),
+        };
+        let directory = self.drives[drive].as_ref().ok_or(ERROR_BAD_DEVICE)?;
+        let existing = fs::read_dir(directory).ok().and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(&path.name))
+                .map(|entry| entry.path())
+        });
+        Ok(existing.unwrap_or_else(|| directory.join(&path.name)))
+    }
+
+    fn disk_path(&self, cpu:&CPU, address:u16) -> Result<PathBuf, u16> {
+        let path = self.parse_path(cpu, address)?;
+        self.host_path(&path)
+    }
+
+    fn connection(&mut self, aft:u16) -> Result<&mut Connection, u16> {
+        self.connections.get_mut(aft as usize).and_then(|connection| connection.as_mut()).ok_or(ERROR_BAD_AFTN)
+    }
+
+    fn open_connection(&self, path:&IsisPath, access:u16) -> Result<Connection, u16> {
+        let connection = match path.device {
+            Device::ConsoleIn => Connection::ConsoleIn,
+            Device::ConsoleOut => Connection::ConsoleOut,
+            Device::List => Connection::List,
+            Device::Bucket => Connection::Bucket,
+            Device::Disk(_) => {
+                let host = self.host_path(path)?;
+                let result = match access {
+                    1 => File::open(&host),
+                    2 => File::create(&host),
+                    3 => OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&host),
+                    _ => return Err(ERROR_BAD_ACCESS),
+                };
+                let file = result.map_err(|error| match error.kind() {
+                    io::ErrorKind::NotFound => ERROR_NO_SUCH_FILE,
+                    _ => ERROR_WRITE_PROTECT,
+                })?;
+                Connection::Disk { file, access }
+            }
+        };
+        Ok(connection)
+    }
+
+    fn open(&mut self, cpu:&CPU, address:u16, access:u16) -> Result<u16, u16> {
+        let path = self.parse_path(cpu, address)?;
+        let connection = self.open_connection(&path, access)?;
+        let slot = self.connections.iter().skip(2).position(|connection| connection.is_none()).ok_or(ERROR_TOO_MANY_FILES)? + 2;
+        self.connections[slot] = Some(connection);
+        Ok(slot as u16)
+    }
+
+    fn close(&mut self, aft:u16) -> Result<(), u16> {
+        self.connection(aft)?;
+        // The console connections stay open
+        if aft > CONSOLE_IN {
+            self.connections[aft as usize] = None;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, cpu:&CPU, address:u16) -> Result<(), u16> {
+        let path = self.disk_path(cpu, address)?;
+        fs::remove_file(path).map_err(|_| ERROR_NO_SUCH_FILE)
+    }
+
+    fn rename(&mut self, cpu:&CPU, old:u16, new:u16) -> Result<(), u16> {
+        let old = self.disk_path(cpu, old)?;
+        let new_path = self.parse_path(cpu, new)?;
+        if !old.exists() {
+            return Err(ERROR_NO_SUCH_FILE);
+        }
+        let new = self.host_path(&new_path)?;
+        fs::rename(old, new).map_err(|_| ERROR_WRITE_PROTECT)
+    }
+
+    fn consol(&mut self, cpu:&CPU, input:u16, output:u16) -> Result<(), u16> {
+        let input = self.parse_path(cpu, input)?;
+        let output = self.parse_path(cpu, output)?;
+        let input = self.open_connection(&input, 1)?;
+        let output = self.open_connection(&output, 2)?;
+        self.connections[CONSOLE_IN as usize] = Some(input);
+        self.connections[CONSOLE_OUT as usize] = Some(output);
+        Ok(())
+    }
+
+    fn load(&mut self, cpu:&mut CPU, address:u16, bias:u16) -> Result<u16, u16> {
+        let path = self.disk_path(cpu, address)?;
+        let object = fs::read(path).map_err(|_| ERROR_NO_SUCH_FILE)?;
+        match load_absolute(&mut cpu.ram, &object, bias) {
+            Ok(entry) => Ok(entry.unwrap_or(0)),
+            Err(_) => Err(ERROR_BAD_CALL),
+        }
+    }
+
+    // Moves the next complete line from the host queue into the line buffer.
+    // Returns false if no complete line is queued yet.
+    fn next_line(&mut self) -> bool {
+        let end = match self.console_in.iter().position(|&byte| byte == b'\r' || byte == b'\n') {
+            Some(end) => end,
+            None => return false,
+        };
+        let mut line:Vec<u8> = self.console_in.drain(..end).collect();
+        let terminator = self.console_in.pop_front();
+        if terminator == Some(b'\r') && self.console_in.front() == Some(&b'\n') {
+            self.console_in.pop_front();
+        }
+        line.extend_from_slice(b"\r\n");
+        self.line = line;
+        self.line_position = 0;
+        true
+    }
+
+    // None means the read has to wait for console input
+    fn read(&mut self, cpu:&mut CPU, aft:u16, buffer:u16, count:u16) -> Option<Result<u16, u16>> {
+        let console_line = matches!(self.connection(aft), Ok(Connection::ConsoleIn));
+        if console_line {
+            if self.line_position >= self.line.len() && !self.next_line() {
+                return None;
+            }
+            let available = &self.line[self.line_position..];
+            let actual = available.len().min(count as usize);
+            for (index, byte) in available[..actual].iter().enumerate() {
+                cpu.ram[buffer.wrapping_add(index as u16) as usize] = *byte;
+            }
+            self.line_position += actual;
+            return Some(Ok(actual as u16));
+        }
+
+        let result = match self.connection(aft) {
+            Ok(Connection::Disk { file, .. }) => {
+                let mut data = vec![0u8; count as usize];
+                let mut actual = 0;
+                while actual < data.len() {
+                    match file.read(&mut data[actual..]) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => actual += read,
+                    }
+                }
+                for (index, byte) in data[..actual].iter().enumerate() {
+                    cpu.ram[buffer.wrapping_add(index as u16) as usize] = *byte;
+                }
+                Ok(actual as u16)
+            }
+            Ok(Connection::Bucket) => Ok(0),
+            Ok(_) => Err(ERROR_BAD_ACCESS),
+            Err(error) => Err(error),
+        };
+        Some(result)
+    }
+
+    fn write(&mut self, cpu:&CPU, aft:u16, buffer:u16, count:u16) -> Result<(), u16> {
+        let data:Vec<u8> = (0..count).map(|index| cpu.ram[buffer.wrapping_add(index) as usize]).collect();
+        match self.connections.get_mut(aft as usize).and_then(|connection| connection.as_mut()) {
+            Some(Connection::ConsoleOut) => self.console_out.extend(&data),
+            Some(Connection::List) => self.list_out.extend(&data),
+            Some(Connection::Bucket) => {}
+            Some(Connection::Disk { file, access }) => {
+                if *access == 1 {
+                    return Err(ERROR_BAD_ACCESS);
+                }
+                file.write_all(&data).map_err(|_| ERROR_WRITE_PROTECT)?;
+            }
+            Some(Connection::ConsoleIn) => return Err(ERROR_BAD_ACCESS),
+            None => return Err(ERROR_BAD_AFTN),
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, cpu:&mut CPU, aft:u16, mode:u16, block_pointer:u16, byte_pointer:u16) -> Result<(), u16> {
+        let (blocks, bytes) = (word(cpu, block_pointer), word(cpu, byte_pointer));
+        let offset = blocks as u64 * 128 + bytes as u64;
+        let (file, access) = match self.connection(aft)? {
+            Connection::Disk { file, access } => (file, *access),
+            _ => return Err(ERROR_SEEK_NOT_DISK),
+        };
+        let current = file.stream_position().map_err(|_| ERROR_BAD_SEEK)?;
+        let end = file.metadata().map(|metadata| metadata.len()).map_err(|_| ERROR_BAD_SEEK)?;
+
+        let (target, error) = match mode {
+            0 => {
+                set_word(cpu, block_pointer, (current / 128) as u16);
+                set_word(cpu, byte_pointer, (current % 128) as u16);
+                return Ok(());
+            }
+            1 => match current.checked_sub(offset) {
+                Some(target) => (target, None),
+                None => (0, Some(ERROR_SEEK_BACK)),
+            },
+            2 => (offset, None),
+            3 => (current + offset, None),
+            4 => (end, None),
+            _ => return Err(ERROR_BAD_SEEK),
+        };
+
+        // Seeking past the end of a read only file stops at the end; files
+        // open for writing are extended with zeros
+        let target = if target > end {
+            if access == 1 {
+                end
+            } else {
+                file.set_len(target).map_err(|_| ERROR_WRITE_PROTECT)?;
+                target
+            }
+        } else {
+            target
+        };
+        file.seek(SeekFrom::Start(target)).map_err(|_| ERROR_BAD_SEEK)?;
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn spath(&self, cpu:&mut CPU, address:u16, info:u16) -> Result<(), u16> {
+        let path = self.parse_path(cpu, address)?;
+        let mut bytes = [0u8; 12];
+        bytes[0] = path.device.number();
+        let name = if let Device::Disk(_) = path.device { path.name.as_str() } else { "" };
+        let (base, extension) = match name.find('.') {
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+            None => (name, ""),
+        };
+        bytes[1..1 + base.len()].copy_from_slice(base.as_bytes());
+        bytes[7..7 + extension.len()].copy_from_slice(extension.as_bytes());
+        // Device type: 0 input, 1 output, 3 random access
+        bytes[10] = match path.device {
+            Device::Disk(_) => 3,
+            Device::ConsoleIn => 0,
+            _ => 1,
+        };
+        bytes[11] = if let Device::Disk(_) = path.device { 1 } else { 0 };
+        for (index, byte) in bytes.iter().enumerate() {
+            cpu.ram[info.wrapping_add(index as u16) as usize] = *byte;
+        }
+        Ok(())
+    }
+}
+
+fn word(cpu:&CPU, address:u16) -> u16 {
+    cpu.ram[address as usize] as u16 | (cpu.ram[address.wrapping_add(1) as usize] as u16) << 8
+}
+
+fn set_word(cpu:&mut CPU, address:u16, value:u16) {
+    cpu.ram[address as usize] = value as u8;
+    cpu.ram[address.wrapping_add(1) as usize] = (value >> 8) as u8;
+}
+
+// Object module record types
+const RECORD_MODULE_HEADER:u8 = 0x02;
+const RECORD_MODULE_END:u8 = 0x04;
+const RECORD_CONTENT:u8 = 0x06;
+const RECORD_EOF:u8 = 0x0E;
+
+// Loads an ISIS absolute object file (8080 OMF) into `ram`, adding `bias` to
+// every load address. Returns the entry point of a main module.
+pub fn load_absolute(ram:&mut [u8], object:&[u8], bias:u16) -> io::Result<Option<u16>> {
+    let invalid = |message:&str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut entry = None;
+    let mut position = 0;
+
+    while position < object.len() {
+        if position + 3 > object.len() {
+            return Err(invalid("truncated object record"));
+        }
+        let kind = object[position];
+        let length = object[position + 1] as usize | (object[position + 2] as usize) << 8;
+        let end = position + 3 + length;
+        if length == 0 || end > object.len() {
+            return Err(invalid("truncated object record"));
+        }
+        if object[position..end].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(invalid("object record checksum error"));
+        }
+        // Without the checksum byte
+        let body = &object[position + 3..end - 1];
+
+        match kind {
+            RECORD_CONTENT => {
+                if body.len() < 3 {
+                    return Err(invalid("short content record"));
+                }
+                if body[0] != 0 {
+                    return Err(invalid("relocatable segment in absolute object file"));
+                }
+                let address = (body[1] as u16 | (body[2] as u16) << 8).wrapping_add(bias);
+                for (index, byte) in body[3..].iter().enumerate() {
+                    ram[address.wrapping_add(index as u16) as usize] = *byte;
+                }
+            }
+            // Module type 1 is a main module with a start address
+            RECORD_MODULE_END if body.len() >= 4 && body[0] == 1 => {
+                entry = Some((body[2] as u16 | (body[3] as u16) << 8).wrapping_add(bias));
+            }
+            RECORD_EOF => break,
+            RECORD_MODULE_HEADER => {}
+            // Symbol, line number and other debug records
+            _ => {}
+        }
+        position = end;
+    }
+    Ok(entry)
+}
@@ -2,6 +2,39 @@ use core::panic;
 use std::fs::File;
 use std::io::Write;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub mod asm;
+pub mod cpm;
+pub mod dap;
+pub mod devices;
+pub mod disasm;
+pub mod disk;
+pub mod hex;
+pub mod dma;
+pub mod gdb;
+pub mod interrupt;
+pub mod io;
+pub mod isis;
+pub mod json;
+pub mod listing;
+pub mod loader;
+pub mod machine;
+pub mod patch;
+pub mod romset;
+pub mod semihost;
+pub mod subroutine;
+pub mod trap;
+
+use dma::BusMaster;
+use interrupt::InterruptSource;
+use io::IoBus;
+use semihost::Semihost;
+use subroutine::Registers;
+use trap::{TrapAction, TrapHandler};
+
 const RAM_SIZE:usize = 65536; //64 KiB
 pub struct CPU {
     pub pc:u16, // Program Counter
@@ -22,14 +55,23 @@ pub struct CPU {
     cy:bool, // Carry bit  
     ac:bool, // Aux carry
     pub int_enabled:bool, // Interrupt bit
+    int_delay:bool, // EI takes effect after the following instruction
+    pub halted:bool,
     pub last_interrupt:u8,
     pub cycles:u32,
 
-    //IO API (TODO)
-    //try_input:bool,
-    //try_output:bool,
-    //in_port:u8,
+    //IO API
+    pub io:IoBus,
     pub out_port:u8,
+    pub semihost:Option<Semihost>,
+    interrupt_sources:Vec<Rc<RefCell<dyn InterruptSource>>>,
+    bus_masters:Vec<Rc<RefCell<dyn BusMaster>>>,
+
+    //High level emulation traps
+    traps:HashMap<u16, TrapHandler>,
+    call_traps:HashMap<u16, TrapHandler>,
+    pub stopped:bool,
+    trap_resume:Option<u16>,
 }
 
 impl CPU {
@@ -51,14 +93,28 @@ impl CPU {
             cy:false,
             ac:false,
             int_enabled:true,
+            int_delay:false,
+            halted:false,
             last_interrupt:16,
             cycles: 0,
-            //try_input:false,
-            //try_output:false,
-            //in_port:0,
+            io:IoBus::new(),
             out_port:255,
+            semihost:None,
+            interrupt_sources:Vec::new(),
+            bus_masters:Vec::new(),
+            traps:HashMap::new(),
+            call_traps:HashMap::new(),
+            stopped:false,
+            trap_resume:None,
         };
 
+        // cpudiag prints through CP/M's BDOS console calls
+        #[cfg(feature = "cpudiag")]
+        let new_cpu = {
+            let mut new_cpu = new_cpu;
+            new_cpu.set_call_trap(5, trap::cpudiag_bdos);
+            new_cpu
+        };
 
         new_cpu
     }
@@ -80,12 +136,13 @@ impl CPU {
         self.cy = false;
         self.ac = false;
         self.int_enabled = false;
+        self.int_delay = false;
+        self.halted = false;
         self.last_interrupt = 16;
         self.cycles = 0;
-        //self.try_input = false;
-        //self.try_output = false;
-        //self.in_port = 0;
         self.out_port = 255;
+        self.stopped = false;
+        self.trap_resume = None;
     }
 
     pub fn init_start_addr(&mut self, start_addr:u16) {
@@ -93,17 +150,221 @@ impl CPU {
     }
 
     pub fn tick(&mut self) {
+        self.step();
+    }
+
+    // Runs one instruction (or one interrupt acknowledge, or one idle HLT
+    // cycle) and returns the opcode that was executed
+    fn step(&mut self) -> u8 {
+        // A trap stopped the CPU; nothing runs until `resume`
+        if self.stopped {
+            return 0x00;
+        }
+
+        self.service_hold();
+
+        if let Some(op) = self.poll_interrupts() {
+            return op;
+        }
+
+        if self.halted {
+            self.cycles += 4;
+            return 0x76;
+        }
+
+        let pc = self.pc;
+        if self.trap_resume.take() != Some(pc) {
+            match self.run_trap(false, pc) {
+                Some(TrapAction::Return) => {
+                    self.trap_return();
+                    return 0xC9;
+                }
+                Some(TrapAction::Stop) => {
+                    self.stopped = true;
+                    return 0x00;
+                }
+                _ => {}
+            }
+        }
+
         //Fetch & Decode
         let op:u8 = self.fetch();
+        let sp = self.sp;
+        let target = (self.ram[self.pc as usize] as u16) | (self.ram[self.pc.wrapping_add(1) as usize] as u16) << 8;
         //Execute
         self.execute(op);
+
+        // CALL, conditional CALLs and their undocumented aliases, when taken
+        let call = op & 0xC7 == 0xC4 || op & 0xCF == 0xCD;
+        if call && self.pc == target && self.sp == sp.wrapping_sub(2) {
+            match self.run_trap(true, target) {
+                Some(TrapAction::Return) => self.trap_return(),
+                Some(TrapAction::Stop) => {
+                    self.stopped = true;
+                    self.trap_resume = Some(self.pc);
+                }
+                _ => {}
+            }
+        }
+
+        op
+    }
+
+    // Runs `handler` whenever execution reaches `address`
+    pub fn set_trap<F>(&mut self, address:u16, handler:F)
+    where
+        F:FnMut(&mut CPU) -> TrapAction + 'static,
+    {
+        self.traps.insert(address, Box::new(handler));
+    }
+
+    pub fn remove_trap(&mut self, address:u16) {
+        self.traps.remove(&address);
+    }
+
+    // Runs `handler` whenever a CALL to `address` is taken
+    pub fn set_call_trap<F>(&mut self, address:u16, handler:F)
+    where
+        F:FnMut(&mut CPU) -> TrapAction + 'static,
+    {
+        self.call_traps.insert(address, Box::new(handler));
+    }
+
+    pub fn remove_call_trap(&mut self, address:u16) {
+        self.call_traps.remove(&address);
+    }
+
+    pub fn clear_traps(&mut self) {
+        self.traps.clear();
+        self.call_traps.clear();
+    }
+
+    // Continues after a trap stopped the CPU, without running that trap
+    // again for the instruction it stopped at
+    pub fn resume(&mut self) {
+        if self.stopped {
+            self.stopped = false;
+            self.trap_resume = Some(self.pc);
+        }
+    }
+
+    fn run_trap(&mut self, call:bool, address:u16) -> Option<TrapAction> {
+        let traps = if call { &mut self.call_traps } else { &mut self.traps };
+        let mut handler = traps.remove(&address)?;
+        let action = handler(self);
+
+        // Unless the handler installed a replacement for itself
+        let traps = if call { &mut self.call_traps } else { &mut self.traps };
+        traps.entry(address).or_insert(handler);
+        Some(action)
+    }
+
+    fn trap_return(&mut self) {
+        let low_byte = self.ram[self.sp as usize] as u16;
+        let high_byte = self.ram[self.sp.wrapping_add(1) as usize] as u16;
+        self.sp = self.sp.wrapping_add(2);
+        self.pc = (high_byte << 8) | low_byte;
+        self.cycles += 10;
+    }
+
+    pub fn attach_bus_master(&mut self, master:Rc<RefCell<dyn BusMaster>>) {
+        self.bus_masters.push(master);
+    }
+
+    pub fn clear_bus_masters(&mut self) {
+        self.bus_masters.clear();
+    }
+
+    // HOLD is honoured between instructions; the cycles a master keeps the
+    // bus are added to the cycle counter. At most one master gets the bus
+    // per instruction, so a DRQ that never drops still lets the CPU run.
+    fn service_hold(&mut self) {
+        let cycles = self.cycles;
+        let Some(master) = self.bus_masters.iter().find(|master| master.borrow_mut().hold(cycles)).cloned() else {
+            return;
+        };
+
+        // HLDA
+        let stolen = master.borrow_mut().bus_granted(&mut self.ram, cycles);
+        self.cycles = self.cycles.wrapping_add(stolen);
+    }
+
+    pub fn attach_interrupt_source(&mut self, source:Rc<RefCell<dyn InterruptSource>>) {
+        self.interrupt_sources.push(source);
+    }
+
+    pub fn clear_interrupt_sources(&mut self) {
+        self.interrupt_sources.clear();
+    }
+
+    fn poll_interrupts(&mut self) -> Option<u8> {
+        if self.int_delay {
+            self.int_delay = false;
+            return None;
+        }
+        if !self.int_enabled {
+            return None;
+        }
+
+        let cycles = self.cycles;
+        let source = self.interrupt_sources.iter().find(|source| source.borrow_mut().pending(cycles))?.clone();
+        let instruction = source.borrow_mut().acknowledge(cycles);
+
+        if self.interrupt(&instruction) {
+            instruction.first().copied()
+        } else {
+            None
+        }
+    }
+
+    /*
+    Raises INT with `instruction` on the data bus, e.g. &[0xCF] for RST 1.
+    Ignored (returns false) while interrupts are disabled. Acknowledging
+    disables further interrupts and wakes the CPU from HLT. CALL is the only
+    instruction of more than one byte accepted; others are ignored too.
+    */
+    pub fn interrupt(&mut self, instruction:&[u8]) -> bool {
+        if !self.int_enabled || instruction.is_empty() {
+            return false;
+        }
+        let op = instruction[0];
+        if op != 0xCD && disasm::disassemble(&[op], 0).length > 1 {
+            return false;
+        }
+
+        self.int_enabled = false;
+        self.halted = false;
+
+        match op {
+            //RST n
+            _ if op & 0xC7 == 0xC7 => {
+                self.push_pc();
+                self.pc = (op & 0x38) as u16;
+                self.cycles += 11;
+            }
+            //CALL adr
+            0xCD => {
+                let low_byte = instruction.get(1).copied().unwrap_or(0xFF) as u16;
+                let high_byte = instruction.get(2).copied().unwrap_or(0xFF) as u16;
+                self.push_pc();
+                self.pc = (high_byte << 8) | low_byte;
+                self.cycles += 17;
+            }
+            // Any other one byte instruction executes without advancing PC
+            _ => self.execute(op),
+        }
+
+        true
+    }
+
+    fn push_pc(&mut self) {
+        self.sp = self.sp.wrapping_sub(2);
+        self.ram[self.sp.wrapping_add(1) as usize] = (self.pc >> 8) as u8;
+        self.ram[self.sp as usize] = self.pc as u8;
     }
 
     pub fn debug_tick (&mut self) -> String {
-        //Fetch & Decode
-        let op:u8 = self.fetch();
-        //Execute
-        self.execute(op);
+        let op:u8 = self.step();
 
         let mut flag_value:u8 = 0;
         let flag_vec:Vec<bool> = vec![self.s, self.z, false, self.ac, false, self.p, false, self.cy];
@@ -136,10 +397,7 @@ impl CPU {
     }
 
     pub fn gui_debug_tick (&mut self) -> (Vec<u16>, Vec<&str>){
-        //Fetch & Decode
-        let op:u8 = self.fetch();
-        //Execute
-        self.execute(op);
+        let op:u8 = self.step();
 
         let mut flag_value:u8 = 0;
         let flag_vec:Vec<bool> = vec![self.s, self.z, false, self.ac, false, self.p, false, self.cy];
@@ -173,6 +431,44 @@ impl CPU {
         self.ram[start..end].copy_from_slice(data);
     }
 
+    pub fn flags(&self) -> u8 {
+        (self.s as u8) << 7 | (self.z as u8) << 6 | (self.ac as u8) << 4 | (self.p as u8) << 2 | 0x02 | self.cy as u8
+    }
+
+    pub fn set_flags(&mut self, value:u8) {
+        self.s = value & 0x80 != 0;
+        self.z = value & 0x40 != 0;
+        self.ac = value & 0x10 != 0;
+        self.p = value & 0x04 != 0;
+        self.cy = value & 0x01 != 0;
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            flags: self.flags(),
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers:&Registers) {
+        self.a = registers.a;
+        self.set_flags(registers.flags);
+        self.b = registers.b;
+        self.c = registers.c;
+        self.d = registers.d;
+        self.e = registers.e;
+        self.h = registers.h;
+        self.l = registers.l;
+        self.sp = registers.sp;
+    }
+
     fn fetch(&mut self) -> u8 {
         let opcode = self.ram[self.pc as usize] as u8;
         self.pc += 1;
@@ -964,7 +1260,7 @@ impl CPU {
                 1 Byte
                 SP = SP + 1
                 */
-                self.sp += 1;
+                self.sp = self.sp.wrapping_add(1);
 
                 self.cycles += 5;
             }
@@ -1824,10 +2120,13 @@ impl CPU {
 
             //HLT
             (7, 6) => {
-                //TODO
-                
+                /*
+                1 Byte
+                Stops until an interrupt is acknowledged
+                */
+
+                self.halted = true;
                 self.cycles += 7;
-                unimplemented!("Called 0x76 HLT")
             }
 
             //MOV M, A
@@ -3239,10 +3538,10 @@ impl CPU {
 
                 if self.z == false {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -3259,9 +3558,9 @@ impl CPU {
                 */
 
                 self.c = self.ram[self.sp as usize];
-                self.b = self.ram[(self.sp + 1) as usize];
+                self.b = self.ram[(self.sp.wrapping_add(1)) as usize];
 
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
                 self.cycles += 10;
             }
 
@@ -3302,10 +3601,10 @@ impl CPU {
                 */
 
                 if self.z == false {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -3327,10 +3626,10 @@ impl CPU {
                 Stores C at (SP - 2), B at (SP - 1), SP - 2
                 */
 
-                self.ram[(self.sp - 2) as usize] = self.c;
-                self.ram[(self.sp - 1) as usize] = self.b;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = self.c;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = self.b;
 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
                 self.cycles += 11;
             }
 
@@ -3361,10 +3660,10 @@ impl CPU {
                 CALL $0
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x0;
                 self.cycles += 11;
@@ -3379,10 +3678,10 @@ impl CPU {
 
                 if self.z {
                         let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -3400,10 +3699,10 @@ impl CPU {
                 */
 
                 let low_byte = self.ram[self.sp as usize] as u16;
-                let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                 self.pc = (high_byte << 8) | low_byte;
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
                 self.cycles += 10;
             }
 
@@ -3447,10 +3746,10 @@ impl CPU {
                 */
 
                 if self.z {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -3477,29 +3776,6 @@ impl CPU {
 
                 let address = (high_byte << 8) | low_byte;
 
-                #[cfg(feature = "cpudiag")]
-                if address == 5 {
-                    if self.c == 9 {
-                        let mut offset = ((self.d as u16) << 8) | self.e as u16;
-                        loop {
-                            let character = self.ram[offset as usize];
-
-                            if character as char == '$' {
-                                break;
-                            } else {
-                                offset += 1;
-                            }
-                            print!("{}", character as char);
-                        }
-                        print!("\n");
-                    } else if address == 0 {
-                        ::std::process::exit(0)
-                    }
-                    if self.c == 2 {
-                        println!("{}", self.e as char)
-                    }
-                }
-                
                 /* if address == 5 {
                     if self.c == 9 {
                         let offset = ((self.d as u16) << 8) | self.e as u16;
@@ -3518,10 +3794,10 @@ impl CPU {
                     }
                 } */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = address;
                 self.cycles += 17;
@@ -3557,10 +3833,10 @@ impl CPU {
                 CALL $8 (0x0008)
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x8;
                 self.cycles += 11;
@@ -3575,10 +3851,10 @@ impl CPU {
 
                 if self.cy == false {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -3595,9 +3871,9 @@ impl CPU {
                 */
 
                 self.e = self.ram[self.sp as usize];
-                self.d = self.ram[(self.sp + 1) as usize];
+                self.d = self.ram[(self.sp.wrapping_add(1)) as usize];
 
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
                 self.cycles += 10;
             }
 
@@ -3622,10 +3898,20 @@ impl CPU {
 
             //OUT D8
             (0xD, 3) => {
-
-                //TODO IMPLEMENT OUT API
+                /*
+                2 Byte
+                Write A to the port given by the 2nd Byte
+                */
 
                 self.out_port = self.ram[self.pc as usize];
+                self.io.output(self.out_port, self.a, self.cycles);
+
+                if self.semihost.as_ref().is_some_and(|semihost| semihost.port == self.out_port) {
+                    if let Some(mut semihost) = self.semihost.take() {
+                        semihost.call(self);
+                        self.semihost = Some(semihost);
+                    }
+                }
 
                 #[cfg(feature = "cputest")]
                 match self.out_port { //Emulates CP/M Sys Calls
@@ -3670,10 +3956,10 @@ impl CPU {
                 */
 
                 if self.cy == false {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -3695,10 +3981,10 @@ impl CPU {
                 Stores E at (SP - 2), D at (SP - 1), SP - 2
                 */
 
-                self.ram[(self.sp - 2) as usize] = self.e;
-                self.ram[(self.sp - 1) as usize] = self.d;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = self.e;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = self.d;
 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
                 self.cycles += 11;
             }
 
@@ -3731,10 +4017,10 @@ impl CPU {
                 CALL $0 (0x0000)
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x10;
                 self.cycles += 11;
@@ -3749,10 +4035,10 @@ impl CPU {
 
                 if self.cy {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -3770,10 +4056,10 @@ impl CPU {
                 */
 
                 let low_byte = self.ram[self.sp as usize] as u16;
-                let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                 self.pc = (high_byte << 8) | low_byte;
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
                 self.cycles += 10;
             }
             
@@ -3801,9 +4087,10 @@ impl CPU {
                 2 Byte
                 Write A with input from bort 2nd Byte
                 */
-                
-                //TODO: Implement API
-                
+
+                let port = self.ram[self.pc as usize];
+                self.a = self.io.input(port, self.cycles);
+
                 self.pc += 1;
                 self.cycles += 10;
             }
@@ -3816,10 +4103,10 @@ impl CPU {
                 */
 
                 if self.cy{
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -3842,10 +4129,10 @@ impl CPU {
                 Subroutine Call
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 let low_byte = self.ram[self.pc as usize] as u16;
                 let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -3884,10 +4171,10 @@ impl CPU {
                 CALL $13
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x13;
                 self.cycles += 11;
@@ -3902,10 +4189,10 @@ impl CPU {
 
                 if self.p == false {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -3923,9 +4210,9 @@ impl CPU {
                 */
 
                 self.l = self.ram[self.sp as usize];
-                self.h = self.ram[(self.sp + 1) as usize];
+                self.h = self.ram[(self.sp.wrapping_add(1)) as usize];
                 
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
 
                 self.cycles += 10;
             }
@@ -3958,8 +4245,8 @@ impl CPU {
 
                 let mut xchng_byte = self.h;
 
-                self.h = self.ram[(self.sp + 1) as usize];
-                self.ram[(self.sp + 1) as usize] = xchng_byte;
+                self.h = self.ram[(self.sp.wrapping_add(1)) as usize];
+                self.ram[(self.sp.wrapping_add(1)) as usize] = xchng_byte;
 
                 xchng_byte = self.l;
 
@@ -3977,10 +4264,10 @@ impl CPU {
                 */
 
                 if self.p == false {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4002,10 +4289,10 @@ impl CPU {
                 Stores L at (SP - 2), H at (SP - 1), SP - 2
                 */
 
-                self.ram[(self.sp - 2) as usize] = self.l;
-                self.ram[(self.sp - 1) as usize] = self.h;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = self.l;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = self.h;
 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
                 self.cycles += 11;
             }
 
@@ -4036,10 +4323,10 @@ impl CPU {
                 CALL $20
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x20;
                 self.cycles += 11;
@@ -4054,10 +4341,10 @@ impl CPU {
 
                 if self.p {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -4127,10 +4414,10 @@ impl CPU {
                 */
 
                 if self.p {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4153,10 +4440,10 @@ impl CPU {
                 Subroutine Call
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 let low_byte = self.ram[self.pc as usize] as u16;
                 let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4191,10 +4478,10 @@ impl CPU {
                 CALL $28
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x28;
                 self.cycles += 11;
@@ -4209,10 +4496,10 @@ impl CPU {
 
                 if self.s == false {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -4235,9 +4522,9 @@ impl CPU {
                 self.p = (flag_val & 0x4) != 0;
                 self.cy = (flag_val & 0x1) != 0;
 
-                self.a = self.ram[(self.sp + 1) as usize];
+                self.a = self.ram[(self.sp.wrapping_add(1)) as usize];
 
-                self.sp += 2;
+                self.sp = self.sp.wrapping_add(2);
                 self.cycles += 10;
             }
 
@@ -4274,10 +4561,10 @@ impl CPU {
                 */
 
                 if self.s == false {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4309,11 +4596,11 @@ impl CPU {
                     }
                 }
                 
-                self.ram[(self.sp - 1) as usize] = self.a;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = self.a;
 
-                self.ram[(self.sp - 2) as usize] = flag_value;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = flag_value;
 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
                 self.cycles += 11;
             }
 
@@ -4343,10 +4630,10 @@ impl CPU {
                 CALL $30
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x30;
                 self.cycles += 11;
@@ -4361,10 +4648,10 @@ impl CPU {
 
                 if self.s {
                     let low_byte = self.ram[self.sp as usize] as u16;
-                    let high_byte = self.ram[(self.sp + 1) as usize] as u16;
+                    let high_byte = self.ram[(self.sp.wrapping_add(1)) as usize] as u16;
 
                     self.pc = (high_byte << 8) | low_byte;
-                    self.sp += 2;
+                    self.sp = self.sp.wrapping_add(2);
                     self.cycles += 11;
                 }
                 else {
@@ -4408,6 +4695,7 @@ impl CPU {
             //EI
             (0xF, 0xB) => {
                 self.int_enabled = true;
+                self.int_delay = true;
                 self.cycles += 4;
             }
 
@@ -4419,10 +4707,10 @@ impl CPU {
                 */
 
                 if self.s {
-                    self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                    self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                    self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                    self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                     
-                    self.sp -= 2;
+                    self.sp = self.sp.wrapping_sub(2);
 
                     let low_byte = self.ram[self.pc as usize] as u16;
                     let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4445,10 +4733,10 @@ impl CPU {
                 Subroutine Call
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 let low_byte = self.ram[self.pc as usize] as u16;
                 let high_byte = self.ram[(self.pc + 1) as usize] as u16;
@@ -4483,10 +4771,10 @@ impl CPU {
                 CALL $38
                 */
 
-                self.ram[(self.sp - 1) as usize] = ((self.pc + 2) >> 8) as u8;
-                self.ram[(self.sp - 2) as usize] = (self.pc + 2) as u8;
+                self.ram[(self.sp.wrapping_sub(1)) as usize] = ((self.pc + 2) >> 8) as u8;
+                self.ram[(self.sp.wrapping_sub(2)) as usize] = (self.pc + 2) as u8;
                 
-                self.sp -= 2;
+                self.sp = self.sp.wrapping_sub(2);
 
                 self.pc = 0x38;
                 self.cycles += 11;
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Assembler listing maps
+// Ties source lines to addresses using the listing (.PRN or .LST) the
+// assembler wrote alongside the object code. Listing line n is taken to be
+// source line n, which holds for CP/M ASM and MAC and any assembler run
+// without macro expansion or include files listed. A line maps to an
+// address when it starts with four hex digits (an M80 style ' or " after
+// them is allowed):
+//
+//   0100 3E05       START:  MVI A,5
+//   0005 =          BDOS    EQU 5
+//
+// The code bytes follow the address, split by single spaces at most, and
+// the source text starts after a wider gap. EQU lines (an '=' after the
+// address) are values, not code. A word ending in ':' at the start of the
+// source text is taken as a label.
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Listing {
+    // The address of each line, line 1 first; None where there isn't one
+    pub lines:Vec<Option<u16>>,
+    // Sorted by address
+    pub labels:Vec<(u16, String)>,
+    // (address, byte count, line) of the lines that generate code, sorted
+    // by address
+    code:Vec<(u16, u16, usize)>,
+}
+
+fn is_byte_field(token:&str) -> bool {
+    (2..=8).contains(&token.len()) && token.len().is_multiple_of(2) && token.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+impl Listing {
+    pub fn parse(text:&str) -> Self {
+        let mut listing = Listing::default();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let address = line
+                .get(..4)
+                .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|digits| u16::from_str_radix(digits, 16).ok())
+                .filter(|_| line[4..].starts_with([' ', '\t', '\'', '"']) || line.len() == 4);
+            let Some(address) = address else {
+                listing.lines.push(None);
+                continue;
+            };
+
+            let mut rest = line[4..].trim_start_matches(['\'', '"']).trim_start();
+            if rest.starts_with('=') {
+                listing.lines.push(None);
+                continue;
+            }
+            let mut length = 0;
+            loop {
+                let end = rest.find([' ', '\t']).unwrap_or(rest.len());
+                if !is_byte_field(&rest[..end]) {
+                    break;
+                }
+                length += end as u16 / 2;
+                rest = &rest[end..];
+                match rest.strip_prefix(' ') {
+                    Some(next) if !next.starts_with([' ', '\t']) => rest = next,
+                    _ => break,
+                }
+            }
+            if let Some(label) = rest.split_whitespace().next().and_then(|token| token.strip_suffix(':')).filter(|label| !label.is_empty()) {
+                listing.labels.push((address, label.to_string()));
+            }
+
+            listing.lines.push(Some(address));
+            if length > 0 {
+                listing.code.push((address, length, number));
+            }
+        }
+        listing.labels.sort();
+        // Stable, so the first line wins where several share an address
+        listing.code.sort_by_key(|(address, _, _)| *address);
+        listing
+    }
+
+    pub fn from_file<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // Where a breakpoint on `line` goes: the first line from there on that
+    // has an address, as (line, address)
+    pub fn address(&self, line:usize) -> Option<(usize, u16)> {
+        let start = line.checked_sub(1)?;
+        self.lines.iter().enumerate().skip(start).find_map(|(index, address)| address.map(|address| (index + 1, address)))
+    }
+
+    // The line of the code containing `address`
+    pub fn line(&self, address:u16) -> Option<usize> {
+        let index = self.code.partition_point(|(start, _, _)| *start <= address).checked_sub(1)?;
+        // Where lines share an address the first one owns it
+        let (start, _, _) = self.code[index];
+        let first = self.code.partition_point(|(other, _, _)| *other < start);
+        let (_, length, line) = self.code[first];
+        if (address as u32) < start as u32 + length as u32 {
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    // The nearest label at or before `address` and the offset from it
+    pub fn label(&self, address:u16) -> Option<(&str, u16)> {
+        let index = self.labels.partition_point(|(start, _)| *start <= address).checked_sub(1)?;
+        let (start, name) = &self.labels[index];
+        Some((name, address - start))
+    }
+}
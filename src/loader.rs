@@ -0,0 +1,333 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hex::{HexError, HexImage};
+use crate::CPU;
+
+// Program loading from files
+// A `Loader` collects images in any of the supported formats, checking
+// every byte against the 64K address space and against what was collected
+// before, then writes the lot into memory with `CPU::load_from`. Nothing is
+// written unless everything was accepted.
+//
+// Formats are picked from the file extension, falling back to the content:
+//   Intel HEX      .hex .ihx, or ASCII text whose lines are all ':' records
+//   Motorola S     .s19 .s28 .s37 .srec .mot, or ASCII text whose lines are
+//                  all 'S0'-'S9' records
+//   CP/M .COM      .com; always loaded at 0x0100 with a zero page
+//   Binary         .bin .rom and anything else, loaded at the given address
+//
+// A load map lists one image per line as `<path> <address>`, plus an
+// optional `start <address>` line for the entry point. Paths are relative to
+// the map file. `#` starts a comment and addresses are decimal, 0x-prefixed
+// or h-suffixed hex:
+//
+//   # Space Invaders
+//   invaders.h 0000h
+//   invaders.g 0800h
+//   invaders.f 1000h
+//   invaders.e 1800h
+//   start 0
+
+// The bare .COM zero page: warm boot (0x0000) halts and BDOS calls (0x0005)
+// return straight away. `cpm::Bdos` can be installed over it.
+pub const COM_ADDRESS:u16 = 0x0100;
+pub const COM_BDOS:u16 = 0xFE00;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    IntelHex,
+    SRecord,
+    Com,
+    Binary,
+}
+
+impl Format {
+    pub fn detect(path:&Path, data:&[u8]) -> Format {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_ascii_lowercase();
+        match extension.as_str() {
+            "hex" | "ihx" => return Format::IntelHex,
+            "s19" | "s28" | "s37" | "srec" | "mot" => return Format::SRecord,
+            "com" => return Format::Com,
+            "bin" | "rom" => return Format::Binary,
+            _ => {}
+        }
+        // ROM images often start with bytes that look like a record, so the
+        // whole file has to be made of records to count as one
+        if !data.is_ascii() {
+            return Format::Binary;
+        }
+        let text = String::from_utf8_lossy(data);
+        let lines:Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let hex_digits = |text:&str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_hexdigit());
+        if lines.is_empty() {
+            Format::Binary
+        } else if lines.iter().all(|line| line.strip_prefix(':').is_some_and(hex_digits)) {
+            Format::IntelHex
+        } else if lines.iter().all(|line| line.strip_prefix('S').is_some_and(|rest| rest.starts_with(|digit:char| digit.is_ascii_digit()) && hex_digits(rest))) {
+            Format::SRecord
+        } else {
+            Format::Binary
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path:PathBuf, error:io::Error },
+    Hex(HexError),
+    SRecordSyntax { line:usize },
+    SRecordChecksum { line:usize },
+    // Data that would run past 0xFFFF
+    OutOfRange { address:u32, length:usize },
+    // A byte already loaded by an earlier image or record
+    Overlap { address:u16 },
+    LoadMap { line:usize, message:String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Hex(error) => write!(f, "Intel HEX: {}", error),
+            LoadError::SRecordSyntax { line } => write!(f, "S-record line {}: malformed record", line),
+            LoadError::SRecordChecksum { line } => write!(f, "S-record line {}: checksum error", line),
+            LoadError::OutOfRange { address, length } => {
+                write!(f, "{} bytes at {:04x} do not fit in 64K", length, address)
+            }
+            LoadError::Overlap { address } => write!(f, "{:04x} is loaded twice", address),
+            LoadError::LoadMap { line, message } => write!(f, "load map line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { error, .. } => Some(error),
+            LoadError::Hex(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<HexError> for LoadError {
+    fn from(error:HexError) -> Self {
+        LoadError::Hex(error)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MapEntry {
+    pub path:PathBuf,
+    pub address:u16,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct LoadMap {
+    pub entries:Vec<MapEntry>,
+    pub start:Option<u16>,
+}
+
+impl LoadMap {
+    pub fn parse(text:&str) -> Result<Self, LoadError> {
+        let mut map = LoadMap::default();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message:&str| LoadError::LoadMap { line: number, message: message.to_string() };
+            let fields:Vec<&str> = line.split_whitespace().collect();
+            let [name, address] = fields[..] else {
+                return Err(error("expected <path> <address>"));
+            };
+            let address = parse_address(address).ok_or_else(|| error("bad address"))?;
+            if name.eq_ignore_ascii_case("start") {
+                map.start = Some(address);
+            } else {
+                map.entries.push(MapEntry { path: PathBuf::from(name), address });
+            }
+        }
+        Ok(map)
+    }
+}
+
+pub fn parse_address(text:&str) -> Option<u16> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(digits) = lower.strip_prefix("0x") {
+        u16::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = lower.strip_suffix('h') {
+        u16::from_str_radix(digits, 16).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+pub struct Loader {
+    segments:Vec<(u16, Vec<u8>)>,
+    loaded:Vec<bool>,
+    pub start:Option<u16>,
+    pub stack:Option<u16>,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self { segments: Vec::new(), loaded: vec![false; 0x10000], start: None, stack: None }
+    }
+
+    pub fn add(&mut self, address:u32, data:&[u8]) -> Result<(), LoadError> {
+        if address as usize + data.len() > 0x10000 {
+            return Err(LoadError::OutOfRange { address, length: data.len() });
+        }
+        let start = address as usize;
+        if let Some(index) = self.loaded[start..start + data.len()].iter().position(|loaded| *loaded) {
+            return Err(LoadError::Overlap { address: (start + index) as u16 });
+        }
+        self.loaded[start..start + data.len()].fill(true);
+        self.segments.push((address as u16, data.to_vec()));
+        Ok(())
+    }
+
+    // `address` is where binary images go; the other formats carry their
+    // own addresses
+    pub fn add_image(&mut self, format:Format, data:&[u8], address:u16) -> Result<(), LoadError> {
+        match format {
+            Format::IntelHex => {
+                let image = HexImage::parse(&String::from_utf8_lossy(data))?;
+                for (address, bytes) in &image.segments {
+                    self.add(*address as u32, bytes)?;
+                }
+                self.start = image.start.or(self.start);
+            }
+            Format::SRecord => self.add_srecords(&String::from_utf8_lossy(data))?,
+            Format::Com => {
+                if COM_ADDRESS as usize + data.len() > COM_BDOS as usize - 2 {
+                    return Err(LoadError::OutOfRange { address: COM_ADDRESS as u32, length: data.len() });
+                }
+                let mut zero_page = [0u8; 0x100];
+                zero_page[0x0000] = 0x76;
+                zero_page[0x0005] = 0xC3;
+                zero_page[0x0006] = COM_BDOS as u8;
+                zero_page[0x0007] = (COM_BDOS >> 8) as u8;
+                self.add(0, &zero_page)?;
+                self.add(COM_ADDRESS as u32, data)?;
+                // A return address of 0x0000 on the stack, then the RET for BDOS
+                self.add(COM_BDOS as u32 - 2, &[0x00, 0x00, 0xC9])?;
+                self.start = Some(COM_ADDRESS);
+                self.stack = Some(COM_BDOS - 2);
+            }
+            Format::Binary => self.add(address as u32, data)?,
+        }
+        Ok(())
+    }
+
+    pub fn add_file<P:AsRef<Path>>(&mut self, path:P, address:u16) -> Result<Format, LoadError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|error| LoadError::Io { path: path.to_path_buf(), error })?;
+        let format = Format::detect(path, &data);
+        self.add_image(format, &data, address)?;
+        Ok(format)
+    }
+
+    // Adds every image in the map, with paths taken relative to `directory`
+    pub fn add_map<P:AsRef<Path>>(&mut self, map:&LoadMap, directory:P) -> Result<(), LoadError> {
+        for entry in &map.entries {
+            self.add_file(directory.as_ref().join(&entry.path), entry.address)?;
+        }
+        if map.start.is_some() {
+            self.start = map.start;
+        }
+        Ok(())
+    }
+
+    pub fn add_map_file<P:AsRef<Path>>(&mut self, path:P) -> Result<(), LoadError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| LoadError::Io { path: path.to_path_buf(), error })?;
+        let map = LoadMap::parse(&text)?;
+        self.add_map(&map, path.parent().unwrap_or(Path::new("")))
+    }
+
+    // S1/S2/S3 data and S7/S8/S9 start records; headers and counts are
+    // checked but otherwise ignored
+    fn add_srecords(&mut self, text:&str) -> Result<(), LoadError> {
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let syntax = || LoadError::SRecordSyntax { line: number };
+            let bytes = line.strip_prefix('S').filter(|rest| rest.len() >= 3 && rest.is_ascii()).ok_or_else(syntax)?;
+            let kind = bytes.as_bytes()[0];
+            let digits = &bytes[1..];
+            if digits.len() % 2 != 0 {
+                return Err(syntax());
+            }
+            let record:Option<Vec<u8>> = (0..digits.len()).step_by(2).map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok()).collect();
+            let record = record.ok_or_else(syntax)?;
+            if record.len() != record[0] as usize + 1 {
+                return Err(syntax());
+            }
+            if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+                return Err(LoadError::SRecordChecksum { line: number });
+            }
+
+            let address_size = match kind {
+                b'0' | b'1' | b'5' | b'9' => 2,
+                b'2' | b'6' | b'8' => 3,
+                b'3' | b'7' => 4,
+                _ => return Err(syntax()),
+            };
+            if record.len() < address_size + 2 {
+                return Err(syntax());
+            }
+            let address = record[1..=address_size].iter().fold(0u32, |address, byte| address << 8 | *byte as u32);
+            let data = &record[address_size + 1..record.len() - 1];
+            match kind {
+                b'1' | b'2' | b'3' => self.add(address, data)?,
+                b'7' | b'8' | b'9' => {
+                    if address > 0xFFFF {
+                        return Err(LoadError::OutOfRange { address, length: 0 });
+                    }
+                    self.start = Some(address as u16);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Writes everything into memory and sets PC and SP when an image gave
+    // them. Returns the start address.
+    pub fn load_into(&self, cpu:&mut CPU) -> Option<u16> {
+        for (address, data) in &self.segments {
+            cpu.load_from(data, *address as usize);
+        }
+        if let Some(start) = self.start {
+            cpu.pc = start;
+        }
+        if let Some(stack) = self.stack {
+            cpu.sp = stack;
+        }
+        self.start
+    }
+}
+
+impl CPU {
+    // Loads one file of any supported format; binary images go at `address`
+    pub fn load_file<P:AsRef<Path>>(&mut self, path:P, address:u16) -> Result<Option<u16>, LoadError> {
+        let mut loader = Loader::new();
+        loader.add_file(path, address)?;
+        Ok(loader.load_into(self))
+    }
+}
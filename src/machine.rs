@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::cpm::Bdos;
+use crate::devices::mits_88sio::{SIO_BASE, TWO_SIO_BASE};
+use crate::devices::{Mb14241, Mits88Sio, Mits88TwoSio};
+use crate::io::IoDevice;
+use crate::loader::{Format, LoadError, Loader};
+use crate::romset::{RomSet, RomSetError};
+use crate::CPU;
+
+// Ready made machines for the command line tools
+// A profile decides what sits around the CPU:
+//   bare      memory only
+//   cpm       the high level BDOS with drive A on a host directory
+//   altair    88-SIO at port 0x00 and 88-2SIO at 0x10 as the console
+//   invaders  the Space Invaders I/O board and its two video interrupts
+// `Machine::step` runs one instruction the way the profile needs and keeps
+// 64 bit cycle and instruction counts.
+
+// 2 MHz / 60 Hz / 2: RST 1 mid screen, RST 2 at vertical blank
+const INVADERS_HALF_FRAME:u64 = 16_667;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Profile {
+    Bare,
+    Cpm,
+    Altair,
+    Invaders,
+}
+
+impl Profile {
+    pub const ALL:[Profile; 4] = [Profile::Bare, Profile::Cpm, Profile::Altair, Profile::Invaders];
+
+    pub fn from_name(name:&str) -> Option<Profile> {
+        Self::ALL.into_iter().find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Bare => "bare",
+            Profile::Cpm => "cpm",
+            Profile::Altair => "altair",
+            Profile::Invaders => "invaders",
+        }
+    }
+}
+
+// Why a machine can't make progress any more
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    // HLT with nothing left to wake the CPU
+    Halted,
+    // A trap or semihosting stopped the CPU
+    Stopped,
+    // The CP/M program warm booted
+    Exited,
+    // A console read with no input queued
+    WaitingForInput,
+}
+
+// Space Invaders I/O ports: inputs on 0-2, the shift register on 2-4 and
+// sound and watchdog latches on 3, 5 and 6
+pub struct InvadersIo {
+    pub shifter:Mb14241,
+    pub inputs:[u8; 3],
+    pub sound:[u8; 2],
+    pub watchdog_writes:u64,
+}
+
+impl Default for InvadersIo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InvadersIo {
+    pub fn new() -> Self {
+        // Port 1 bit 3 is always high; port 0 has unused bits pulled up
+        Self { shifter: Mb14241::new(), inputs: [0x0E, 0x08, 0x00], sound: [0; 2], watchdog_writes: 0 }
+    }
+}
+
+impl IoDevice for InvadersIo {
+    fn input(&mut self, port:u8, _cycles:u32) -> u8 {
+        match port {
+            0..=2 => self.inputs[port as usize],
+            3 => self.shifter.result(),
+            _ => 0x00,
+        }
+    }
+
+    fn output(&mut self, port:u8, value:u8, _cycles:u32) {
+        match port {
+            2 => self.shifter.set_amount(value),
+            3 => self.sound[0] = value,
+            4 => self.shifter.write_data(value),
+            5 => self.sound[1] = value,
+            6 => self.watchdog_writes += 1,
+            _ => {}
+        }
+    }
+}
+
+pub struct Machine {
+    pub cpu:CPU,
+    pub profile:Profile,
+    pub bdos:Option<Rc<RefCell<Bdos>>>,
+    pub sio:Option<Rc<RefCell<Mits88Sio>>>,
+    pub two_sio:Option<Rc<RefCell<Mits88TwoSio>>>,
+    pub invaders:Option<Rc<RefCell<InvadersIo>>>,
+    pub cycles:u64,
+    pub instructions:u64,
+    next_interrupt:u64,
+    vertical_blank:bool,
+}
+
+impl Machine {
+    pub fn new(profile:Profile) -> Self {
+        let mut machine = Self {
+            cpu: CPU::new(),
+            profile,
+            bdos: None,
+            sio: None,
+            two_sio: None,
+            invaders: None,
+            cycles: 0,
+            instructions: 0,
+            next_interrupt: INVADERS_HALF_FRAME,
+            vertical_blank: false,
+        };
+
+        match profile {
+            Profile::Bare => {}
+            Profile::Cpm => {
+                let mut bdos = Bdos::new();
+                bdos.map_drive(0, ".");
+                machine.bdos = Some(Rc::new(RefCell::new(bdos)));
+            }
+            Profile::Altair => {
+                let sio = Rc::new(RefCell::new(Mits88Sio::new()));
+                let two_sio = Rc::new(RefCell::new(Mits88TwoSio::new()));
+                machine.cpu.io.attach(SIO_BASE, 2, sio.clone());
+                machine.cpu.io.attach(TWO_SIO_BASE, 4, two_sio.clone());
+                machine.sio = Some(sio);
+                machine.two_sio = Some(two_sio);
+            }
+            Profile::Invaders => {
+                let io = Rc::new(RefCell::new(InvadersIo::new()));
+                machine.cpu.io.attach(0, 8, io.clone());
+                machine.invaders = Some(io);
+            }
+        }
+        machine
+    }
+
+    // Loads an image of any format the loader knows. Under CP/M a .COM file
+    // is set up as the CCP would with `arguments` as its command tail, and
+    // anything else gets the BDOS installed around it.
+    pub fn load<P:AsRef<Path>>(&mut self, path:P, address:u16, arguments:&str) -> Result<Option<u16>, LoadError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|error| LoadError::Io { path: path.to_path_buf(), error })?;
+        let format = Format::detect(path, &data);
+
+        if let Some(bdos) = &self.bdos {
+            if format == Format::Com {
+                Bdos::load_program(bdos, &mut self.cpu, &data, arguments);
+                return Ok(Some(self.cpu.pc));
+            }
+            Bdos::install(bdos, &mut self.cpu);
+        }
+
+        let mut loader = Loader::new();
+        loader.add_image(format, &data, address)?;
+        let start = loader.load_into(&mut self.cpu);
+        if start.is_none() {
+            self.cpu.pc = address;
+        }
+        Ok(start)
+    }
+
+    pub fn load_romset<P:AsRef<Path>>(&mut self, set:&RomSet, directory:P) -> Result<(), RomSetError> {
+        set.load(directory, &mut self.cpu)?;
+        self.cpu.pc = 0;
+        Ok(())
+    }
+
+    // Runs one instruction, HLE call or interrupt acknowledge
+    pub fn step(&mut self) {
+        let before = self.cpu.cycles;
+
+        if self.invaders.is_some() && self.cycles >= self.next_interrupt {
+            let instruction = if self.vertical_blank { 0xD7 } else { 0xCF };
+            self.cpu.interrupt(&[instruction]);
+            self.vertical_blank = !self.vertical_blank;
+            self.next_interrupt += INVADERS_HALF_FRAME;
+        }
+
+        self.cpu.tick();
+
+        self.cycles += self.cpu.cycles.wrapping_sub(before) as u64;
+        self.instructions += 1;
+    }
+
+    pub fn stop_reason(&self) -> Option<Stop> {
+        // The BDOS stops the CPU for both of these
+        if let Some(bdos) = &self.bdos {
+            let bdos = bdos.borrow();
+            if bdos.exited() {
+                return Some(Stop::Exited);
+            }
+            if bdos.waiting_for_input() && bdos.pending_input() == 0 {
+                return Some(Stop::WaitingForInput);
+            }
+        }
+        if self.cpu.stopped {
+            return Some(Stop::Stopped);
+        }
+        // Only the Invaders board raises interrupts on its own
+        if self.cpu.halted && (self.invaders.is_none() || !self.cpu.int_enabled) {
+            return Some(Stop::Halted);
+        }
+        None
+    }
+
+    pub fn send_input(&mut self, bytes:&[u8]) {
+        if let Some(bdos) = &self.bdos {
+            let mut bdos = bdos.borrow_mut();
+            bdos.send_bytes(bytes);
+            // Retry the console read the CPU stopped on
+            if bdos.waiting_for_input() {
+                self.cpu.stopped = false;
+            }
+        }
+        // Altair software polls one or the other
+        if let Some(sio) = &self.sio {
+            sio.borrow_mut().send_bytes(bytes);
+        }
+        if let Some(two_sio) = &self.two_sio {
+            two_sio.borrow_mut().channel(0).send_bytes(bytes);
+        }
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let mut output = Vec::new();
+        if let Some(bdos) = &self.bdos {
+            output.extend(bdos.borrow_mut().take_output());
+        }
+        if let Some(sio) = &self.sio {
+            output.extend(sio.borrow_mut().take_output());
+        }
+        if let Some(two_sio) = &self.two_sio {
+            output.extend(two_sio.borrow_mut().channel(0).take_output());
+        }
+        output
+    }
+}
@@ -0,0 +1,299 @@
+use std::fmt;
+
+use crate::romset::crc32;
+use crate::CPU;
+
+// IPS and BPS patches
+// `apply` turns an original image into the patched one; the format is told
+// by the header. BPS patches carry CRC32s of the source, the target and the
+// patch itself, and all three are checked. IPS has no checksums.
+//
+// A `LivePatch` applies a patch to memory instead, keeping the bytes it
+// replaced so it can be switched on and off while debugging.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PatchError {
+    // Neither "PATCH" nor "BPS1"
+    UnknownFormat,
+    // The patch ends in the middle of a record
+    Truncated,
+    SourceSize { expected:usize, actual:usize },
+    SourceChecksum { expected:u32, actual:u32 },
+    TargetChecksum { expected:u32, actual:u32 },
+    PatchChecksum { expected:u32, actual:u32 },
+    // A BPS copy reading outside the source or target
+    OutOfRange,
+    // A live patch running past 0xFFFF
+    TooLarge,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS or BPS patch"),
+            PatchError::Truncated => write!(f, "patch is truncated"),
+            PatchError::SourceSize { expected, actual } => {
+                write!(f, "patch is for a {} byte image, this one is {}", expected, actual)
+            }
+            PatchError::SourceChecksum { expected, actual } => {
+                write!(f, "patch is for an image with CRC32 {:08x}, this one is {:08x}", expected, actual)
+            }
+            PatchError::TargetChecksum { expected, actual } => {
+                write!(f, "patched image has CRC32 {:08x}, expected {:08x}", actual, expected)
+            }
+            PatchError::PatchChecksum { expected, actual } => {
+                write!(f, "patch has CRC32 {:08x}, expected {:08x}", actual, expected)
+            }
+            PatchError::OutOfRange => write!(f, "patch copies from outside the image"),
+            PatchError::TooLarge => write!(f, "patched image runs past 0xFFFF"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+pub fn detect(patch:&[u8]) -> Option<PatchFormat> {
+    if patch.starts_with(b"PATCH") {
+        Some(PatchFormat::Ips)
+    } else if patch.starts_with(b"BPS1") {
+        Some(PatchFormat::Bps)
+    } else {
+        None
+    }
+}
+
+pub fn apply(patch:&[u8], source:&[u8]) -> Result<Vec<u8>, PatchError> {
+    match detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(patch, source),
+        Some(PatchFormat::Bps) => apply_bps(patch, source),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+struct Reader<'a> {
+    data:&'a [u8],
+    position:usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count:usize) -> Result<&'a [u8], PatchError> {
+        let end = self.position.checked_add(count).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.position..end).ok_or(PatchError::Truncated)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn big_endian(&mut self, count:usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(count)?.iter().fold(0, |value, byte| value << 8 | *byte as usize))
+    }
+
+    // BPS variable length number
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut value:usize = 0;
+        let mut shift:usize = 1;
+        loop {
+            let byte = self.bytes(1)?[0];
+            let digit = ((byte & 0x7F) as usize).checked_mul(shift).ok_or(PatchError::OutOfRange)?;
+            value = value.checked_add(digit).ok_or(PatchError::OutOfRange)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(128).ok_or(PatchError::OutOfRange)?;
+            value = value.checked_add(shift).ok_or(PatchError::OutOfRange)?;
+        }
+    }
+}
+
+// Records are a 24 bit offset and 16 bit size followed by the data, or a
+// zero size, 16 bit count and fill byte. "EOF" ends the patch and may be
+// followed by a 24 bit length to truncate to.
+pub fn apply_ips(patch:&[u8], source:&[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader { data: patch, position: 5 };
+    if !patch.starts_with(b"PATCH") {
+        return Err(PatchError::UnknownFormat);
+    }
+    let mut target = source.to_vec();
+    loop {
+        if reader.bytes(3)? == b"EOF" {
+            if patch.len() >= reader.position + 3 {
+                let length = reader.big_endian(3)?;
+                target.truncate(length);
+            }
+            return Ok(target);
+        }
+        reader.position -= 3;
+        let offset = reader.big_endian(3)?;
+        let size = reader.big_endian(2)?;
+        let (size, data) = if size == 0 {
+            let count = reader.big_endian(2)?;
+            (count, vec![reader.bytes(1)?[0]; count])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        target[offset..offset + size].copy_from_slice(&data);
+    }
+}
+
+const SOURCE_READ:usize = 0;
+const TARGET_READ:usize = 1;
+const SOURCE_COPY:usize = 2;
+const TARGET_COPY:usize = 3;
+
+pub fn apply_bps(patch:&[u8], source:&[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(b"BPS1") {
+        return Err(PatchError::UnknownFormat);
+    }
+    if patch.len() < 4 + 12 {
+        return Err(PatchError::Truncated);
+    }
+    let footer = patch.len() - 12;
+    let checksum = |offset:usize| u32::from_le_bytes([patch[offset], patch[offset + 1], patch[offset + 2], patch[offset + 3]]);
+    let (source_crc, target_crc, patch_crc) = (checksum(footer), checksum(footer + 4), checksum(footer + 8));
+
+    let actual = crc32(&patch[..footer + 8]);
+    if actual != patch_crc {
+        return Err(PatchError::PatchChecksum { expected: patch_crc, actual });
+    }
+
+    let mut reader = Reader { data: &patch[..footer], position: 4 };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    if source.len() != source_size {
+        return Err(PatchError::SourceSize { expected: source_size, actual: source.len() });
+    }
+    let actual = crc32(source);
+    if actual != source_crc {
+        return Err(PatchError::SourceChecksum { expected: source_crc, actual });
+    }
+
+    // The size comes from the patch, so it isn't trusted with an allocation
+    let mut target = Vec::new();
+    let mut source_offset:isize = 0;
+    let mut target_offset:isize = 0;
+    while reader.position < footer {
+        let command = reader.number()?;
+        let length = (command >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err(PatchError::OutOfRange);
+        }
+        match command & 3 {
+            SOURCE_READ => {
+                let start = target.len();
+                target.extend_from_slice(source.get(start..start + length).ok_or(PatchError::OutOfRange)?);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(length)?),
+            SOURCE_COPY | TARGET_COPY => {
+                let data = reader.number()?;
+                let delta = if data & 1 != 0 { -((data >> 1) as isize) } else { (data >> 1) as isize };
+                if command & 3 == SOURCE_COPY {
+                    source_offset = source_offset.checked_add(delta).ok_or(PatchError::OutOfRange)?;
+                    let start = usize::try_from(source_offset).map_err(|_| PatchError::OutOfRange)?;
+                    let end = start.checked_add(length).ok_or(PatchError::OutOfRange)?;
+                    target.extend_from_slice(source.get(start..end).ok_or(PatchError::OutOfRange)?);
+                    source_offset = end as isize;
+                } else {
+                    target_offset = target_offset.checked_add(delta).ok_or(PatchError::OutOfRange)?;
+                    // Byte by byte, the copy may read what it just wrote
+                    for _ in 0..length {
+                        let start = usize::try_from(target_offset).map_err(|_| PatchError::OutOfRange)?;
+                        let byte = *target.get(start).ok_or(PatchError::OutOfRange)?;
+                        target.push(byte);
+                        target_offset += 1;
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    let actual = crc32(&target);
+    if actual != target_crc {
+        return Err(PatchError::TargetChecksum { expected: target_crc, actual });
+    }
+    Ok(target)
+}
+
+// A patch applied to memory, made of the runs of bytes it changes
+pub struct LivePatch {
+    // Address, patched bytes, original bytes
+    changes:Vec<(u16, Vec<u8>, Vec<u8>)>,
+    applied:bool,
+}
+
+impl LivePatch {
+    // Patches the `size` bytes of memory at `address` as if they were the
+    // image the patch was made for. Memory isn't touched until `apply`.
+    pub fn new(cpu:&CPU, address:u16, size:usize, patch:&[u8]) -> Result<Self, PatchError> {
+        let start = address as usize;
+        let source = cpu.ram.get(start..start + size).ok_or(PatchError::TooLarge)?;
+        let target = apply(patch, source)?;
+        if start + target.len() > cpu.ram.len() {
+            return Err(PatchError::TooLarge);
+        }
+
+        let mut changes = Vec::new();
+        let mut index = 0;
+        while index < target.len() {
+            if cpu.ram[start + index] == target[index] {
+                index += 1;
+                continue;
+            }
+            let first = index;
+            while index < target.len() && cpu.ram[start + index] != target[index] {
+                index += 1;
+            }
+            changes.push(((start + first) as u16, target[first..index].to_vec(), cpu.ram[start + first..start + index].to_vec()));
+        }
+        Ok(Self { changes, applied: false })
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.applied
+    }
+
+    pub fn apply(&mut self, cpu:&mut CPU) {
+        for (address, patched, _) in &self.changes {
+            cpu.load_from(patched, *address as usize);
+        }
+        self.applied = true;
+    }
+
+    pub fn revert(&mut self, cpu:&mut CPU) {
+        for (address, _, original) in &self.changes {
+            cpu.load_from(original, *address as usize);
+        }
+        self.applied = false;
+    }
+
+    pub fn toggle(&mut self, cpu:&mut CPU) {
+        if self.applied {
+            self.revert(cpu);
+        } else {
+            self.apply(cpu);
+        }
+    }
+
+    // Number of bytes the patch changes
+    pub fn len(&self) -> usize {
+        self.changes.iter().map(|(_, patched, _)| patched.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
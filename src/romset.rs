@@ -0,0 +1,281 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::loader::{parse_address, LoadError, Loader};
+use crate::patch::{self, PatchError};
+use crate::CPU;
+
+// ROM sets
+// A set lists the chips a machine needs: file name, where the chip sits in
+// the memory map, its size and the CRC32 and SHA-1 of a good dump. Checking
+// a directory reports every chip as good, missing or bad; a set is only
+// mapped into memory when all of its chips are good.
+//
+// Manifests have one chip per line, `#` starting a comment:
+//
+//   <file> <address> <size> <crc32> [<sha1>]
+//
+// with the address and size in the same notation as load maps and the
+// hashes in hex.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RomChip {
+    pub name:String,
+    pub address:u16,
+    pub size:usize,
+    pub crc32:u32,
+    pub sha1:Option<[u8; 20]>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ChipStatus {
+    Good,
+    Missing,
+    WrongSize { actual:usize },
+    BadCrc32 { actual:u32 },
+    BadSha1 { actual:[u8; 20] },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChipCheck {
+    pub name:String,
+    pub status:ChipStatus,
+}
+
+#[derive(Debug)]
+pub enum RomSetError {
+    Manifest { line:usize, message:String },
+    Io(io::Error),
+    // Chips that are missing or don't match the manifest
+    BadDumps(Vec<ChipCheck>),
+    Load(LoadError),
+    Patch { chip:String, error:PatchError },
+}
+
+impl fmt::Display for RomSetError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomSetError::Manifest { line, message } => write!(f, "manifest line {}: {}", line, message),
+            RomSetError::Io(error) => write!(f, "{}", error),
+            RomSetError::BadDumps(checks) => {
+                write!(f, "bad ROM set:")?;
+                for check in checks {
+                    write!(f, " {} ({})", check.name, check.status)?;
+                }
+                Ok(())
+            }
+            RomSetError::Load(error) => write!(f, "{}", error),
+            RomSetError::Patch { chip, error } => write!(f, "{}: {}", chip, error),
+        }
+    }
+}
+
+impl std::error::Error for RomSetError {}
+
+impl fmt::Display for ChipStatus {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChipStatus::Good => write!(f, "good"),
+            ChipStatus::Missing => write!(f, "missing"),
+            ChipStatus::WrongSize { actual } => write!(f, "wrong size {}", actual),
+            ChipStatus::BadCrc32 { actual } => write!(f, "bad CRC32 {:08x}", actual),
+            ChipStatus::BadSha1 { actual } => write!(f, "bad SHA-1 {}", hex_string(actual)),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RomSet {
+    pub name:String,
+    pub chips:Vec<RomChip>,
+}
+
+impl RomSet {
+    // Space Invaders (Midway, 1978)
+    pub fn invaders() -> Self {
+        let chip = |name:&str, address:u16, crc32:u32, sha1:&str| RomChip {
+            name: name.to_string(),
+            address,
+            size: 0x0800,
+            crc32,
+            sha1: parse_sha1(sha1),
+        };
+        RomSet {
+            name: "invaders".to_string(),
+            chips: vec![
+                chip("invaders.h", 0x0000, 0x734F5AD8, "ff6200af4c9110d8181249cbcef1a8a40fa40b7f"),
+                chip("invaders.g", 0x0800, 0x6BFACA4A, "16f48649b531bdef8c2d1446c429b5f414524350"),
+                chip("invaders.f", 0x1000, 0x0CCEAD96, "537aef03468f63c5b9e11dd61e253f7ae17d9743"),
+                chip("invaders.e", 0x1800, 0x14E538B0, "1d6ca0c99f9df71e2990b610deb9d7da0125e2d8"),
+            ],
+        }
+    }
+
+    pub fn parse(name:&str, manifest:&str) -> Result<Self, RomSetError> {
+        let mut set = RomSet { name: name.to_string(), chips: Vec::new() };
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message:&str| RomSetError::Manifest { line: index + 1, message: message.to_string() };
+            let fields:Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 && fields.len() != 5 {
+                return Err(error("expected <file> <address> <size> <crc32> [<sha1>]"));
+            }
+            let address = parse_address(fields[1]).ok_or_else(|| error("bad address"))?;
+            let size = parse_address(fields[2]).filter(|size| *size > 0).ok_or_else(|| error("bad size"))? as usize;
+            let crc32 = u32::from_str_radix(fields[3], 16).map_err(|_| error("bad CRC32"))?;
+            let sha1 = match fields.get(4) {
+                Some(text) => Some(parse_sha1(text).ok_or_else(|| error("bad SHA-1"))?),
+                None => None,
+            };
+            set.chips.push(RomChip { name: fields[0].to_string(), address, size, crc32, sha1 });
+        }
+        Ok(set)
+    }
+
+    pub fn from_file<P:AsRef<Path>>(path:P) -> Result<Self, RomSetError> {
+        let path = path.as_ref();
+        let manifest = fs::read_to_string(path).map_err(RomSetError::Io)?;
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        Self::parse(&name, &manifest)
+    }
+
+    fn check(chip:&RomChip, data:Option<&[u8]>) -> ChipStatus {
+        let Some(data) = data else {
+            return ChipStatus::Missing;
+        };
+        if data.len() != chip.size {
+            return ChipStatus::WrongSize { actual: data.len() };
+        }
+        let crc = crc32(data);
+        if crc != chip.crc32 {
+            return ChipStatus::BadCrc32 { actual: crc };
+        }
+        if let Some(expected) = chip.sha1 {
+            let actual = sha1(data);
+            if actual != expected {
+                return ChipStatus::BadSha1 { actual };
+            }
+        }
+        ChipStatus::Good
+    }
+
+    // Checks every chip against the files in `directory`
+    pub fn verify<P:AsRef<Path>>(&self, directory:P) -> Vec<ChipCheck> {
+        self.chips
+            .iter()
+            .map(|chip| {
+                let data = fs::read(directory.as_ref().join(&chip.name)).ok();
+                ChipCheck { name: chip.name.clone(), status: Self::check(chip, data.as_deref()) }
+            })
+            .collect()
+    }
+
+    // Verifies the set and maps it into memory. Nothing is written if any
+    // chip is bad or the chips overlap.
+    pub fn load<P:AsRef<Path>>(&self, directory:P, cpu:&mut CPU) -> Result<(), RomSetError> {
+        self.load_patched(directory, &[], cpu)
+    }
+
+    // As `load`, with IPS or BPS patches applied to the named chips after
+    // they have been verified
+    pub fn load_patched<P:AsRef<Path>>(&self, directory:P, patches:&[(&str, &[u8])], cpu:&mut CPU) -> Result<(), RomSetError> {
+        let mut loader = Loader::new();
+        let mut bad = Vec::new();
+        for chip in &self.chips {
+            let data = fs::read(directory.as_ref().join(&chip.name)).ok();
+            match Self::check(chip, data.as_deref()) {
+                ChipStatus::Good => {
+                    let mut data = data.unwrap_or_default();
+                    for (_, patch) in patches.iter().filter(|(name, _)| *name == chip.name) {
+                        data = patch::apply(patch, &data).map_err(|error| RomSetError::Patch { chip: chip.name.clone(), error })?;
+                    }
+                    loader.add(chip.address as u32, &data).map_err(RomSetError::Load)?;
+                }
+                status => bad.push(ChipCheck { name: chip.name.clone(), status }),
+            }
+        }
+        if !bad.is_empty() {
+            return Err(RomSetError::BadDumps(bad));
+        }
+        loader.load_into(cpu);
+        Ok(())
+    }
+}
+
+fn parse_sha1(text:&str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+pub fn hex_string(bytes:&[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// CRC-32 as used by zip and MAME (reflected, polynomial 0x04C11DB7)
+pub fn crc32(data:&[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn sha1(data:&[u8]) -> [u8; 20] {
+    let mut h:[u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            w[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            w[index] = (w[index - 3] ^ w[index - 8] ^ w[index - 14] ^ w[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (index, word) in w.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut hash = [0u8; 20];
+    for (index, state) in h.iter().enumerate() {
+        hash[index * 4..index * 4 + 4].copy_from_slice(&state.to_be_bytes());
+    }
+    hash
+}
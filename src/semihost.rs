@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::CPU;
+
+// Semihosting for guest test programs
+// Writing a function code to the semihosting port with OUT performs it on the
+// host. Arguments go in BC, DE and HL; results come back in A (0 = ok,
+// 0xFF = failed) and the registers listed below. Strings are NUL terminated.
+// Paths are relative to the root directory and can't leave it: absolute
+// paths and `..` fail.
+// Exiting stops the CPU (see `CPU::stopped`) instead of ending the process,
+// so a harness can collect the exit code, output and assertion results.
+//
+//   0x00 EXIT        C = exit code
+//   0x01 PUTCHAR     C = character
+//   0x02 PUTS        HL = string
+//   0x03 WRITE       HL = buffer, BC = length
+//   0x10 READ_FILE   HL = path, DE = destination, BC = maximum length;
+//                    BC = bytes read
+//   0x11 WRITE_FILE  HL = path, DE = source, BC = length
+//   0x20 CYCLES      DE:HL = cycle counter
+//   0x30 ASSERT      C = condition (0 fails), HL = message or 0
+
+pub const EXIT:u8 = 0x00;
+pub const PUTCHAR:u8 = 0x01;
+pub const PUTS:u8 = 0x02;
+pub const WRITE:u8 = 0x03;
+pub const READ_FILE:u8 = 0x10;
+pub const WRITE_FILE:u8 = 0x11;
+pub const CYCLES:u8 = 0x20;
+pub const ASSERT:u8 = 0x30;
+
+const OK:u8 = 0x00;
+const FAILED:u8 = 0xFF;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssertFailure {
+    pub pc:u16, // Address of the OUT instruction
+    pub message:String,
+}
+
+pub struct Semihost {
+    pub port:u8,
+    // Guest paths are resolved against this directory
+    pub root:PathBuf,
+
+    output:Vec<u8>,
+    exit_code:Option<u8>,
+    passed:u32,
+    failures:Vec<AssertFailure>,
+}
+
+impl Semihost {
+    pub fn new<P:AsRef<Path>>(port:u8, root:P) -> Self {
+        Self {
+            port,
+            root: root.as_ref().to_path_buf(),
+            output: Vec::new(),
+            exit_code: None,
+            passed: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    pub fn passed(&self) -> u32 {
+        self.passed
+    }
+
+    pub fn failures(&self) -> &[AssertFailure] {
+        &self.failures
+    }
+
+    // Exited with code 0 and no failed assertions
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && self.failures.is_empty()
+    }
+
+    // Performs the function in A. Called by the CPU on OUT to `port`, with
+    // PC still pointing at the port byte of the instruction.
+    pub fn call(&mut self, cpu:&mut CPU) {
+        let bc = (cpu.b as u16) << 8 | cpu.c as u16;
+        let de = (cpu.d as u16) << 8 | cpu.e as u16;
+        let hl = (cpu.h as u16) << 8 | cpu.l as u16;
+
+        cpu.a = match cpu.a {
+            EXIT => {
+                self.exit_code = Some(cpu.c);
+                cpu.stopped = true;
+                OK
+            }
+            PUTCHAR => {
+                self.output.push(cpu.c);
+                OK
+            }
+            PUTS => {
+                let string = read_string(cpu, hl);
+                self.output.extend(string);
+                OK
+            }
+            WRITE => {
+                self.output.extend((0..bc).map(|index| cpu.ram[hl.wrapping_add(index) as usize]));
+                OK
+            }
+            READ_FILE => {
+                match self.path(cpu, hl).and_then(|path| fs::read(path).ok()) {
+                    Some(data) => {
+                        let count = data.len().min(bc as usize);
+                        for (index, byte) in data[..count].iter().enumerate() {
+                            cpu.ram[de.wrapping_add(index as u16) as usize] = *byte;
+                        }
+                        cpu.b = (count >> 8) as u8;
+                        cpu.c = count as u8;
+                        OK
+                    }
+                    None => {
+                        cpu.b = 0;
+                        cpu.c = 0;
+                        FAILED
+                    }
+                }
+            }
+            WRITE_FILE => {
+                let data:Vec<u8> = (0..bc).map(|index| cpu.ram[de.wrapping_add(index) as usize]).collect();
+                match self.path(cpu, hl).map(|path| fs::write(path, data)) {
+                    Some(Ok(_)) => OK,
+                    _ => FAILED,
+                }
+            }
+            CYCLES => {
+                let cycles = cpu.cycles;
+                cpu.d = (cycles >> 24) as u8;
+                cpu.e = (cycles >> 16) as u8;
+                cpu.h = (cycles >> 8) as u8;
+                cpu.l = cycles as u8;
+                OK
+            }
+            ASSERT => {
+                if cpu.c != 0 {
+                    self.passed += 1;
+                } else {
+                    let message = if hl == 0 { String::new() } else { String::from_utf8_lossy(&read_string(cpu, hl)).into_owned() };
+                    self.failures.push(AssertFailure { pc: cpu.pc.wrapping_sub(1), message });
+                }
+                OK
+            }
+            _ => FAILED,
+        };
+    }
+
+    // None for paths that would leave `root`
+    fn path(&self, cpu:&CPU, address:u16) -> Option<PathBuf> {
+        let path = PathBuf::from(String::from_utf8_lossy(&read_string(cpu, address)).into_owned());
+        if !path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+            return None;
+        }
+        Some(self.root.join(path))
+    }
+}
+
+fn read_string(cpu:&CPU, address:u16) -> Vec<u8> {
+    let mut string = Vec::new();
+    let mut address = address;
+    while cpu.ram[address as usize] != 0 && string.len() < 0xFFFF {
+        string.push(cpu.ram[address as usize]);
+        address = address.wrapping_add(1);
+    }
+    string
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::CPU;
+
+// Calling 8080 subroutines from Rust, mainly for unit testing assembly code.
+// `call_subroutine` pushes a sentinel return address, runs until execution
+// reaches it and hands back the registers along with the cycles used.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Registers {
+    pub a:u8,
+    pub flags:u8, // As pushed by PUSH PSW: S Z 0 AC 0 P 1 CY
+    pub b:u8,
+    pub c:u8,
+    pub d:u8,
+    pub e:u8,
+    pub h:u8,
+    pub l:u8,
+    pub sp:u16,
+}
+
+impl Registers {
+    pub fn bc(&self) -> u16 {
+        (self.b as u16) << 8 | self.c as u16
+    }
+
+    pub fn de(&self) -> u16 {
+        (self.d as u16) << 8 | self.e as u16
+    }
+
+    pub fn hl(&self) -> u16 {
+        (self.h as u16) << 8 | self.l as u16
+    }
+
+    pub fn set_bc(&mut self, value:u16) {
+        self.b = (value >> 8) as u8;
+        self.c = value as u8;
+    }
+
+    pub fn set_de(&mut self, value:u16) {
+        self.d = (value >> 8) as u8;
+        self.e = value as u8;
+    }
+
+    pub fn set_hl(&mut self, value:u16) {
+        self.h = (value >> 8) as u8;
+        self.l = value as u8;
+    }
+
+    pub fn carry(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    pub fn zero(&self) -> bool {
+        self.flags & 0x40 != 0
+    }
+
+    pub fn sign(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+
+    pub fn parity(&self) -> bool {
+        self.flags & 0x04 != 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CpuError {
+    InstructionLimit { pc:u16, instructions:u64 },
+    CycleLimit { pc:u16, cycles:u64 },
+    // Returned to the sentinel with SP somewhere else than before the call
+    StackImbalance { expected:u16, actual:u16 },
+    // HLT with interrupts disabled
+    Halted { pc:u16 },
+    // A trap or semihosting exit stopped the CPU
+    Stopped { pc:u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::InstructionLimit { pc, instructions } => {
+                write!(f, "instruction limit of {} reached at {:04x}", instructions, pc)
+            }
+            CpuError::CycleLimit { pc, cycles } => write!(f, "cycle limit of {} reached at {:04x}", cycles, pc),
+            CpuError::StackImbalance { expected, actual } => {
+                write!(f, "stack imbalance: SP is {:04x} on return, expected {:04x}", actual, expected)
+            }
+            CpuError::Halted { pc } => write!(f, "halted with interrupts disabled at {:04x}", pc),
+            CpuError::Stopped { pc } => write!(f, "stopped at {:04x}", pc),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CallOptions {
+    pub max_instructions:u64,
+    pub max_cycles:u64,
+    // Pushed as the return address; the call ends when PC gets there
+    pub return_address:u16,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self { max_instructions: 10_000_000, max_cycles: u64::MAX, return_address: 0xFFFF }
+    }
+}
+
+impl CPU {
+    pub fn call_subroutine(&mut self, address:u16, registers:Registers) -> Result<(Registers, u64), CpuError> {
+        self.call_subroutine_with(address, registers, CallOptions::default())
+    }
+
+    // Runs the subroutine at `address` with `registers` (SP included) until
+    // it returns, and reports the final registers and the cycles it took
+    pub fn call_subroutine_with(&mut self, address:u16, registers:Registers, options:CallOptions) -> Result<(Registers, u64), CpuError> {
+        self.set_registers(&registers);
+        self.halted = false;
+        self.stopped = false;
+
+        let sentinel = options.return_address;
+        self.sp = self.sp.wrapping_sub(2);
+        self.ram[self.sp as usize] = sentinel as u8;
+        self.ram[self.sp.wrapping_add(1) as usize] = (sentinel >> 8) as u8;
+        self.pc = address;
+
+        let mut instructions:u64 = 0;
+        let mut cycles:u64 = 0;
+        loop {
+            if self.pc == sentinel && !self.halted {
+                if self.sp != registers.sp {
+                    return Err(CpuError::StackImbalance { expected: registers.sp, actual: self.sp });
+                }
+                return Ok((self.registers(), cycles));
+            }
+            if instructions >= options.max_instructions {
+                return Err(CpuError::InstructionLimit { pc: self.pc, instructions });
+            }
+            if cycles >= options.max_cycles {
+                return Err(CpuError::CycleLimit { pc: self.pc, cycles });
+            }
+            if self.halted && !self.int_enabled {
+                return Err(CpuError::Halted { pc: self.pc.wrapping_sub(1) });
+            }
+
+            let before = self.cycles;
+            self.step();
+            if self.stopped {
+                return Err(CpuError::Stopped { pc: self.pc });
+            }
+            instructions += 1;
+            cycles += self.cycles.wrapping_sub(before) as u64;
+        }
+    }
+}
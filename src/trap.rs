@@ -0,0 +1,43 @@
+use crate::CPU;
+
+// High level emulation traps
+// A trap is a Rust closure attached to an address. PC traps run when the CPU
+// is about to execute the instruction at that address; CALL traps run when a
+// CALL (conditional or not) to that address is taken, after the return
+// address has been pushed. The closure has full access to the CPU and picks
+// what happens next.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrapAction {
+    // Pop the return address as if a RET had executed at the address
+    Return,
+    // Carry on executing at PC (which the closure may have changed)
+    Continue,
+    // Stop the CPU with PC left at the address until `CPU::resume`
+    Stop,
+}
+
+pub type TrapHandler = Box<dyn FnMut(&mut CPU) -> TrapAction>;
+
+// The CP/M console calls the cpudiag test makes through BDOS (CALL 5)
+#[cfg(feature = "cpudiag")]
+pub fn cpudiag_bdos(cpu:&mut CPU) -> TrapAction {
+    if cpu.c == 9 {
+        let mut offset = ((cpu.d as u16) << 8) | cpu.e as u16;
+        loop {
+            let character = cpu.ram[offset as usize];
+
+            if character as char == '$' {
+                break;
+            } else {
+                offset += 1;
+            }
+            print!("{}", character as char);
+        }
+        println!();
+    }
+    if cpu.c == 2 {
+        println!("{}", cpu.e as char)
+    }
+    TrapAction::Continue
+}
@@ -0,0 +1,43 @@
+use intel8080_core::asm8080;
+
+#[test]
+fn rst_vectors_may_be_variables() {
+    let vector = 7;
+    let code = asm8080! {
+        rst vector;
+        rst 1
+    };
+    assert_eq!(code, [0xFF, 0xCF]);
+}
+
+#[test]
+#[should_panic(expected = "rst vector must be 0-7")]
+fn rst_vectors_past_7_panic() {
+    let vector = 256;
+    asm8080! {
+        rst vector
+    };
+}
+
+#[test]
+fn labels_resolve_forwards_and_backwards() {
+    let code = asm8080! {
+        org 0x100;
+        jmp start;
+    message:
+        db "hi";
+    start:
+        lxi h, message;
+        call subroutine;
+    again:
+        jnz again;
+        hlt;
+    subroutine:
+        ret;
+        dw start
+    };
+    assert_eq!(
+        code,
+        [0xC3, 0x05, 0x01, b'h', b'i', 0x21, 0x03, 0x01, 0xCD, 0x0F, 0x01, 0xC2, 0x0B, 0x01, 0x76, 0xC9, 0x05, 0x01]
+    );
+}
@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use intel8080_core::cpm::bios::skew_table;
+use intel8080_core::cpm::{Bdos, Bios, CpmDrive, DiskParameterBlock};
+use intel8080_core::disk::{DiskGeometry, DiskImage};
+use intel8080_core::CPU;
+
+fn run(cpu:&mut CPU, steps:usize) {
+    for _ in 0..steps {
+        cpu.tick();
+    }
+}
+
+#[test]
+fn bdos_calls_return_through_traps() {
+    let bdos = Rc::new(RefCell::new(Bdos::new()));
+    let mut cpu = CPU::new();
+    // MVI C,1; CALL 5; MOV E,A; MVI C,2; CALL 5; MVI C,0; CALL 5
+    let program = [0x0E, 0x01, 0xCD, 0x05, 0x00, 0x5F, 0x0E, 0x02, 0xCD, 0x05, 0x00, 0x0E, 0x00, 0xCD, 0x05, 0x00];
+    Bdos::load_program(&bdos, &mut cpu, &program, "");
+
+    // Console input blocks with the CPU stopped at the entry point
+    run(&mut cpu, 10);
+    assert!(cpu.stopped);
+    assert!(bdos.borrow().waiting_for_input());
+    let sp = cpu.sp;
+
+    bdos.borrow_mut().send(b'x');
+    cpu.stopped = false;
+    run(&mut cpu, 10);
+    assert!(cpu.stopped);
+    assert!(bdos.borrow().exited());
+    assert_eq!(cpu.sp, sp);
+    assert_eq!(bdos.borrow_mut().take_output(), b"xx");
+}
+
+#[test]
+fn fcbs_wrap_around_the_top_of_memory() {
+    for function in [10, 15, 16, 17, 19, 20, 21, 22, 23, 30, 33, 34, 35, 36, 40] {
+        let bdos = Rc::new(RefCell::new(Bdos::new()));
+        bdos.borrow_mut().map_drive(0, "/nonexistent/cpm");
+        bdos.borrow_mut().send_bytes(b"abc\r");
+        let mut cpu = CPU::new();
+        // MVI C,function; LXI D,FFF0; CALL 5; HLT
+        let program = [0x0E, function, 0x11, 0xF0, 0xFF, 0xCD, 0x05, 0x00, 0x76];
+        Bdos::load_program(&bdos, &mut cpu, &program, "");
+        cpu.ram[0xFFF0] = 8;
+        cpu.ram[0xFFF1..].fill(b'?');
+        run(&mut cpu, 6);
+        assert!(cpu.halted, "function {}", function);
+    }
+}
+
+// Runs BDOS `function` on an FCB at 0x1000 holding `name` and, from
+// offset 16, `new_name`; returns A
+fn fcb_call(bdos:&Rc<RefCell<Bdos>>, function:u8, name:&[u8; 11], new_name:&[u8; 11]) -> u8 {
+    let mut cpu = CPU::new();
+    // MVI C,function; LXI D,1000; CALL 5; HLT
+    let program = [0x0E, function, 0x11, 0x00, 0x10, 0xCD, 0x05, 0x00, 0x76];
+    Bdos::load_program(bdos, &mut cpu, &program, "");
+    cpu.ram[0x1000] = 0;
+    cpu.ram[0x1001..0x100C].copy_from_slice(name);
+    cpu.ram[0x1010] = 0;
+    cpu.ram[0x1011..0x101C].copy_from_slice(new_name);
+    run(&mut cpu, 6);
+    assert!(cpu.halted);
+    cpu.a
+}
+
+#[test]
+fn fcb_names_stay_inside_the_drive_directory() {
+    const MAKE:u8 = 22;
+    const RENAME:u8 = 23;
+    let root = std::env::temp_dir().join(format!("cpm-names-{}", std::process::id()));
+    let drive = root.join("a");
+    fs::create_dir_all(&drive).unwrap();
+    let bdos = Rc::new(RefCell::new(Bdos::new()));
+    bdos.borrow_mut().map_drive(0, drive.to_str().unwrap());
+
+    for name in [b"../X       ", b"..         ", b"/X         ", b"\\X         ", b"A B     TXT"] {
+        assert_eq!(fcb_call(&bdos, MAKE, name, name), 0xFF, "{}", String::from_utf8_lossy(name));
+    }
+    assert_eq!(fcb_call(&bdos, MAKE, b"OLD     TXT", b"           "), 0);
+    assert_eq!(fcb_call(&bdos, RENAME, b"OLD     TXT", b"../Y    TXT"), 0xFF);
+    assert_eq!(fcb_call(&bdos, RENAME, b"OLD     TXT", b"NEW     TXT"), 0);
+
+    let mut outside:Vec<_> = fs::read_dir(&root).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    let mut inside:Vec<_> = fs::read_dir(&drive).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    outside.sort();
+    inside.sort();
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(outside, ["a"]);
+    assert_eq!(inside, ["NEW.TXT"]);
+}
+
+#[test]
+fn low_bdos_base_is_clamped() {
+    let bdos = Rc::new(RefCell::new(Bdos::with_base(0x0080)));
+    assert_eq!(bdos.borrow().base, 0x0100);
+    let mut cpu = CPU::new();
+    Bdos::load_program(&bdos, &mut cpu, &[0x76], "");
+    assert_eq!(cpu.pc, 0x0100);
+
+    bdos.borrow_mut().base = 0;
+    Bdos::load_program(&bdos, &mut cpu, &[0x76], "");
+    assert_eq!(cpu.pc, 0x0100);
+}
+
+// Calls BIOS function `function` from a HLT at 0x0000 and returns HL
+fn bios_call(cpu:&mut CPU, bios:&Rc<RefCell<Bios>>, function:u16, bc:u16, de:u16) -> u16 {
+    cpu.ram[0x0000] = 0x76;
+    cpu.ram[0x1FFE..0x2000].fill(0x00);
+    cpu.sp = 0x1FFE;
+    cpu.pc = bios.borrow().base + function * 3;
+    (cpu.b, cpu.c, cpu.d, cpu.e) = ((bc >> 8) as u8, bc as u8, (de >> 8) as u8, de as u8);
+    cpu.halted = false;
+    run(cpu, 3);
+    assert!(cpu.halted && cpu.pc == 0x0001, "BIOS function {}", function);
+    (cpu.h as u16) << 8 | cpu.l as u16
+}
+
+#[test]
+fn skewed_records_round_trip_on_512_byte_sectors() {
+    const SELDSK:u16 = 9;
+    const SETTRK:u16 = 10;
+    const SETSEC:u16 = 11;
+    const SETDMA:u16 = 12;
+    const READ:u16 = 13;
+    const WRITE:u16 = 14;
+    const SECTRAN:u16 = 16;
+
+    let dpb = DiskParameterBlock { spt: 36, bsh: 4, blm: 15, exm: 1, dsm: 170, drm: 63, al0: 0x80, al1: 0, cks: 16, off: 2 };
+    let bios = Rc::new(RefCell::new(Bios::new(64)));
+    let drive = CpmDrive::new(DiskImage::blank(DiskGeometry::MINI_DSDD), dpb, Some(skew_table(36, 5, 1)));
+    bios.borrow_mut().set_drive(0, drive);
+    let mut cpu = CPU::new();
+    assert!(Bios::install(&bios, &mut cpu));
+
+    let dph = bios_call(&mut cpu, &bios, SELDSK, 0, 0);
+    let xlt = cpu.ram[dph as usize] as u16 | (cpu.ram[dph as usize + 1] as u16) << 8;
+    bios_call(&mut cpu, &bios, SETTRK, 2, 0);
+    bios_call(&mut cpu, &bios, SETDMA, 0x1000, 0);
+    for record in 0..36 {
+        let sector = bios_call(&mut cpu, &bios, SECTRAN, record, xlt);
+        bios_call(&mut cpu, &bios, SETSEC, sector, 0);
+        cpu.ram[0x1000..0x1080].fill(record as u8 + 1);
+        bios_call(&mut cpu, &bios, WRITE, 0, 0);
+        assert_eq!(cpu.a, 0, "writing record {}", record);
+    }
+    for record in 0..36 {
+        let sector = bios_call(&mut cpu, &bios, SECTRAN, record, xlt);
+        bios_call(&mut cpu, &bios, SETSEC, sector, 0);
+        bios_call(&mut cpu, &bios, READ, 0, 0);
+        assert_eq!(cpu.a, 0, "reading record {}", record);
+        assert!(cpu.ram[0x1000..0x1080].iter().all(|byte| *byte == record as u8 + 1), "record {}", record);
+    }
+
+    // Track 2 is cylinder 1, side 0, filled by the 36 records
+    let bios = bios.borrow();
+    let image = &bios.drive(0).unwrap().image;
+    for sector in 1..=9 {
+        assert!(image.read_sector(1, 0, sector).unwrap().iter().all(|byte| *byte != 0), "sector {}", sector);
+    }
+}
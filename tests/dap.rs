@@ -0,0 +1,133 @@
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use intel8080_core::dap::DapServer;
+use intel8080_core::json::Json;
+
+fn request(seq:i64, command:&str, arguments:Json) -> Vec<u8> {
+    let body = Json::object([("seq", seq.into()), ("type", "request".into()), ("command", command.into()), ("arguments", arguments)]).to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+}
+
+// Requests for the server, each sent once the stops asked for by the
+// requests before it have been reported
+struct Requests {
+    pending:Vec<(Vec<u8>, bool)>,
+    stops:Receiver<()>,
+}
+
+impl Read for Requests {
+    fn read(&mut self, buffer:&mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        let (bytes, wait) = &mut self.pending[0];
+        if *wait {
+            // The server only goes away once it has stopped reading
+            let _ = self.stops.recv();
+            *wait = false;
+        }
+        let length = bytes.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&bytes[..length]);
+        bytes.drain(..length);
+        if bytes.is_empty() {
+            self.pending.remove(0);
+        }
+        Ok(length)
+    }
+}
+
+// Collects the server output and signals each stopped event
+struct Output {
+    bytes:Vec<u8>,
+    stops:Sender<()>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buffer:&[u8]) -> io::Result<usize> {
+        let event = b"\"event\":\"stopped\"";
+        let before = self.bytes.windows(event.len()).filter(|window| window == event).count();
+        self.bytes.extend_from_slice(buffer);
+        let after = self.bytes.windows(event.len()).filter(|window| window == event).count();
+        for _ in before..after {
+            let _ = self.stops.send(());
+        }
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs the requests and returns the responses to them. A request after
+// one that resumes the program waits for it to stop again.
+fn session(requests:&[(&str, Json)]) -> Vec<Json> {
+    let (sender, receiver) = mpsc::channel();
+    let mut pending = Vec::new();
+    let mut wait = false;
+    for (index, (command, arguments)) in requests.iter().enumerate() {
+        pending.push((request(index as i64 + 1, command, arguments.clone()), wait));
+        wait = matches!(*command, "configurationDone" | "continue" | "next" | "stepIn" | "stepOut");
+    }
+    let mut output = Output { bytes: Vec::new(), stops: sender };
+    DapServer::new(&mut output).serve(BufReader::new(Requests { pending, stops: receiver })).unwrap();
+
+    let text = String::from_utf8(output.bytes).unwrap();
+    text.split("Content-Length: ")
+        .filter_map(|message| message.split_once("\r\n\r\n"))
+        .map(|(_, body)| Json::parse(body).unwrap())
+        .filter(|message| message.get("type").as_str() == Some("response"))
+        .collect()
+}
+
+#[test]
+fn out_of_range_offsets_are_clamped() {
+    let program = std::env::temp_dir().join(format!("dap-{}.bin", std::process::id()));
+    fs::write(&program, [0x00, 0x00, 0x76]).unwrap();
+    let launch = Json::object([("program", program.to_str().unwrap().into()), ("stopOnEntry", true.into())]);
+    let disassemble = |offset:i64, count:i64| {
+        Json::object([("memoryReference", "0x0002".into()), ("instructionOffset", offset.into()), ("instructionCount", count.into())])
+    };
+    let responses = session(&[
+        ("launch", launch),
+        ("configurationDone", Json::object([])),
+        ("disassemble", disassemble(-1_000_000, 2)),
+        ("disassemble", disassemble(1_000_000_000_000, 1)),
+        ("disassemble", disassemble(0, 1_000_000_000_000)),
+        ("readMemory", Json::object([("memoryReference", "0x0002".into()), ("offset", i64::MAX.into()), ("count", 4.into())])),
+        ("disconnect", Json::object([])),
+    ]);
+    fs::remove_file(&program).unwrap();
+
+    assert_eq!(responses.len(), 7);
+    assert!(responses.iter().all(|response| response.get("success").as_bool() == Some(true)));
+    assert_eq!(responses[2].get("body").get("instructions").as_array().len(), 2);
+    assert_eq!(responses[4].get("body").get("instructions").as_array().len(), 0x10000);
+    assert_eq!(responses[5].get("body").get("data").as_str(), Some(""));
+}
+
+// A call made with SP at 0x0000 pushes the return address at the top of
+// memory, and the return wraps SP back round to 0x0000
+#[test]
+fn step_over_returns_with_the_stack_at_the_top_of_memory() {
+    let program = std::env::temp_dir().join(format!("dap-wrap-{}.bin", std::process::id()));
+    // LXI SP,0; CALL 8; HLT; NOP; 8: RET
+    fs::write(&program, [0x31, 0x00, 0x00, 0xCD, 0x08, 0x00, 0x76, 0x00, 0xC9]).unwrap();
+    let launch = Json::object([("program", program.to_str().unwrap().into()), ("stopOnEntry", true.into())]);
+    let thread = || Json::object([("threadId", 1.into())]);
+    let responses = session(&[
+        ("launch", launch),
+        ("configurationDone", Json::object([])),
+        ("next", thread()),
+        ("next", thread()),
+        ("stackTrace", thread()),
+        ("disconnect", Json::object([])),
+    ]);
+    fs::remove_file(&program).unwrap();
+
+    let frames = responses[4].get("body").get("stackFrames").as_array();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].get("instructionPointerReference").as_str(), Some("0x0006"));
+}
@@ -0,0 +1,28 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use intel8080_core::devices::i8257::{Channel, Dma8257};
+use intel8080_core::CPU;
+
+// A DRQ that stays high on a channel without TC stop keeps asking for the
+// bus; the CPU must still get to run between grants
+#[test]
+fn held_dreq_lets_tick_return() {
+    let mut cpu = CPU::new();
+    let dma = Rc::new(RefCell::new(Dma8257::new()));
+    {
+        let mut dma = dma.borrow_mut();
+        // Verify transfers of 16K bytes, looping forever as the count wraps
+        dma.channels[0] = Channel { address: 0x4000, count: 0x3FFF };
+        dma.mode = 0x01;
+        dma.set_dreq(0, true);
+    }
+    cpu.attach_bus_master(dma.clone());
+
+    for _ in 0..4 {
+        cpu.tick();
+    }
+    // Four NOPs ran between the bursts
+    assert_eq!(cpu.pc, 4);
+    assert!(dma.borrow().stolen_cycles > 0);
+}
@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::io::{self, Cursor, Read, Write};
+use std::rc::Rc;
+
+use intel8080_core::gdb::{Connection, GdbServer};
+use intel8080_core::machine::{Machine, Profile};
+
+// Canned debugger input; everything the server sends is kept
+struct Pipe {
+    input:Cursor<Vec<u8>>,
+    output:Rc<RefCell<Vec<u8>>>,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buffer:&mut [u8]) -> io::Result<usize> {
+        self.input.read(buffer)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buffer:&[u8]) -> io::Result<usize> {
+        self.output.borrow_mut().extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection for Pipe {}
+
+fn packet(data:&[u8]) -> Vec<u8> {
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let mut packet = vec![b'$'];
+    packet.extend_from_slice(data);
+    packet.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+    packet
+}
+
+#[test]
+fn unknown_commands_get_an_empty_reply() {
+    let mut input = packet(b"\xffx");
+    input.extend(packet(b"?"));
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let pipe = Pipe { input: Cursor::new(input), output: output.clone() };
+    GdbServer::new(Machine::new(Profile::Bare), pipe).serve().unwrap();
+
+    let mut expected = b"+".to_vec();
+    expected.extend(packet(b""));
+    expected.push(b'+');
+    expected.extend(packet(b"S05"));
+    assert_eq!(*output.borrow(), expected);
+}
+
+// Runs the packets against `machine` and returns the reply data
+fn replies(machine:Machine, packets:&[&[u8]]) -> Vec<String> {
+    let input:Vec<u8> = packets.iter().flat_map(|data| packet(data)).collect();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let pipe = Pipe { input: Cursor::new(input), output: output.clone() };
+    GdbServer::new(machine, pipe).serve().unwrap();
+
+    let output = String::from_utf8(output.borrow().clone()).unwrap();
+    output.split('$').skip(1).map(|reply| reply.split_once('#').unwrap().0.to_string()).collect()
+}
+
+// MVI A,42h; LXI H,2000h; MOV M,A; INR A; HLT
+fn program() -> Machine {
+    let mut machine = Machine::new(Profile::Bare);
+    machine.cpu.load_from(&[0x3E, 0x42, 0x21, 0x00, 0x20, 0x77, 0x3C, 0x76], 0x0000);
+    machine.cpu.pc = 0;
+    machine
+}
+
+#[test]
+fn registers_read_and_write() {
+    let replies = replies(program(), &[b"G021144336655887700100000", b"g", b"p3", b"P3=3412", b"p3", b"p9", b"P5=0300", b"p5"]);
+    assert_eq!(replies, ["OK", "021144336655887700100000", "8877", "OK", "3412", "E01", "OK", "0300"]);
+}
+
+#[test]
+fn memory_reads_and_writes() {
+    let replies = replies(program(), &[b"M2000,2:abcd", b"X2002,2:AB", b"m2000,4", b"m0000,2", b"M2000,2:ab"]);
+    assert_eq!(replies, ["OK", "OK", "abcd4142", "3e42", "E01"]);
+}
+
+#[test]
+fn breakpoints_watchpoints_and_steps_stop_the_target() {
+    let replies = replies(
+        program(),
+        &[b"Z2,2000,1", b"Z0,7,1", b"c", b"p5", b"m2000,1", b"z2,2000,1", b"c", b"p5", b"p0", b"z0,7,1", b"s", b"p5", b"s", b"p5"],
+    );
+    assert_eq!(replies[..5], ["OK", "OK", "T05watch:2000;", "0600", "42"]);
+    // INR A runs, then the breakpoint on the HLT stops it
+    assert_eq!(replies[5..8], ["OK", "S05", "0700"]);
+    // AF is sent flags first, so A is the second byte
+    assert!(replies[8].ends_with("43"), "{}", replies[8]);
+    // Single steps: the HLT, then nothing more to run
+    assert_eq!(replies[9..], ["OK", "S05", "0800", "S05", "0800"]);
+}
@@ -0,0 +1,66 @@
+use intel8080_core::hex::{write_hex, HexError, HexImage};
+use intel8080_core::CPU;
+
+#[test]
+fn data_past_the_linear_address_space_is_out_of_range() {
+    // Extended linear address 0xFFFF, then 16 bytes at offset 0xFFF8
+    let text = ":02000004FFFFFC\n:10FFF8000102030405060708090A0B0C0D0E0F1071\n:00000001FF\n";
+    assert_eq!(HexImage::parse(text), Err(HexError::OutOfRange { line: 2, address: u32::MAX }));
+}
+
+#[test]
+fn bad_checksums_are_rejected() {
+    // 3E 05 at 0100 with the checksum off by one
+    let text = ":020100003E05BB\n:00000001FF\n";
+    assert_eq!(HexImage::parse(text), Err(HexError::Checksum { line: 1, expected: 0xBA, actual: 0xBB }));
+    assert_eq!(HexImage::parse(":020100003E05BA\n:00000001FF\n").unwrap().segments, [(0x0100, vec![0x3E, 0x05])]);
+}
+
+#[test]
+fn overlapping_records_are_rejected() {
+    let text = ":020100003E05BA\n:01010100AA53\n:00000001FF\n";
+    assert_eq!(HexImage::parse(text), Err(HexError::Overlap { line: 2, address: 0x0101 }));
+}
+
+#[test]
+fn load_hex_sets_pc_from_the_start_record() {
+    let mut cpu = CPU::new();
+    let text = write_hex(&[0x3E, 0x05, 0x76], 0x0200, Some(0x0201));
+    assert_eq!(cpu.load_hex(&text), Ok(Some(0x0201)));
+    assert_eq!(cpu.pc, 0x0201);
+    assert_eq!(cpu.ram[0x0200..0x0203], [0x3E, 0x05, 0x76]);
+}
+
+#[test]
+fn rejected_images_leave_memory_alone() {
+    let mut cpu = CPU::new();
+    cpu.pc = 0x1234;
+    // Good data and start records, then a bad one
+    let mut text = write_hex(&[0xAA; 32], 0x0300, Some(0x0300));
+    text.insert_str(text.rfind(':').unwrap(), ":00000002FF\n");
+    assert!(matches!(cpu.load_hex(&text), Err(HexError::Checksum { line: 4, .. })));
+    assert!(cpu.ram.iter().all(|byte| *byte == 0));
+    assert_eq!(cpu.pc, 0x1234);
+
+    assert_eq!(cpu.load_hex(":020100003E05BA\n"), Err(HexError::MissingEndOfFile));
+    assert!(cpu.ram.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn dumps_parse_back_to_the_same_bytes() {
+    let mut cpu = CPU::new();
+    for (index, byte) in cpu.ram[0xFFE0..].iter_mut().enumerate() {
+        *byte = index as u8 * 7;
+    }
+    // Runs into the end of memory, so only 0x20 bytes come out
+    let text = cpu.dump_hex(0xFFE0, 0x100, Some(0xFFE0));
+    assert_eq!(text.lines().count(), 4);
+    let image = HexImage::parse(&text).unwrap();
+    assert_eq!(image.segments, [(0xFFE0, cpu.ram[0xFFE0..].to_vec())]);
+    assert_eq!(image.start, Some(0xFFE0));
+
+    let data:Vec<u8> = (0..=40).collect();
+    let image = HexImage::parse(&write_hex(&data, 0x1000, None)).unwrap();
+    assert_eq!(image.segments, [(0x1000, data)]);
+    assert_eq!(image.start, None);
+}
@@ -0,0 +1,118 @@
+use intel8080_core::devices::i8253::Pit8253;
+use intel8080_core::io::IoDevice;
+
+// Counter 0 programmed with `control` and a 16-bit count, then given the
+// CLK pulse that loads the count
+fn programmed(control:u8, count:u16) -> Pit8253 {
+    let mut pit = Pit8253::new(1_000_000, 1_000_000);
+    pit.output(3, control, 0);
+    pit.output(0, count as u8, 0);
+    pit.output(0, (count >> 8) as u8, 0);
+    pit.clock(0, 1);
+    pit
+}
+
+fn count(pit:&mut Pit8253) -> u16 {
+    let lsb = pit.input(0, 0);
+    u16::from_le_bytes([lsb, pit.input(0, 0)])
+}
+
+#[test]
+fn mode_0_goes_high_at_terminal_count() {
+    let mut pit = programmed(0x30, 5);
+    assert!(!pit.out(0, 0));
+    pit.clock(0, 2);
+    assert_eq!(count(&mut pit), 3);
+    pit.clock(0, 2);
+    assert!(!pit.out(0, 0));
+    pit.clock(0, 1);
+    assert!(pit.out(0, 0));
+    // And stays there while the counter wraps
+    pit.clock(0, 10);
+    assert!(pit.out(0, 0));
+    assert_eq!(pit.take_rising_edges(0, 0), 1);
+}
+
+#[test]
+fn mode_2_pulses_low_once_per_period() {
+    let mut pit = programmed(0x34, 4);
+    assert!(pit.out(0, 0));
+    pit.clock(0, 2);
+    assert!(pit.out(0, 0));
+    pit.clock(0, 1);
+    assert!(!pit.out(0, 0));
+    pit.clock(0, 1);
+    assert!(pit.out(0, 0));
+    assert_eq!(count(&mut pit), 4);
+
+    pit.take_rising_edges(0, 0);
+    pit.clock(0, 40);
+    assert_eq!(pit.take_rising_edges(0, 0), 10);
+    // GATE low holds OUT high and stops counting
+    pit.set_gate(0, false, 0);
+    pit.clock(0, 40);
+    assert!(pit.out(0, 0));
+    assert_eq!(pit.take_rising_edges(0, 0), 0);
+}
+
+#[test]
+fn mode_3_is_a_square_wave() {
+    // Odd counts are high one pulse longer than they are low
+    let mut pit = programmed(0x36, 5);
+    let mut levels = Vec::new();
+    for _ in 0..10 {
+        levels.push(pit.out(0, 0));
+        pit.clock(0, 1);
+    }
+    assert_eq!(levels, [true, true, true, false, false, true, true, true, false, false]);
+
+    let mut pit = programmed(0x36, 6);
+    pit.take_rising_edges(0, 0);
+    pit.clock(0, 60);
+    assert_eq!(pit.take_rising_edges(0, 0), 10);
+}
+
+#[test]
+fn latched_counts_hold_until_read() {
+    let mut pit = programmed(0x30, 1000);
+    pit.clock(0, 10);
+    pit.output(3, 0x00, 0);
+    pit.clock(0, 100);
+    assert_eq!(count(&mut pit), 990);
+    assert_eq!(count(&mut pit), 890);
+
+    // A second latch command before the read is ignored
+    pit.output(3, 0x00, 0);
+    pit.clock(0, 10);
+    pit.output(3, 0x00, 0);
+    assert_eq!(count(&mut pit), 890);
+}
+
+#[test]
+fn bcd_counters_count_in_decimal() {
+    let mut pit = programmed(0x31, 0x1000);
+    pit.clock(0, 1);
+    assert_eq!(count(&mut pit), 0x0999);
+    pit.clock(0, 990);
+    assert_eq!(count(&mut pit), 0x0009);
+
+    // Zero is 10000
+    let mut pit = programmed(0x31, 0x0000);
+    pit.clock(0, 1);
+    assert_eq!(count(&mut pit), 0x9999);
+    pit.clock(0, 9998);
+    assert!(!pit.out(0, 0));
+    pit.clock(0, 1);
+    assert!(pit.out(0, 0));
+}
+
+#[test]
+fn cpu_cycles_drive_the_clock() {
+    // 2 MHz CPU, 1 MHz counter clock
+    let mut pit = Pit8253::new(2_000_000, 1_000_000);
+    pit.output(3, 0x30, 0);
+    pit.output(0, 100, 0);
+    pit.output(0, 0, 0);
+    assert!(!pit.out(0, 2 + 2 * 99));
+    assert!(pit.out(0, 2 + 2 * 100));
+}
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use intel8080_core::devices::i8255::{Ppi8255, PpiPort};
+use intel8080_core::io::IoDevice;
+
+// A PPI whose outputs are recorded
+fn recorded() -> (Ppi8255, Rc<RefCell<Vec<(PpiPort, u8)>>>) {
+    let outputs = Rc::new(RefCell::new(Vec::new()));
+    let mut ppi = Ppi8255::new();
+    let record = outputs.clone();
+    ppi.set_output_callback(Box::new(move |port, value| record.borrow_mut().push((port, value))));
+    (ppi, outputs)
+}
+
+#[test]
+fn mode_0_ports_read_pins_and_write_latches() {
+    let (mut ppi, outputs) = recorded();
+    // A in, B out, C out
+    ppi.output(3, 0x90, 0);
+    outputs.borrow_mut().clear();
+    ppi.set_pins(PpiPort::A, 0x5A);
+    assert_eq!(ppi.input(0, 0), 0x5A);
+
+    ppi.output(1, 0x33, 0);
+    ppi.output(2, 0xA5, 0);
+    assert_eq!(*outputs.borrow(), [(PpiPort::B, 0x33), (PpiPort::C, 0xA5)]);
+    assert_eq!(ppi.output_latch(PpiPort::B), 0x33);
+    assert_eq!(ppi.input(1, 0), 0x33);
+    assert_eq!(ppi.port_c_pins(), 0xA5);
+
+    // Upper C in, lower C out
+    ppi.output(3, 0x98, 0);
+    ppi.set_input_callback(Box::new(|port| if port == PpiPort::C { 0x70 } else { 0 }));
+    ppi.output(2, 0xFF, 0);
+    assert_eq!(ppi.input(2, 0), 0x7F);
+}
+
+#[test]
+fn port_c_bits_set_and_reset() {
+    let mut ppi = Ppi8255::new();
+    ppi.output(3, 0x80, 0);
+    ppi.output(3, 0x0F, 0);
+    ppi.output(3, 0x05, 0);
+    assert_eq!(ppi.port_c_pins(), 0x84);
+    ppi.output(3, 0x0E, 0);
+    assert_eq!(ppi.input(2, 0), 0x04);
+}
+
+#[test]
+fn mode_1_input_strobes_set_ibf_and_intr_with_inte() {
+    let mut ppi = Ppi8255::new();
+    // Port A mode 1 input
+    ppi.output(3, 0xB0, 0);
+    ppi.strobe_a(0x42);
+    assert!(ppi.ibf_a());
+    assert!(!ppi.intr_a());
+    assert_eq!(ppi.input(2, 0) & 0x38, 0x20);
+
+    // INTE A is PC4
+    ppi.output(3, 0x09, 0);
+    assert!(ppi.intr_a());
+    assert_eq!(ppi.input(2, 0) & 0x38, 0x38);
+
+    // Reading the data clears IBF and INTR
+    assert_eq!(ppi.input(0, 0), 0x42);
+    assert!(!ppi.ibf_a() && !ppi.intr_a());
+    assert_eq!(ppi.input(2, 0) & 0x38, 0x10);
+}
+
+#[test]
+fn mode_1_output_sets_obf_until_acknowledged() {
+    let (mut ppi, outputs) = recorded();
+    // Port B mode 1 output
+    ppi.output(3, 0x84, 0);
+    // INTE B is PC2
+    ppi.output(3, 0x05, 0);
+    assert!(ppi.intr_b());
+
+    ppi.output(1, 0x99, 0);
+    assert!(ppi.obf_b());
+    assert!(!ppi.intr_b());
+    // OBF is active low on PC1
+    assert_eq!(ppi.port_c_pins() & 0x03, 0x00);
+    assert!(outputs.borrow().contains(&(PpiPort::B, 0x99)));
+
+    assert_eq!(ppi.ack_b(), 0x99);
+    assert!(!ppi.obf_b());
+    assert!(ppi.intr_b());
+    assert_eq!(ppi.port_c_pins() & 0x03, 0x03);
+}
@@ -0,0 +1,96 @@
+use intel8080_core::devices::i8259::Pic8259;
+use intel8080_core::interrupt::InterruptSource;
+use intel8080_core::io::IoDevice;
+
+// Single 8259 in 8080 mode, vectors from 0x2000
+fn initialized(icw1:u8, icw4:Option<u8>) -> Pic8259 {
+    let mut pic = Pic8259::new();
+    pic.output(0, icw1, 0);
+    pic.output(1, 0x20, 0);
+    if let Some(icw4) = icw4 {
+        pic.output(1, icw4, 0);
+    }
+    pic
+}
+
+fn isr(pic:&mut Pic8259) -> u8 {
+    // OCW3: read ISR
+    pic.output(0, 0x0B, 0);
+    pic.input(0, 0)
+}
+
+#[test]
+fn initialization_takes_the_words_icw1_asks_for() {
+    let mut pic = Pic8259::new();
+    // Cascaded, with ICW4
+    pic.output(0, 0x11, 0);
+    pic.output(1, 0x40, 0);
+    pic.output(1, 0x04, 0);
+    pic.set_irq(0, true);
+    assert!(!pic.pending(0), "still waiting for ICW4");
+    pic.output(1, 0x02, 0);
+    assert_eq!((pic.icw1, pic.icw2, pic.icw3, pic.icw4), (0x11, 0x40, 0x04, 0x02));
+    assert!(pic.pending(0));
+
+    // With the sequence done, A0 = 1 writes go to the mask
+    pic.output(1, 0x5A, 0);
+    assert_eq!(pic.input(1, 0), 0x5A);
+    assert_eq!(pic.icw4, 0x02);
+}
+
+#[test]
+fn acknowledges_call_vectors_four_or_eight_bytes_apart() {
+    let mut pic = initialized(0x16, None);
+    pic.set_irq(3, true);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x0C, 0x20]);
+
+    let mut pic = initialized(0x12, None);
+    pic.set_irq(3, true);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x18, 0x20]);
+
+    // ICW1 A7-A5 fill in the low byte at interval 4
+    let mut pic = initialized(0xF6, None);
+    pic.set_irq(7, true);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0xFC, 0x20]);
+}
+
+#[test]
+fn requests_are_served_by_priority_until_eoi() {
+    let mut pic = initialized(0x16, None);
+    pic.set_irq(5, true);
+    pic.set_irq(2, true);
+    assert_eq!(pic.input(0, 0), 0x24);
+
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x08, 0x20]);
+    assert_eq!(isr(&mut pic), 0x04);
+    // IR5 waits behind IR2 in service, a higher priority IR1 doesn't
+    assert!(!pic.pending(0));
+    pic.pulse_irq(1);
+    assert!(pic.pending(0));
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x04, 0x20]);
+    assert_eq!(isr(&mut pic), 0x06);
+
+    // Non-specific EOI ends the highest level in service
+    pic.output(0, 0x20, 0);
+    assert_eq!(isr(&mut pic), 0x04);
+    // Specific EOI for IR2
+    pic.output(0, 0x62, 0);
+    assert_eq!(isr(&mut pic), 0x00);
+
+    // Masked requests stay in IRR
+    pic.output(1, 0x20, 0);
+    assert!(!pic.pending(0));
+    pic.output(1, 0x00, 0);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x14, 0x20]);
+}
+
+#[test]
+fn auto_eoi_leaves_nothing_in_service() {
+    let mut pic = initialized(0x17, Some(0x02));
+    pic.set_irq(3, true);
+    pic.set_irq(6, true);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x0C, 0x20]);
+    assert_eq!(isr(&mut pic), 0x00);
+    assert_eq!(pic.acknowledge(0), [0xCD, 0x18, 0x20]);
+    assert_eq!(isr(&mut pic), 0x00);
+}
@@ -0,0 +1,29 @@
+use intel8080_core::devices::i8275::Crt8275;
+use intel8080_core::dma::DmaPeripheral;
+use intel8080_core::io::IoDevice;
+
+// Row buffer DMA requests come in bursts with gaps between them
+#[test]
+fn row_dma_is_paced_by_bursts() {
+    // One character clock per CPU cycle
+    let mut crt = Crt8275::new(1_000_000, 1_000_000);
+    // Reset: 80 characters, 25 rows, 10 lines, then preset counters
+    crt.output(1, 0x00, 0);
+    for parameter in [0x4F, 0x18, 0x99, 0x00] {
+        crt.output(0, parameter, 0);
+    }
+    crt.output(1, 0xE0, 0);
+    // Start display, 7 clock burst space, 2 byte bursts
+    crt.output(1, 0x25, 0);
+    assert_eq!((crt.burst_space, crt.burst_count), (7, 2));
+
+    for cycles in [0, 7, 14] {
+        assert!(crt.dreq(cycles), "at {}", cycles);
+        crt.dma_write(b'A', cycles);
+        assert!(crt.dreq(cycles));
+        crt.dma_write(b'A', cycles);
+        assert!(!crt.dreq(cycles));
+        assert!(!crt.dreq(cycles + 6));
+    }
+    assert!(crt.dreq(21));
+}
@@ -0,0 +1,115 @@
+use intel8080_core::devices::i8279::{Kdc8279, KeyboardMode, STATUS_FULL, STATUS_OVERRUN, STATUS_SENSOR, STATUS_UNDERRUN};
+use intel8080_core::io::IoDevice;
+
+fn fill_display(kdc:&mut Kdc8279, value:u8) {
+    // Write display RAM from 0, auto-increment
+    kdc.output(1, 0x90, 0);
+    for _ in 0..8 {
+        kdc.output(0, value, 0);
+    }
+}
+
+#[test]
+fn clear_takes_the_blank_code_from_cd1_cd0() {
+    let mut kdc = Kdc8279::new();
+    // Clear all with CD2 off: still clears, with all ones
+    fill_display(&mut kdc, 0x12);
+    kdc.output(1, 0xCD, 0);
+    assert_eq!(kdc.display_ram(), [0xFF; 8]);
+
+    // CD2 with CD1:CD0 = 10 blanks to 0x20
+    fill_display(&mut kdc, 0x12);
+    kdc.output(1, 0xD8, 0);
+    assert_eq!(kdc.display_ram(), [0x20; 8]);
+
+    // Neither CD2 nor CA leaves the display alone
+    fill_display(&mut kdc, 0x12);
+    kdc.output(1, 0xCC, 0);
+    assert_eq!(kdc.display_ram(), [0x12; 8]);
+}
+
+#[test]
+fn display_ram_reads_back() {
+    let mut kdc = Kdc8279::new();
+    kdc.output(1, 0x90, 0);
+    for value in 1..=8 {
+        kdc.output(0, value, 0);
+    }
+    // Read display RAM from 2, auto-increment
+    kdc.output(1, 0x72, 0);
+    assert_eq!([kdc.input(0, 0), kdc.input(0, 0)], [3, 4]);
+}
+
+#[test]
+fn fifo_queues_keys_in_order() {
+    let mut kdc = Kdc8279::new();
+    kdc.shift = true;
+    kdc.key_press(1, 2);
+    kdc.shift = false;
+    kdc.key_press(3, 4);
+    assert!(kdc.irq());
+    assert_eq!(kdc.input(1, 0), 2);
+    assert_eq!(kdc.input(0, 0), 0x40 | 1 << 3 | 2);
+    assert_eq!(kdc.input(0, 0), 3 << 3 | 4);
+    assert!(!kdc.irq());
+
+    kdc.input(0, 0);
+    assert_eq!(kdc.input(1, 0) & STATUS_UNDERRUN, STATUS_UNDERRUN);
+
+    for column in 0..8 {
+        kdc.key_press(0, column);
+    }
+    assert_eq!(kdc.input(1, 0) & STATUS_FULL, STATUS_FULL);
+    kdc.key_press(1, 0);
+    assert_eq!(kdc.input(1, 0) & STATUS_OVERRUN, STATUS_OVERRUN);
+    assert_eq!(kdc.fifo_len(), 8);
+
+    // Clear FIFO status
+    kdc.output(1, 0xC2, 0);
+    assert_eq!((kdc.fifo_len(), kdc.input(1, 0)), (0, 0));
+}
+
+#[test]
+fn lockout_ignores_a_second_key_and_rollover_takes_it() {
+    let mut kdc = Kdc8279::new();
+    kdc.key_down(0, 1);
+    kdc.key_down(0, 2);
+    assert_eq!(kdc.fifo_len(), 1);
+
+    let mut kdc = Kdc8279::new();
+    // Encoded scan, 2 key rollover
+    kdc.output(1, 0x02, 0);
+    assert_eq!(kdc.keyboard_mode, KeyboardMode::EncodedRollover);
+    kdc.key_down(0, 1);
+    kdc.key_down(0, 2);
+    assert_eq!(kdc.fifo_len(), 2);
+}
+
+#[test]
+fn sensor_matrix_mode_reports_closures() {
+    let mut kdc = Kdc8279::new();
+    kdc.output(1, 0x04, 0);
+    kdc.key_down(2, 5);
+    assert!(kdc.irq());
+    assert_eq!(kdc.input(1, 0) & STATUS_SENSOR, STATUS_SENSOR);
+    // Read sensor RAM row 2
+    kdc.output(1, 0x42, 0);
+    assert_eq!(kdc.input(0, 0), 1 << 5);
+    // End interrupt
+    kdc.output(1, 0xE0, 0);
+    assert!(!kdc.irq());
+    assert_eq!(kdc.input(1, 0), 0);
+}
+
+#[test]
+fn right_entry_shifts_characters_in() {
+    let mut kdc = Kdc8279::new();
+    // 16 characters, right entry
+    kdc.output(1, 0x18, 0);
+    assert_eq!(kdc.display_chars, 16);
+    kdc.output(1, 0x90, 0);
+    kdc.output(0, 1, 0);
+    kdc.output(0, 2, 0);
+    assert_eq!(&kdc.display_ram()[14..], [1, 2]);
+    assert!(kdc.display_ram()[..14].iter().all(|&value| value == 0));
+}
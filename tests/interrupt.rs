@@ -0,0 +1,33 @@
+use intel8080_core::CPU;
+
+fn waiting_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.pc = 0x0100;
+    cpu.sp = 0x3000;
+    cpu.int_enabled = true;
+    // MVI A,55 in memory, so operands taken from PC would show
+    cpu.load_from(&[0x3E, 0x55], 0x0100);
+    cpu
+}
+
+#[test]
+fn multi_byte_instructions_other_than_call_are_refused() {
+    let mut cpu = waiting_cpu();
+    assert!(!cpu.interrupt(&[0x3E, 0x42]));
+    assert_eq!((cpu.a, cpu.pc), (0x00, 0x0100));
+    assert!(cpu.int_enabled);
+}
+
+#[test]
+fn call_and_one_byte_instructions_are_accepted() {
+    let mut cpu = waiting_cpu();
+    assert!(cpu.interrupt(&[0xCD, 0x00, 0x20]));
+    assert_eq!((cpu.pc, cpu.sp), (0x2000, 0x2FFE));
+    assert_eq!(&cpu.ram[0x2FFE..0x3000], &[0x00, 0x01]);
+
+    let mut cpu = waiting_cpu();
+    // INR A
+    assert!(cpu.interrupt(&[0x3C]));
+    assert_eq!((cpu.a, cpu.pc), (0x01, 0x0100));
+    assert!(!cpu.int_enabled);
+}
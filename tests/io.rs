@@ -0,0 +1,50 @@
+use intel8080_core::devices::i8251::{Usart8251, STATUS_OE, STATUS_RXRDY};
+use intel8080_core::io::IoDevice;
+use intel8080_core::CPU;
+
+#[test]
+fn unmapped_ports_read_ff() {
+    let mut cpu = CPU::new();
+    // MVI A,0; IN 42; HLT
+    cpu.load_from(&[0x3E, 0x00, 0xDB, 0x42, 0x76], 0x0000);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.a, 0xFF);
+}
+
+// 8 data bits, 1 stop bit, 16x clock, receiver enabled
+fn receiving_usart(cpu_hz:u32, clock_hz:u32) -> Usart8251 {
+    let mut usart = Usart8251::new(cpu_hz, clock_hz);
+    usart.output(1, 0x4E, 0);
+    usart.output(1, 0x04, 0);
+    usart
+}
+
+#[test]
+fn unread_characters_are_overrun() {
+    let mut usart = receiving_usart(2_000_000, 16 * 9600);
+    let frame = usart.char_cycles();
+    usart.send_bytes(b"abc");
+
+    assert_eq!(usart.input(1, frame) & (STATUS_RXRDY | STATUS_OE), STATUS_RXRDY);
+    assert_eq!(usart.input(0, frame), b'a');
+
+    // b and c both land before the program reads again
+    let status = usart.input(1, 4 * frame);
+    assert_eq!(status & (STATUS_RXRDY | STATUS_OE), STATUS_RXRDY | STATUS_OE);
+    assert_eq!(usart.input(0, 4 * frame), b'c');
+
+    // Error reset clears the flag
+    usart.output(1, 0x14, 4 * frame);
+    assert_eq!(usart.input(1, 4 * frame) & STATUS_OE, 0);
+}
+
+#[test]
+fn instant_usart_waits_for_reads() {
+    let mut usart = receiving_usart(0, 0);
+    usart.send_bytes(b"ab");
+    assert_eq!(usart.input(1, 100) & (STATUS_RXRDY | STATUS_OE), STATUS_RXRDY);
+    assert_eq!(usart.input(0, 100), b'a');
+    assert_eq!(usart.input(0, 200), b'b');
+    assert_eq!(usart.input(1, 300) & STATUS_OE, 0);
+}
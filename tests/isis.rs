@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use intel8080_core::isis::{Isis, ERROR_BAD_AFTN, ERROR_BAD_DEVICE, ERROR_BAD_FILENAME, ERROR_NO_FILENAME, ERROR_NO_SUCH_FILE};
+use intel8080_core::CPU;
+
+const BLOCK:u16 = 0x1000;
+const STATUS:u16 = 0x1100;
+const RESULT:u16 = 0x1102;
+const PATH:u16 = 0x1200;
+const BUFFER:u16 = 0x1300;
+
+struct System {
+    isis:Rc<RefCell<Isis>>,
+    cpu:CPU,
+    root:PathBuf,
+}
+
+impl System {
+    // :F1: is mapped to a fresh directory, :F0: to an empty one
+    fn new(name:&str) -> Self {
+        let root = std::env::temp_dir().join(format!("isis-{}-{}", name, std::process::id()));
+        fs::create_dir_all(root.join("f0")).unwrap();
+        fs::create_dir_all(root.join("f1")).unwrap();
+        let isis = Rc::new(RefCell::new(Isis::new()));
+        isis.borrow_mut().map_drive(0, root.join("f0"));
+        isis.borrow_mut().map_drive(1, root.join("f1"));
+        let mut cpu = CPU::new();
+        Isis::install(&isis, &mut cpu);
+        System { isis, cpu, root }
+    }
+
+    // Runs system call `function` with `parameters` and returns the status
+    fn call(&mut self, function:u8, parameters:&[u16]) -> u16 {
+        let cpu = &mut self.cpu;
+        for (index, parameter) in parameters.iter().enumerate() {
+            cpu.ram[BLOCK as usize + index * 2..][..2].copy_from_slice(&parameter.to_le_bytes());
+        }
+        cpu.ram[STATUS as usize..][..2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        // CALL 0040; HLT
+        cpu.load_from(&[0xCD, 0x40, 0x00, 0x76], 0x0100);
+        (cpu.pc, cpu.sp, cpu.halted) = (0x0100, 0x2000, false);
+        (cpu.c, cpu.d, cpu.e) = (function, (BLOCK >> 8) as u8, BLOCK as u8);
+        for _ in 0..4 {
+            cpu.tick();
+        }
+        assert!(cpu.halted, "function {}", function);
+        self.word(STATUS)
+    }
+
+    fn word(&self, address:u16) -> u16 {
+        u16::from_le_bytes([self.cpu.ram[address as usize], self.cpu.ram[address as usize + 1]])
+    }
+
+    // OPEN with the path in memory; returns the status and AFTN
+    fn open(&mut self, path:&str, access:u16) -> (u16, u16) {
+        self.cpu.ram[PATH as usize..][..path.len() + 1].copy_from_slice(format!("{} ", path).as_bytes());
+        let status = self.call(0, &[RESULT, PATH, access, 0, STATUS]);
+        (status, self.word(RESULT))
+    }
+}
+
+impl Drop for System {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn paths_name_drives_and_8_3_files() {
+    let mut system = System::new("paths");
+    fs::write(system.root.join("f1").join("prog.plm"), b"").unwrap();
+
+    assert_eq!(system.open(":F1:PROG.PLM", 1).0, 0);
+    // Case doesn't matter on either side
+    assert_eq!(system.open(":f1:Prog.Plm", 1).0, 0);
+    // No prefix is :F0:
+    assert_eq!(system.open("PROG.PLM", 1).0, ERROR_NO_SUCH_FILE);
+    assert_eq!(system.open(":F2:PROG.PLM", 1).0, ERROR_BAD_DEVICE);
+    assert_eq!(system.open(":FX:PROG.PLM", 1).0, ERROR_BAD_DEVICE);
+    assert_eq!(system.open(":XY:PROG.PLM", 1).0, ERROR_BAD_DEVICE);
+    assert_eq!(system.open(":F1PROG.PLM", 1).0, ERROR_BAD_FILENAME);
+    assert_eq!(system.open(":F1:PROGRAM.PLM", 1).0, ERROR_BAD_FILENAME);
+    assert_eq!(system.open(":F1:PROG.PLMX", 1).0, ERROR_BAD_FILENAME);
+    assert_eq!(system.open(":F1:A.B.C", 1).0, ERROR_BAD_FILENAME);
+    assert_eq!(system.open(":F1:.PLM", 1).0, ERROR_NO_FILENAME);
+    assert_eq!(system.open("", 1).0, ERROR_NO_FILENAME);
+    // Parsing stops at anything that can't be in a name
+    assert_eq!(system.open(":F1:../PROG", 2).0, ERROR_NO_FILENAME);
+}
+
+#[test]
+fn files_open_read_write_and_close() {
+    let mut system = System::new("files");
+    let (status, aft) = system.open(":F1:OUT.DAT", 2);
+    assert_eq!(status, 0);
+    assert!(aft >= 2);
+    system.cpu.load_from(b"HELLO", BUFFER as usize);
+    assert_eq!(system.call(4, &[aft, BUFFER, 5, STATUS]), 0);
+    assert_eq!(system.call(1, &[aft, STATUS]), 0);
+    assert_eq!(system.call(1, &[aft, STATUS]), ERROR_BAD_AFTN);
+    assert_eq!(fs::read(system.root.join("f1").join("OUT.DAT")).unwrap(), b"HELLO");
+
+    let (status, aft) = system.open(":F1:OUT.DAT", 1);
+    assert_eq!(status, 0);
+    system.cpu.ram[BUFFER as usize..][..8].fill(0);
+    assert_eq!(system.call(3, &[aft, BUFFER, 8, RESULT, STATUS]), 0);
+    assert_eq!(system.word(RESULT), 5);
+    assert_eq!(system.cpu.ram[BUFFER as usize..][..5], *b"HELLO");
+    // Nothing left, and a read only file can't be written
+    assert_eq!(system.call(3, &[aft, BUFFER, 8, RESULT, STATUS]), 0);
+    assert_eq!(system.word(RESULT), 0);
+    assert_ne!(system.call(4, &[aft, BUFFER, 5, STATUS]), 0);
+    assert_eq!(system.call(1, &[aft, STATUS]), 0);
+
+    // Devices open like files
+    let (status, aft) = system.open(":CO:", 2);
+    assert_eq!(status, 0);
+    assert_eq!(system.call(4, &[aft, BUFFER, 5, STATUS]), 0);
+    assert_eq!(system.isis.borrow_mut().take_output(), b"HELLO");
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+
+use intel8080_core::loader::{Format, LoadError, LoadMap, Loader, MapEntry, COM_ADDRESS, COM_BDOS};
+use intel8080_core::CPU;
+
+const SRECORDS:&str = "S00600004844521B\nS10601003E05763F\nS9030100FB\n";
+
+#[test]
+fn formats_come_from_the_extension_then_whole_file_content() {
+    let detect = |name:&str, data:&[u8]| Format::detect(Path::new(name), data);
+    // LDA 2000H; MOV D,E; LXI SP: code that starts like a record
+    assert_eq!(detect("invaders.h", &[0x3A, 0x00, 0x20, 0x53, 0x31]), Format::Binary);
+    assert_eq!(detect("rom.bin", b":00000001FF\n"), Format::Binary);
+    assert_eq!(detect("boot.rom", SRECORDS.as_bytes()), Format::Binary);
+    assert_eq!(detect("code", b"S1 not really\n"), Format::Binary);
+    assert_eq!(detect("code", b":0000\x80"), Format::Binary);
+    assert_eq!(detect("code", b""), Format::Binary);
+
+    assert_eq!(detect("code", b"\r\n:00000001FF\r\n"), Format::IntelHex);
+    assert_eq!(detect("code", SRECORDS.as_bytes()), Format::SRecord);
+    assert_eq!(detect("CODE.HEX", b""), Format::IntelHex);
+    assert_eq!(detect("prog.mot", b""), Format::SRecord);
+    assert_eq!(detect("prog.Com", b""), Format::Com);
+}
+
+#[test]
+fn srecords_load_data_and_start() {
+    let mut loader = Loader::new();
+    loader.add_image(Format::SRecord, SRECORDS.as_bytes(), 0).unwrap();
+    let mut cpu = CPU::new();
+    assert_eq!(loader.load_into(&mut cpu), Some(0x0100));
+    assert_eq!(cpu.pc, 0x0100);
+    assert_eq!(cpu.ram[0x0100..0x0103], [0x3E, 0x05, 0x76]);
+}
+
+#[test]
+fn bad_srecords_are_rejected_with_their_line() {
+    let mut loader = Loader::new();
+    let bad_checksum = "S10601003E05763F\nS10601033E057600\n";
+    assert!(matches!(loader.add_image(Format::SRecord, bad_checksum.as_bytes(), 0), Err(LoadError::SRecordChecksum { line: 2 })));
+
+    for text in ["S1060100", "S1050100ZZ05763F", "S10701003E05763F", "X10601003E05763F", "S40601003E05763F"] {
+        let result = Loader::new().add_image(Format::SRecord, text.as_bytes(), 0);
+        assert!(matches!(result, Err(LoadError::SRecordSyntax { line: 1 })), "{}", text);
+    }
+}
+
+#[test]
+fn com_files_get_a_zero_page() {
+    let mut loader = Loader::new();
+    loader.add_image(Format::Com, &[0xC9], 0x4000).unwrap();
+    let mut cpu = CPU::new();
+    assert_eq!(loader.load_into(&mut cpu), Some(COM_ADDRESS));
+    assert_eq!(cpu.sp, COM_BDOS - 2);
+    assert_eq!(cpu.ram[0x0000], 0x76);
+    assert_eq!(cpu.ram[0x0005..0x0008], [0xC3, COM_BDOS as u8, (COM_BDOS >> 8) as u8]);
+    assert_eq!(cpu.ram[COM_ADDRESS as usize], 0xC9);
+    assert_eq!(cpu.ram[COM_BDOS as usize - 2..=COM_BDOS as usize], [0x00, 0x00, 0xC9]);
+
+    let too_big = vec![0; (COM_BDOS - COM_ADDRESS) as usize];
+    assert!(matches!(Loader::new().add_image(Format::Com, &too_big, 0), Err(LoadError::OutOfRange { .. })));
+}
+
+#[test]
+fn overlapping_and_oversized_images_are_rejected() {
+    let mut loader = Loader::new();
+    loader.add(0x1000, &[1; 0x10]).unwrap();
+    assert!(matches!(loader.add(0x0FF8, &[2; 0x10]), Err(LoadError::Overlap { address: 0x1000 })));
+    assert!(matches!(loader.add(0xFFF0, &[3; 0x11]), Err(LoadError::OutOfRange { address: 0xFFF0, length: 0x11 })));
+    loader.add(0xFFF0, &[3; 0x10]).unwrap();
+
+    // Nothing from the rejected images was taken
+    let mut cpu = CPU::new();
+    loader.load_into(&mut cpu);
+    assert_eq!(cpu.ram[0x0FF8..0x1000], [0; 8]);
+    assert_eq!(cpu.ram[0x1000..0x1010], [1; 0x10]);
+}
+
+#[test]
+fn load_maps_parse_entries_and_start() {
+    let map = LoadMap::parse("# Space Invaders\ninvaders.h 0000h\n\n  invaders.g 0x0800  # second\nstart 100\n").unwrap();
+    assert_eq!(
+        map.entries,
+        [MapEntry { path: "invaders.h".into(), address: 0x0000 }, MapEntry { path: "invaders.g".into(), address: 0x0800 }]
+    );
+    assert_eq!(map.start, Some(100));
+
+    assert!(matches!(LoadMap::parse("a 0\nb\n"), Err(LoadError::LoadMap { line: 2, .. })));
+    assert!(matches!(LoadMap::parse("a 10000h\n"), Err(LoadError::LoadMap { line: 1, .. })));
+}
+
+#[test]
+fn load_map_files_are_relative_to_the_map() {
+    let directory = std::env::temp_dir().join(format!("loader-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("invaders.h"), [0x3A, 0x00, 0x20]).unwrap();
+    fs::write(directory.join("invaders.g"), [0x76]).unwrap();
+    fs::write(directory.join("game.map"), "invaders.h 0\ninvaders.g 0800h\nstart 0\n").unwrap();
+
+    let mut loader = Loader::new();
+    let result = loader.add_map_file(directory.join("game.map"));
+    let mut cpu = CPU::new();
+    cpu.pc = 0x1234;
+    loader.load_into(&mut cpu);
+    fs::remove_dir_all(&directory).unwrap();
+
+    result.unwrap();
+    assert_eq!(cpu.pc, 0);
+    assert_eq!(cpu.ram[0..3], [0x3A, 0x00, 0x20]);
+    assert_eq!(cpu.ram[0x0800], 0x76);
+}
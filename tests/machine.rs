@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use intel8080_core::machine::{Machine, Profile, Stop};
+
+// MVI C,9; LXI D,msg; CALL 5; JMP 0; msg: "HI$"
+const HELLO:[u8; 14] = [0x0E, 0x09, 0x11, 0x0B, 0x01, 0xCD, 0x05, 0x00, 0xC3, 0x00, 0x00, b'H', b'I', b'$'];
+// MVI C,1; CALL 5; JMP 0
+const GETCHAR:[u8; 8] = [0x0E, 0x01, 0xCD, 0x05, 0x00, 0xC3, 0x00, 0x00];
+// JMP 0
+const FOREVER:[u8; 3] = [0xC3, 0x00, 0x00];
+
+fn image(directory:&Path, name:&str, data:&[u8]) -> PathBuf {
+    fs::create_dir_all(directory).unwrap();
+    let path = directory.join(name);
+    fs::write(&path, data).unwrap();
+    path
+}
+
+fn run(machine:&mut Machine, limit:u64) -> Option<Stop> {
+    while machine.instructions < limit {
+        if let Some(stop) = machine.stop_reason() {
+            return Some(stop);
+        }
+        machine.step();
+    }
+    None
+}
+
+fn run8080(arguments:&[&str], image:&Path) -> i32 {
+    let status = Command::new(env!("CARGO_BIN_EXE_run8080")).args(arguments).arg("-q").arg(image).output().unwrap().status;
+    status.code().unwrap()
+}
+
+#[test]
+fn machines_stop_for_halts_exits_and_input() {
+    let directory = std::env::temp_dir().join(format!("machine-{}", std::process::id()));
+
+    let mut machine = Machine::new(Profile::Bare);
+    machine.load(image(&directory, "halt.bin", &[0x00, 0x76]), 0x0000, "").unwrap();
+    assert_eq!(run(&mut machine, 100), Some(Stop::Halted));
+    assert_eq!((machine.instructions, machine.cpu.pc), (2, 0x0002));
+
+    let mut machine = Machine::new(Profile::Cpm);
+    assert_eq!(machine.load(image(&directory, "hello.com", &HELLO), 0x0000, "").unwrap(), Some(0x0100));
+    assert_eq!(run(&mut machine, 1000), Some(Stop::Exited));
+    assert_eq!(machine.take_output(), b"HI");
+
+    let mut machine = Machine::new(Profile::Cpm);
+    machine.load(image(&directory, "getchar.com", &GETCHAR), 0x0000, "").unwrap();
+    assert_eq!(run(&mut machine, 1000), Some(Stop::WaitingForInput));
+    machine.send_input(b"x");
+    assert_eq!(run(&mut machine, 1000), Some(Stop::Exited));
+    assert_eq!(machine.cpu.a, b'x');
+
+    let mut machine = Machine::new(Profile::Bare);
+    machine.load(image(&directory, "forever.bin", &FOREVER), 0x0000, "").unwrap();
+    assert_eq!(run(&mut machine, 1000), None);
+    assert_eq!(machine.cycles, 10 * 1000);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn run8080_exit_codes() {
+    let directory = std::env::temp_dir().join(format!("run8080-{}", std::process::id()));
+
+    assert_eq!(run8080(&[], &image(&directory, "halt.bin", &[0x76])), 0);
+    assert_eq!(run8080(&["-p", "cpm"], &image(&directory, "hello.com", &HELLO)), 0);
+    assert_eq!(run8080(&["-p", "cpm", "-i", "x"], &image(&directory, "getchar.com", &GETCHAR)), 0);
+    assert_eq!(run8080(&["-n", "1000"], &image(&directory, "forever.bin", &FOREVER)), 2);
+    assert_eq!(run8080(&["-p", "cpm"], &image(&directory, "getchar.com", &GETCHAR)), 4);
+    assert_eq!(run8080(&[], &directory.join("missing.bin")), 1);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,65 @@
+use intel8080_core::devices::mc6850::{Acia6850, STATUS_CTS, STATUS_IRQ, STATUS_RDRF, STATUS_TDRE};
+use intel8080_core::io::IoDevice;
+
+#[test]
+fn master_reset_holds_the_acia_until_a_divide_is_selected() {
+    let mut acia = Acia6850::new();
+    acia.send(b'a');
+    acia.output(1, b'b', 0);
+    assert_eq!(acia.input(0, 0), 0);
+    assert_eq!(acia.receive(), None);
+
+    // 8N1, divide by 16
+    acia.output(0, 0x15, 0);
+    assert_eq!(acia.input(0, 0), STATUS_RDRF | STATUS_TDRE);
+    assert_eq!(acia.input(1, 0), b'a');
+    assert_eq!(acia.input(0, 0), STATUS_TDRE);
+    acia.output(1, b'b', 0);
+    assert_eq!(acia.receive(), Some(b'b'));
+
+    // Resetting again drops a received character
+    acia.send(b'c');
+    acia.output(0, 0x03, 0);
+    assert_eq!(acia.input(0, 0), 0);
+    acia.output(0, 0x16, 0);
+    assert_eq!(acia.input(0, 0), STATUS_TDRE);
+}
+
+#[test]
+fn seven_bit_words_drop_the_top_bit() {
+    let mut acia = Acia6850::new();
+    // 7E1, divide by 1
+    acia.output(0, 0x08, 0);
+    acia.send(0xC1);
+    assert_eq!(acia.input(1, 0), 0x41);
+    acia.output(1, 0xFF, 0);
+    assert_eq!(acia.take_output(), [0x7F]);
+}
+
+#[test]
+fn interrupts_and_modem_lines() {
+    let mut acia = Acia6850::new();
+    // Receive interrupt enabled
+    acia.output(0, 0x95, 0);
+    assert!(!acia.irq());
+    acia.send(b'x');
+    assert!(acia.irq());
+    assert_eq!(acia.input(0, 0) & STATUS_IRQ, STATUS_IRQ);
+    acia.input(1, 0);
+    assert!(!acia.irq());
+
+    // Transmit interrupt while CTS is asserted
+    acia.output(0, 0x35, 0);
+    assert!(acia.irq() && acia.rts());
+    acia.cts = false;
+    assert!(!acia.irq());
+    assert_eq!(acia.input(0, 0), STATUS_CTS);
+    acia.output(1, b'y', 0);
+    assert_eq!(acia.receive(), None);
+
+    // RTS high, then break
+    acia.output(0, 0x55, 0);
+    assert!(!acia.rts());
+    acia.output(0, 0x75, 0);
+    assert!(acia.sending_break());
+}
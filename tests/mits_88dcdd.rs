@@ -0,0 +1,89 @@
+use intel8080_core::devices::mits_88dcdd::{Mits88Dcdd, STATUS_ENWD, STATUS_HEAD, STATUS_MOVE_HEAD, STATUS_NRDA, STATUS_TRACK0};
+use intel8080_core::disk::{DiskGeometry, DiskImage};
+use intel8080_core::io::IoDevice;
+
+fn pattern(seed:u8) -> Vec<u8> {
+    (0..137).map(|index| (index as u8).wrapping_mul(3).wrapping_add(seed)).collect()
+}
+
+// Drive 0 holds an Altair image with a pattern in track 2, sector 5
+fn controller() -> Mits88Dcdd {
+    let mut image = DiskImage::blank(DiskGeometry::ALTAIR_8);
+    assert_eq!(image.data().len(), 77 * 32 * 137);
+    image.write_sector(2, 0, 5, &pattern(1));
+    let mut dcdd = Mits88Dcdd::new();
+    dcdd.insert(0, image);
+    dcdd
+}
+
+// Polls the sector position until `sector` comes round
+fn wait_for_sector(dcdd:&mut Mits88Dcdd, sector:u8) {
+    for _ in 0..64 {
+        let position = dcdd.input(1, 0);
+        if position & 0x01 == 0 && (position >> 1) & 0x1F == sector {
+            return;
+        }
+    }
+    panic!("sector {} never came round", sector);
+}
+
+#[test]
+fn selecting_drives() {
+    let mut dcdd = controller();
+    assert_eq!(dcdd.input(0, 0), 0xFF);
+    // Empty drive
+    dcdd.output(0, 0x01, 0);
+    assert_eq!(dcdd.input(0, 0), 0xFF);
+    assert_eq!(dcdd.position(), None);
+
+    dcdd.output(0, 0x00, 0);
+    let status = dcdd.input(0, 0);
+    assert_eq!(status & (STATUS_MOVE_HEAD | STATUS_TRACK0), 0);
+    assert_eq!(status & (STATUS_HEAD | STATUS_NRDA), STATUS_HEAD | STATUS_NRDA);
+    assert_eq!(dcdd.position(), Some((0, 0, None)));
+
+    // Deselect
+    dcdd.output(0, 0x80, 0);
+    assert_eq!(dcdd.input(0, 0), 0xFF);
+}
+
+#[test]
+fn sector_position_needs_the_head_loaded() {
+    let mut dcdd = controller();
+    dcdd.output(0, 0x00, 0);
+    assert_eq!(dcdd.input(1, 0), 0xFF);
+    dcdd.output(1, 0x04, 0);
+    assert_eq!(dcdd.input(0, 0) & (STATUS_HEAD | STATUS_NRDA), 0);
+    // Sector true with each sector in turn, wrapping after 31
+    let sectors:Vec<u8> = (0..33).map(|_| dcdd.input(1, 0)).collect();
+    assert!(sectors.iter().all(|position| position & 0x01 == 0));
+    assert_eq!(sectors[..3], [0xC0, 0xC2, 0xC4]);
+    assert_eq!(sectors[31..], [0xFE, 0xC0]);
+}
+
+#[test]
+fn sectors_read_and_write() {
+    let mut dcdd = controller();
+    dcdd.output(0, 0x00, 0);
+    dcdd.output(1, 0x04, 0);
+    dcdd.output(1, 0x01, 0);
+    dcdd.output(1, 0x01, 0);
+    assert_eq!(dcdd.input(0, 0) & STATUS_TRACK0, STATUS_TRACK0);
+
+    wait_for_sector(&mut dcdd, 5);
+    let data:Vec<u8> = (0..137).map(|_| dcdd.input(2, 0)).collect();
+    assert_eq!(data, pattern(1));
+
+    wait_for_sector(&mut dcdd, 7);
+    dcdd.output(1, 0x80, 0);
+    assert_eq!(dcdd.input(0, 0) & STATUS_ENWD, 0);
+    for byte in pattern(9) {
+        dcdd.output(2, byte, 0);
+    }
+    assert_eq!(dcdd.input(0, 0) & STATUS_ENWD, STATUS_ENWD);
+    assert_eq!(dcdd.position(), Some((0, 2, Some(7))));
+
+    let image = dcdd.eject(0).unwrap();
+    assert_eq!(image.read_sector(2, 0, 7).unwrap(), pattern(9));
+    assert_eq!(image.read_sector(2, 0, 5).unwrap(), pattern(1));
+}
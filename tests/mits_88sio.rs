@@ -0,0 +1,54 @@
+use intel8080_core::devices::mits_88sio::{Mits88Sio, Mits88TwoSio, SioStatus};
+use intel8080_core::io::IoDevice;
+
+#[test]
+fn sio_status_bits_are_active_low() {
+    let mut sio = Mits88Sio::new();
+    // Transmitter ready (bit 7 low), nothing received (bit 0 high)
+    assert_eq!(sio.input(0, 0), 0x01);
+    sio.send_bytes(b"AB");
+    assert_eq!(sio.input(0, 0), 0x00);
+    assert_eq!(sio.input(1, 0), b'A');
+    assert_eq!(sio.input(0, 0), 0x00);
+    assert_eq!(sio.input(1, 0), b'B');
+    assert_eq!(sio.input(0, 0), 0x01);
+    assert_eq!(sio.pending_input(), 0);
+
+    let mut sio = Mits88Sio::with_status(SioStatus { rx_ready: 0x02, tx_ready: 0x01, active_low: false });
+    assert_eq!(sio.input(0, 0), 0x01);
+    sio.send(b'x');
+    assert_eq!(sio.input(0, 0), 0x03);
+}
+
+#[test]
+fn sio_transmits_to_the_host_and_interrupts_on_receive() {
+    let mut sio = Mits88Sio::new();
+    sio.output(1, b'h', 0);
+    sio.output(1, b'i', 0);
+    assert_eq!(sio.take_output(), b"hi");
+
+    sio.output(0, 0x01, 0);
+    assert!(!sio.irq());
+    sio.send(b'x');
+    assert!(sio.irq());
+    sio.input(1, 0);
+    assert!(!sio.irq());
+}
+
+#[test]
+fn two_sio_channels_are_separate_acias() {
+    let mut sio = Mits88TwoSio::new();
+    // Master reset then 8N1 /16 on channel 1 only
+    sio.output(2, 0x03, 0);
+    sio.output(2, 0x15, 0);
+    sio.channel(1).send(b'x');
+    assert_eq!(sio.input(2, 0) & 0x03, 0x03);
+    assert_eq!(sio.input(3, 0), b'x');
+    sio.output(3, b'y', 0);
+    assert_eq!(sio.channel(1).receive(), Some(b'y'));
+
+    // Channel 0 is still held in reset
+    assert_eq!(sio.input(0, 0), 0);
+    sio.output(1, b'z', 0);
+    assert_eq!(sio.channel(0).receive(), None);
+}
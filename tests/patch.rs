@@ -0,0 +1,153 @@
+use intel8080_core::patch::{apply, detect, LivePatch, PatchError, PatchFormat};
+use intel8080_core::romset::crc32;
+use intel8080_core::CPU;
+
+// A BPS patch with `body` between the header and a valid footer
+fn bps(body:&[u8], source:&[u8]) -> Vec<u8> {
+    bps_for(body, crc32(source), 0)
+}
+
+fn bps_for(body:&[u8], source_crc:u32, target_crc:u32) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    patch.extend_from_slice(body);
+    patch.extend_from_slice(&source_crc.to_le_bytes());
+    patch.extend_from_slice(&target_crc.to_le_bytes());
+    let checksum = crc32(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+    patch
+}
+
+#[test]
+fn oversized_bps_numbers_are_rejected() {
+    assert_eq!(apply(&bps(&[0x7F; 12], &[]), &[]), Err(PatchError::OutOfRange));
+}
+
+#[test]
+fn huge_bps_sizes_are_rejected() {
+    // Source size 0, a target size of about 2^56 and no metadata
+    let mut body = vec![0x80];
+    body.extend_from_slice(&[0x7E; 8]);
+    body.extend_from_slice(&[0x80, 0x80]);
+    assert_eq!(apply(&bps(&body, &[]), &[]), Err(PatchError::Truncated));
+}
+
+// BPS variable length number
+fn number(body:&mut Vec<u8>, mut value:usize) {
+    loop {
+        let digit = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            body.push(0x80 | digit);
+            return;
+        }
+        body.push(digit);
+        value -= 1;
+    }
+}
+
+const SOURCE:&[u8] = b"HELLO WORLD";
+const TARGET:&[u8] = b"HELLO, WORLD WORLD!!!!!";
+
+// Builds TARGET from SOURCE with every kind of command
+fn hello_body() -> Vec<u8> {
+    let mut body = Vec::new();
+    number(&mut body, SOURCE.len());
+    number(&mut body, TARGET.len());
+    number(&mut body, 3);
+    body.extend_from_slice(b"m=1");
+    // SourceRead "HELLO"
+    number(&mut body, 4 << 2);
+    // TargetRead ", "
+    number(&mut body, 1 << 2 | 1);
+    body.extend_from_slice(b", ");
+    // SourceCopy "WORLD" from 6
+    number(&mut body, 4 << 2 | 2);
+    number(&mut body, 6 << 1);
+    // TargetCopy " WORLD" from 6
+    number(&mut body, 5 << 2 | 3);
+    number(&mut body, 6 << 1);
+    // TargetRead "!!"
+    number(&mut body, 1 << 2 | 1);
+    body.extend_from_slice(b"!!");
+    // TargetCopy of three bytes from 19, each reading the one before
+    number(&mut body, 2 << 2 | 3);
+    number(&mut body, (19 - 12) << 1);
+    body
+}
+
+#[test]
+fn bps_commands_build_the_target() {
+    let patch = bps_for(&hello_body(), crc32(SOURCE), crc32(TARGET));
+    assert_eq!(detect(&patch), Some(PatchFormat::Bps));
+    assert_eq!(apply(&patch, SOURCE).as_deref(), Ok(TARGET));
+}
+
+#[test]
+fn bps_checksums_are_checked() {
+    let patch = bps_for(&hello_body(), crc32(SOURCE), crc32(TARGET));
+    assert_eq!(apply(&patch, b"HELLO THERE"), Err(PatchError::SourceChecksum { expected: crc32(SOURCE), actual: crc32(b"HELLO THERE") }));
+    assert!(matches!(apply(&patch, b"HELLO"), Err(PatchError::SourceSize { expected: 11, actual: 5 })));
+
+    let patch = bps_for(&hello_body(), crc32(SOURCE), 0x1234_5678);
+    assert_eq!(apply(&patch, SOURCE), Err(PatchError::TargetChecksum { expected: 0x1234_5678, actual: crc32(TARGET) }));
+
+    let mut patch = bps_for(&hello_body(), crc32(SOURCE), crc32(TARGET));
+    patch[8] ^= 1;
+    assert!(matches!(apply(&patch, SOURCE), Err(PatchError::PatchChecksum { .. })));
+}
+
+// "PATCH", records, then "EOF" with an optional truncation length
+fn ips(records:&[u8], truncate:Option<usize>) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(records);
+    patch.extend_from_slice(b"EOF");
+    if let Some(length) = truncate {
+        patch.extend_from_slice(&length.to_be_bytes()[5..]);
+    }
+    patch
+}
+
+#[test]
+fn ips_records_write_and_fill() {
+    let records = [
+        // "ab" at 1
+        &[0x00, 0x00, 0x01, 0x00, 0x02, b'a', b'b'][..],
+        // Three 'z' at 6
+        &[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, b'z'],
+        // Past the end, growing the image
+        &[0x00, 0x00, 0x0D, 0x00, 0x01, b'!'],
+    ]
+    .concat();
+    let patch = ips(&records, None);
+    assert_eq!(detect(&patch), Some(PatchFormat::Ips));
+    assert_eq!(apply(&patch, SOURCE).unwrap(), b"HabLO zzzLD\0\0!");
+    assert_eq!(apply(&ips(&records, Some(4)), SOURCE).unwrap(), b"HabL");
+    assert_eq!(apply(&ips(&records[..5], None), SOURCE), Err(PatchError::Truncated));
+    assert_eq!(apply(b"NOTAPATCH", SOURCE), Err(PatchError::UnknownFormat));
+}
+
+#[test]
+fn live_patches_switch_on_and_off() {
+    let mut cpu = CPU::new();
+    cpu.load_from(SOURCE, 0x0100);
+    // "ab" at 1 and 'D' at 10, which is already there
+    let patch = ips(&[0x00, 0x00, 0x01, 0x00, 0x02, b'a', b'b', 0x00, 0x00, 0x0A, 0x00, 0x01, b'D'], None);
+    let mut live = LivePatch::new(&cpu, 0x0100, SOURCE.len(), &patch).unwrap();
+    assert_eq!(live.len(), 2);
+    assert_eq!(&cpu.ram[0x0100..0x010B], SOURCE);
+
+    live.apply(&mut cpu);
+    assert!(live.is_applied());
+    assert_eq!(&cpu.ram[0x0100..0x010B], b"HabLO WORLD");
+    live.revert(&mut cpu);
+    assert!(!live.is_applied());
+    assert_eq!(&cpu.ram[0x0100..0x010B], SOURCE);
+
+    live.toggle(&mut cpu);
+    assert_eq!(&cpu.ram[0x0100..0x010B], b"HabLO WORLD");
+    live.toggle(&mut cpu);
+    assert_eq!(&cpu.ram[0x0100..0x010B], SOURCE);
+
+    let growing = ips(&[0x00, 0x00, 0x10, 0x00, 0x01, b'!'], None);
+    assert!(matches!(LivePatch::new(&cpu, 0xFFF8, 8, &growing), Err(PatchError::TooLarge)));
+}
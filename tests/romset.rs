@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::PathBuf;
+
+use intel8080_core::romset::{crc32, hex_string, sha1, ChipCheck, ChipStatus, RomSet, RomSetError};
+use intel8080_core::CPU;
+
+fn directory(name:&str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("romset-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn manifest_line(name:&str, address:u16, data:&[u8]) -> String {
+    format!("{} {:04X}h {} {:08x} {}\n", name, address, data.len(), crc32(data), hex_string(&sha1(data)))
+}
+
+#[test]
+fn hashes_match_known_vectors() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(hex_string(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    assert_eq!(hex_string(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    // Two blocks once padded
+    assert_eq!(hex_string(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+}
+
+#[test]
+fn manifests_parse_and_report_the_bad_line() {
+    let set = RomSet::parse("test", "# comment\n\nrom.a 0000h 0800h 734f5ad8 # trailing\nrom.b 0x0800 2048 6BFACA4A a9993e364706816aba3e25717850c26c9cd0d89d\n").unwrap();
+    assert_eq!(set.name, "test");
+    assert_eq!(set.chips.len(), 2);
+    assert_eq!((set.chips[0].address, set.chips[0].size, set.chips[0].crc32, set.chips[0].sha1), (0x0000, 0x0800, 0x734F5AD8, None));
+    assert_eq!((set.chips[1].address, set.chips[1].crc32), (0x0800, 0x6BFACA4A));
+    assert_eq!(set.chips[1].sha1, Some(sha1(b"abc")));
+
+    let line = |manifest:&str| match RomSet::parse("test", manifest) {
+        Err(RomSetError::Manifest { line, message }) => (line, message),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(line("rom.a 0000h 0800h"), (1, "expected <file> <address> <size> <crc32> [<sha1>]".to_string()));
+    assert_eq!(line("# header\nrom.a zzzz 0800h 734f5ad8").0, 2);
+    assert_eq!(line("rom.a zzzz 0800h 734f5ad8").1, "bad address");
+    assert_eq!(line("rom.a 0000h 0 734f5ad8").1, "bad size");
+    assert_eq!(line("rom.a 0000h 0800h 734f5adx").1, "bad CRC32");
+    assert_eq!(line("rom.a 0000h 0800h 734f5ad8 a9993e").1, "bad SHA-1");
+}
+
+#[test]
+fn verify_reports_each_chip() {
+    let directory = directory("verify");
+    let good = [0x11u8; 16];
+    let crc = [0x22u8; 16];
+    let sha = [0x33u8; 16];
+    let manifest = manifest_line("good.bin", 0x0000, &good)
+        + &manifest_line("missing.bin", 0x0010, &good)
+        + &manifest_line("short.bin", 0x0020, &good)
+        + &manifest_line("crc.bin", 0x0030, &crc)
+        + &format!("sha.bin 0040h 16 {:08x} {}\n", crc32(&sha), hex_string(&sha1(b"abc")));
+    let set = RomSet::parse("test", &manifest).unwrap();
+    fs::write(directory.join("good.bin"), good).unwrap();
+    fs::write(directory.join("short.bin"), &good[..8]).unwrap();
+    fs::write(directory.join("crc.bin"), [0x44u8; 16]).unwrap();
+    fs::write(directory.join("sha.bin"), sha).unwrap();
+
+    let statuses:Vec<ChipStatus> = set.verify(&directory).into_iter().map(|check| check.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            ChipStatus::Good,
+            ChipStatus::Missing,
+            ChipStatus::WrongSize { actual: 8 },
+            ChipStatus::BadCrc32 { actual: crc32(&[0x44u8; 16]) },
+            ChipStatus::BadSha1 { actual: sha1(&sha) },
+        ]
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn load_maps_good_sets_and_nothing_else() {
+    let directory = directory("load");
+    let low = [0xAAu8; 16];
+    let high = [0x55u8; 16];
+    let set = RomSet::parse("test", &(manifest_line("low.bin", 0x0100, &low) + &manifest_line("high.bin", 0x0110, &high))).unwrap();
+    fs::write(directory.join("low.bin"), low).unwrap();
+
+    let mut cpu = CPU::new();
+    match set.load(&directory, &mut cpu) {
+        Err(RomSetError::BadDumps(checks)) => assert_eq!(checks, [ChipCheck { name: "high.bin".to_string(), status: ChipStatus::Missing }]),
+        other => panic!("{:?}", other),
+    }
+    assert!(cpu.ram[0x0100..0x0120].iter().all(|byte| *byte == 0));
+
+    fs::write(directory.join("high.bin"), high).unwrap();
+    set.load(&directory, &mut cpu).unwrap();
+    assert_eq!(cpu.ram[0x0100..0x0110], low);
+    assert_eq!(cpu.ram[0x0110..0x0120], high);
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,40 @@
+use std::fs;
+
+use intel8080_core::semihost::{Semihost, READ_FILE, WRITE_FILE};
+use intel8080_core::CPU;
+
+const PORT:u8 = 0xFE;
+
+// Runs `function` with HL = `path`, DE = 0x0300 and BC = 4; returns A
+fn file_call(root:&std::path::Path, function:u8, path:&str) -> u8 {
+    let mut cpu = CPU::new();
+    cpu.semihost = Some(Semihost::new(PORT, root));
+    // LXI H,0200; LXI D,0300; LXI B,0004; MVI A,function; OUT PORT; HLT
+    let program = [0x21, 0x00, 0x02, 0x11, 0x00, 0x03, 0x01, 0x04, 0x00, 0x3E, function, 0xD3, PORT, 0x76];
+    cpu.load_from(&program, 0);
+    cpu.load_from(path.as_bytes(), 0x0200);
+    cpu.load_from(b"data", 0x0300);
+    for _ in 0..6 {
+        cpu.tick();
+    }
+    assert!(cpu.halted);
+    cpu.a
+}
+
+#[test]
+fn paths_stay_inside_the_root() {
+    let root = std::env::temp_dir().join(format!("semihost-{}", std::process::id()));
+    let inside = root.join("inside");
+    fs::create_dir_all(&inside).unwrap();
+
+    assert_eq!(file_call(&inside, WRITE_FILE, "file.txt"), 0x00);
+    assert_eq!(file_call(&inside, READ_FILE, "./file.txt"), 0x00);
+    assert_eq!(file_call(&inside, WRITE_FILE, "../escaped.txt"), 0xFF);
+    assert!(!root.join("escaped.txt").exists());
+    let absolute = root.join("absolute.txt");
+    assert_eq!(file_call(&inside, WRITE_FILE, absolute.to_str().unwrap()), 0xFF);
+    assert!(!absolute.exists());
+    assert_eq!(file_call(&inside, READ_FILE, "sub/../../inside/file.txt"), 0xFF);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,60 @@
+use intel8080_core::asm8080;
+use intel8080_core::subroutine::{CallOptions, CpuError, Registers};
+use intel8080_core::CPU;
+
+fn cpu_with(code:&[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_from(code, 0x0100);
+    cpu
+}
+
+fn registers() -> Registers {
+    Registers { b: 2, c: 3, sp: 0x2000, ..Registers::default() }
+}
+
+#[test]
+fn returns_at_the_sentinel() {
+    let mut cpu = cpu_with(&asm8080! {
+        mov a, b;
+        add c;
+        ret
+    });
+    let (result, cycles) = cpu.call_subroutine(0x0100, registers()).unwrap();
+    assert_eq!(result.a, 5);
+    assert_eq!(result.sp, 0x2000);
+    assert_eq!(cycles, 5 + 4 + 10);
+    assert_eq!(cpu.pc, CallOptions::default().return_address);
+}
+
+#[test]
+fn stops_at_the_instruction_limit() {
+    let mut cpu = cpu_with(&asm8080! {
+        org 0x100;
+    spin:
+        jmp spin
+    });
+    let options = CallOptions { max_instructions: 100, ..CallOptions::default() };
+    let result = cpu.call_subroutine_with(0x0100, registers(), options);
+    assert_eq!(result, Err(CpuError::InstructionLimit { pc: 0x0100, instructions: 100 }));
+}
+
+#[test]
+fn reports_a_stack_imbalance() {
+    // Returns to the sentinel through the pushed BC, one word short
+    let mut cpu = cpu_with(&asm8080! {
+        push b;
+        ret
+    });
+    let registers = Registers { b: 0xFF, c: 0xFF, ..registers() };
+    let result = cpu.call_subroutine(0x0100, registers);
+    assert_eq!(result, Err(CpuError::StackImbalance { expected: 0x2000, actual: 0x1FFE }));
+}
+
+#[test]
+fn reports_a_halt() {
+    let mut cpu = cpu_with(&asm8080! {
+        di;
+        hlt
+    });
+    assert_eq!(cpu.call_subroutine(0x0100, registers()), Err(CpuError::Halted { pc: 0x0101 }));
+}
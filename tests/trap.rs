@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use intel8080_core::interrupt::{rst, InterruptSource};
+use intel8080_core::trap::TrapAction;
+use intel8080_core::CPU;
+
+// INT held active, as a level triggered RST 7
+struct Level;
+
+impl InterruptSource for Level {
+    fn pending(&mut self, _cycles:u32) -> bool {
+        true
+    }
+
+    fn acknowledge(&mut self, _cycles:u32) -> Vec<u8> {
+        vec![rst(7)]
+    }
+}
+
+#[test]
+fn stopped_cpu_ignores_interrupts() {
+    let mut cpu = CPU::new();
+    cpu.pc = 0x0100;
+    cpu.sp = 0x2000;
+    cpu.set_trap(0x0100, |_| TrapAction::Stop);
+    cpu.tick();
+    assert!(cpu.stopped);
+
+    cpu.int_enabled = true;
+    cpu.attach_interrupt_source(Rc::new(RefCell::new(Level)));
+    cpu.tick();
+    assert!(cpu.stopped);
+    assert_eq!(cpu.pc, 0x0100);
+    assert_eq!(cpu.sp, 0x2000);
+    assert!(cpu.int_enabled);
+}
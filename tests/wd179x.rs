@@ -0,0 +1,65 @@
+use intel8080_core::devices::wd179x::{STATUS_DRQ, STATUS_LOST_DATA, STATUS_NOT_FOUND};
+use intel8080_core::devices::{Fdc179x, FdcModel};
+use intel8080_core::disk::{DiskGeometry, DiskImage};
+use intel8080_core::io::IoDevice;
+
+const CPU_HZ:u32 = 2_000_000;
+// 32 us per byte at 2 MHz
+const BYTE:u32 = 64;
+
+// Double sided disk with sector 1 of track 0 holding 0, 1, 2... on side 0
+// and 0x11 on side 1
+fn controller(model:FdcModel) -> Fdc179x {
+    let mut disk = DiskImage::blank(DiskGeometry::MINI_DSDD);
+    let pattern:Vec<u8> = (0..512).map(|index| index as u8).collect();
+    disk.write_sector(0, 0, 1, &pattern);
+    disk.write_sector(0, 1, 1, &[0x11; 512]);
+    let mut fdc = Fdc179x::new(model, CPU_HZ);
+    fdc.insert(0, disk);
+    fdc
+}
+
+#[test]
+fn side_compare_checks_the_id_field() {
+    // C = 1, S = 1 on side 1
+    let mut fdc = controller(FdcModel::Fd1793);
+    fdc.output(4, 0x04, 0);
+    fdc.output(0, 0x8A, 0);
+    assert_ne!(fdc.input(0, 10 * BYTE) & STATUS_DRQ, 0);
+    assert_eq!(fdc.input(3, 10 * BYTE), 0x11);
+    assert_eq!(fdc.side, 1);
+
+    // C = 1, S = 1 on side 0: the command doesn't switch sides
+    let mut fdc = controller(FdcModel::Fd1793);
+    fdc.output(0, 0x8A, 0);
+    assert_ne!(fdc.input(0, 10 * BYTE) & STATUS_NOT_FOUND, 0);
+    assert_eq!(fdc.side, 0);
+
+    // No compare, the latch decides
+    let mut fdc = controller(FdcModel::Fd1793);
+    fdc.output(4, 0x04, 0);
+    fdc.output(0, 0x88, 0);
+    assert_eq!(fdc.input(3, 10 * BYTE), 0x11);
+}
+
+#[test]
+fn fd1791_inverts_only_controller_registers() {
+    let mut fdc = controller(FdcModel::Fd1791);
+    fdc.output(4, 0x05, 0);
+    assert_eq!((fdc.selected, fdc.side), (1, 1));
+    assert_eq!(fdc.input(4, 0), 0x05);
+    fdc.output(1, !5, 0);
+    assert_eq!(fdc.track, 5);
+    assert_eq!(fdc.input(1, 0), !5);
+}
+
+#[test]
+fn missed_bytes_set_lost_data() {
+    let mut fdc = controller(FdcModel::Fd1793);
+    fdc.output(0, 0x80, 0);
+    // The first byte arrives after 8 byte times; three more go by unread
+    let status = fdc.input(0, 11 * BYTE);
+    assert_ne!(status & STATUS_LOST_DATA, 0);
+    assert_ne!(status & STATUS_DRQ, 0);
+    assert_eq!(fdc.input(3, 11 * BYTE), 3);
+}